@@ -0,0 +1,4 @@
+-- Local counterpart of the Convex `by_user_habit_and_completed_at` index.
+-- Completion day-range queries and "undo latest for day" scan it in completedAt order.
+CREATE INDEX IF NOT EXISTS idx_completions_habit_completed_at
+  ON completions (habitId, completedAt);
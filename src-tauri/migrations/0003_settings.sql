@@ -0,0 +1,7 @@
+-- Native-only key/value settings. Values are JSON documents owned by the
+-- module that defines the key (e.g. `timers`).
+CREATE TABLE IF NOT EXISTS settings (
+  key TEXT PRIMARY KEY,
+  value TEXT NOT NULL,
+  updatedAt INTEGER NOT NULL
+);
@@ -0,0 +1,17 @@
+-- Native-only per-habit reminders fired by the Rust scheduler.
+-- `timeOfDay` is minutes after local midnight; `weekdays` is a bitmask with
+-- bit 0 = Sunday ... bit 6 = Saturday, matching JavaScript's `Date.getDay()`.
+CREATE TABLE IF NOT EXISTS reminders (
+  id TEXT PRIMARY KEY,
+  habitId TEXT NOT NULL,
+  timeOfDay INTEGER NOT NULL,
+  weekdays INTEGER DEFAULT 127 NOT NULL,
+  isEnabled INTEGER DEFAULT 1 NOT NULL,
+  snoozedUntil INTEGER,
+  lastFiredAt INTEGER,
+  createdAt INTEGER NOT NULL,
+  updatedAt INTEGER NOT NULL,
+  FOREIGN KEY (habitId) REFERENCES habits(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_reminders_habit ON reminders (habitId);
@@ -0,0 +1,3 @@
+-- How often a habit is meant to be done, as JSON (see `recurrence.rs`).
+-- NULL means daily, the only schedule earlier versions knew.
+ALTER TABLE habits ADD COLUMN recurrence TEXT;
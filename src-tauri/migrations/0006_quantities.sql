@@ -0,0 +1,7 @@
+-- Quantitative habits: how much a completion counted for (e.g. 2 glasses,
+-- 5.5 km) and the daily amount a habit aims for. Existing completions are
+-- backfilled as a quantity of 1 by the column default.
+ALTER TABLE completions ADD COLUMN quantity REAL DEFAULT 1 NOT NULL;
+ALTER TABLE completions ADD COLUMN unit TEXT;
+ALTER TABLE habits ADD COLUMN targetQuantity REAL;
+ALTER TABLE habits ADD COLUMN unit TEXT;
@@ -0,0 +1,3 @@
+-- Length of the timed session a completion was logged from, so exports can
+-- show when the work actually happened. NULL for plain check-offs.
+ALTER TABLE completions ADD COLUMN durationSeconds INTEGER;
@@ -0,0 +1,43 @@
+-- Rows deleted here after they reached the server. Sync pushes these
+-- deletions before pulling so the server copy does not bring the rows back.
+-- Triggers catch every path, including habits removed by a calendar's
+-- cascade; rows that never had an owner were never pushed. Rows are named by
+-- `localUuid`, the identity the server knows them by, since imported
+-- calendars and habits keep one that differs from their `id`.
+CREATE TABLE IF NOT EXISTS pendingDeletions (
+  tableName TEXT NOT NULL,
+  localUuid TEXT NOT NULL,
+  userId TEXT NOT NULL,
+  deletedAt INTEGER NOT NULL,
+  -- Completions only: the server deletes those by habit and time
+  habitLocalUuid TEXT,
+  completedAt INTEGER,
+  PRIMARY KEY (tableName, localUuid)
+);
+
+CREATE TRIGGER IF NOT EXISTS calendars_pending_deletion
+AFTER DELETE ON calendars WHEN OLD.userId IS NOT NULL
+BEGIN
+  INSERT OR REPLACE INTO pendingDeletions (tableName, localUuid, userId, deletedAt)
+  VALUES ('calendars', OLD.localUuid, OLD.userId, CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER));
+END;
+
+CREATE TRIGGER IF NOT EXISTS habits_pending_deletion
+AFTER DELETE ON habits WHEN OLD.userId IS NOT NULL
+BEGIN
+  INSERT OR REPLACE INTO pendingDeletions (tableName, localUuid, userId, deletedAt)
+  VALUES ('habits', OLD.localUuid, OLD.userId, CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER));
+END;
+
+-- A completion's `localUuid` is its `id`. Completions cascaded from their
+-- habit find no habit here and are not recorded: the server deletes them
+-- along with the habit.
+CREATE TRIGGER IF NOT EXISTS completions_pending_deletion
+AFTER DELETE ON completions WHEN OLD.userId IS NOT NULL
+BEGIN
+  INSERT OR REPLACE INTO pendingDeletions
+    (tableName, localUuid, userId, deletedAt, habitLocalUuid, completedAt)
+  SELECT 'completions', OLD.id, OLD.userId, CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER),
+         localUuid, OLD.completedAt
+  FROM habits WHERE id = OLD.habitId;
+END;
@@ -0,0 +1,458 @@
+// Scheduled snapshots of the native database.
+//
+// A background thread copies the live database into `<app data>/backups`
+// with SQLite's online backup API, which reads a consistent image even while
+// the WAL holds uncommitted pages. Snapshots of an encrypted database are
+// encrypted with the same key. Old snapshots are rotated
+// grandfather-style: the newest snapshot of each of the last `keepDaily` days
+// and of each of the last `keepWeekly` ISO weeks survive. Restoring first
+// snapshots the current state, so a restore can itself be undone.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use rusqlite::backup::Backup;
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+use crate::days;
+use crate::encryption::{self, DatabaseKey};
+use crate::error::{Error, Result};
+use crate::migrations;
+use crate::settings;
+use crate::storage::{self, now_millis, Database};
+
+pub const RESTORED_EVENT: &str = "backup://restored";
+
+const BACKUPS_DIR: &str = "backups";
+const FILE_PREFIX: &str = "habistat-";
+const FILE_EXTENSION: &str = "db";
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%3fZ";
+const POLL_INTERVAL: Duration = Duration::from_secs(15 * 60);
+const SETTINGS_KEY: &str = "autoBackup";
+// Callers hold the connection lock, so there is no point yielding between steps
+const PAGES_PER_STEP: i32 = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AutoBackupSettings {
+    pub enabled: bool,
+    /// Minimum time between scheduled snapshots.
+    pub interval_hours: u32,
+    /// Days for which the newest snapshot is kept.
+    pub keep_daily: u32,
+    /// ISO weeks for which the newest snapshot is kept.
+    pub keep_weekly: u32,
+}
+
+impl Default for AutoBackupSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_hours: 24,
+            keep_daily: 7,
+            keep_weekly: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupInfo {
+    pub file_name: String,
+    pub path: PathBuf,
+    pub created_at: i64,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreResult {
+    pub restored: BackupInfo,
+    /// Snapshot of the data as it was just before the restore.
+    pub previous: BackupInfo,
+}
+
+/// Copies the database behind `conn`, encrypted with `key` if any, into
+/// `dir` as a snapshot taken at `now`.
+pub fn snapshot(
+    conn: &Connection,
+    key: Option<&DatabaseKey>,
+    dir: &Path,
+    now: i64,
+) -> Result<BackupInfo> {
+    fs::create_dir_all(dir)?;
+    let taken_at = DateTime::<Utc>::from_timestamp_millis(now)
+        .ok_or_else(|| Error::InvalidInput(format!("timestamp {now} is out of range")))?;
+    let file_name = format!(
+        "{FILE_PREFIX}{}.{FILE_EXTENSION}",
+        taken_at.format(TIMESTAMP_FORMAT)
+    );
+    let path = dir.join(&file_name);
+    // Written under a temporary name so a crash never leaves a half snapshot
+    storage::write_atomically(&path, |partial| {
+        // A leftover partial file would be opened as a database, not replaced
+        let _ = fs::remove_file(partial);
+        let mut target = Connection::open(partial)?;
+        // SQLCipher only copies pages between databases sharing a key
+        encryption::apply_key(&target, key)?;
+        Backup::new(conn, &mut target)?.run_to_completion(PAGES_PER_STEP, Duration::ZERO, None)?;
+        Ok(())
+    })?;
+    info(&path)?.ok_or_else(|| Error::not_found("backup", file_name))
+}
+
+fn info(path: &Path) -> Result<Option<BackupInfo>> {
+    let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+        return Ok(None);
+    };
+    let created_at = file_name
+        .strip_prefix(FILE_PREFIX)
+        .and_then(|rest| rest.strip_suffix(&format!(".{FILE_EXTENSION}")))
+        .and_then(|stamp| NaiveDateTime::parse_from_str(stamp, TIMESTAMP_FORMAT).ok())
+        .map(|at| at.and_utc().timestamp_millis());
+    let Some(created_at) = created_at else {
+        return Ok(None);
+    };
+    Ok(Some(BackupInfo {
+        file_name: file_name.to_string(),
+        path: path.to_path_buf(),
+        created_at,
+        size_bytes: fs::metadata(path)?.len(),
+    }))
+}
+
+/// Snapshots in `dir`, newest first. Other files are ignored.
+pub fn list(dir: &Path) -> Result<Vec<BackupInfo>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        if let Some(backup) = info(&entry?.path())? {
+            backups.push(backup);
+        }
+    }
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.created_at));
+    Ok(backups)
+}
+
+/// Deletes snapshots outside the daily and weekly generations to keep.
+/// Returns the deleted snapshots.
+pub fn rotate(dir: &Path, keep_daily: u32, keep_weekly: u32) -> Result<Vec<BackupInfo>> {
+    let backups = list(dir)?;
+    let mut keep = HashSet::new();
+    let mut seen_days = HashSet::new();
+    let mut seen_weeks = HashSet::new();
+    // Newest first, so the first snapshot of each day/week is the one kept
+    for backup in &backups {
+        let date = days::local_date_of(backup.created_at);
+        let week = date.iso_week();
+        if seen_days.len() < keep_daily as usize && seen_days.insert(date) {
+            keep.insert(backup.file_name.clone());
+        }
+        if seen_weeks.len() < keep_weekly as usize && seen_weeks.insert(week) {
+            keep.insert(backup.file_name.clone());
+        }
+    }
+    // Never delete the only copy
+    if let Some(newest) = backups.first() {
+        keep.insert(newest.file_name.clone());
+    }
+
+    let mut deleted = Vec::new();
+    for backup in backups {
+        if !keep.contains(&backup.file_name) {
+            fs::remove_file(&backup.path)?;
+            deleted.push(backup);
+        }
+    }
+    Ok(deleted)
+}
+
+/// Takes a snapshot if scheduled backups are on and the newest one is older
+/// than the configured interval, then rotates.
+pub fn run_due(
+    conn: &Connection,
+    key: Option<&DatabaseKey>,
+    dir: &Path,
+    backup_settings: &AutoBackupSettings,
+    now: i64,
+) -> Result<Option<BackupInfo>> {
+    if !backup_settings.enabled {
+        return Ok(None);
+    }
+    let interval = i64::from(backup_settings.interval_hours.max(1)) * 60 * 60 * 1000;
+    if let Some(newest) = list(dir)?.first() {
+        if now - newest.created_at < interval {
+            return Ok(None);
+        }
+    }
+    let backup = snapshot(conn, key, dir, now)?;
+    rotate(dir, backup_settings.keep_daily, backup_settings.keep_weekly)?;
+    Ok(Some(backup))
+}
+
+/// Replaces the contents of `conn` with the snapshot `file_name` from `dir`,
+/// after snapshotting the current data. Older snapshots are migrated forward;
+/// ones written by a newer app version are refused. Snapshots share the
+/// database's `key`.
+pub fn restore(
+    conn: &mut Connection,
+    key: Option<&DatabaseKey>,
+    dir: &Path,
+    file_name: &str,
+) -> Result<RestoreResult> {
+    // Only names from the listing are accepted, never arbitrary paths
+    let restored = list(dir)?
+        .into_iter()
+        .find(|backup| backup.file_name == file_name)
+        .ok_or_else(|| Error::not_found("backup", file_name))?;
+
+    let source = Connection::open_with_flags(&restored.path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    encryption::apply_key(&source, key).map_err(|e| match e {
+        Error::WrongPassphrase => Error::InvalidInput(format!(
+            "backup `{file_name}` is encrypted with a different key"
+        )),
+        e => e,
+    })?;
+    let integrity: String = source.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+    if integrity != "ok" {
+        return Err(Error::InvalidInput(format!(
+            "backup `{file_name}` is damaged: {integrity}"
+        )));
+    }
+    migrations::check_known(&migrations::applied(&source)?)?;
+
+    let previous = snapshot(conn, key, dir, now_millis())?;
+    Backup::new(&source, conn)?.run_to_completion(PAGES_PER_STEP, Duration::ZERO, None)?;
+    drop(source);
+    migrations::run(conn)?;
+    Ok(RestoreResult { restored, previous })
+}
+
+/// Folder scheduled snapshots are written to.
+pub fn backups_dir<R: Runtime>(app: &AppHandle<R>) -> tauri::Result<PathBuf> {
+    Ok(app.path().app_data_dir()?.join(BACKUPS_DIR))
+}
+
+/// Starts the thread that takes scheduled snapshots, beginning right away.
+pub fn start_service<R: Runtime>(app: AppHandle<R>) -> std::io::Result<()> {
+    std::thread::Builder::new()
+        .name("habistat-backups".into())
+        .spawn(move || loop {
+            let result = dir(&app).and_then(|dir| {
+                let db = app.state::<Database>();
+                let conn = db.conn();
+                let backup_settings = settings::get(&conn, SETTINGS_KEY)?;
+                run_due(
+                    &conn,
+                    db.key().as_ref(),
+                    &dir,
+                    &backup_settings,
+                    now_millis(),
+                )
+            });
+            if let Err(e) = result {
+                eprintln!("Scheduled backup failed: {e}");
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        })?;
+    Ok(())
+}
+
+// `backups_dir` with the crate error type
+pub(crate) fn dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf> {
+    backups_dir(app).map_err(|e| Error::Io(std::io::Error::other(e.to_string())))
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn list_backups<R: Runtime>(app: AppHandle<R>) -> Result<Vec<BackupInfo>> {
+    list(&dir(&app)?)
+}
+
+/// Takes a snapshot now, outside the schedule.
+#[tauri::command]
+pub fn create_backup<R: Runtime>(app: AppHandle<R>, db: State<'_, Database>) -> Result<BackupInfo> {
+    snapshot(&db.conn(), db.key().as_ref(), &dir(&app)?, now_millis())
+}
+
+/// Restores a snapshot by file name and tells the webview to reload its data.
+#[tauri::command]
+pub fn restore_backup<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    file_name: String,
+) -> Result<RestoreResult> {
+    let result = restore(&mut db.conn(), db.key().as_ref(), &dir(&app)?, &file_name)?;
+    db.mark_changed();
+    let _ = app.emit(RESTORED_EVENT, &result.restored);
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn get_auto_backup_settings(db: State<'_, Database>) -> Result<AutoBackupSettings> {
+    settings::get(&db.conn(), SETTINGS_KEY)
+}
+
+#[tauri::command]
+pub fn set_auto_backup_settings(
+    db: State<'_, Database>,
+    auto_backup_settings: AutoBackupSettings,
+) -> Result<()> {
+    settings::set(&db.conn(), SETTINGS_KEY, &auto_backup_settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendars::{self, NewCalendar};
+    use crate::storage::{self, new_id};
+    use chrono::NaiveDate;
+
+    const DAY: i64 = 24 * 60 * 60 * 1000;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("habistat-snapshots-{}", new_id()))
+    }
+
+    fn names(conn: &Connection) -> Vec<String> {
+        calendars::list(conn)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect()
+    }
+
+    #[test]
+    fn restores_a_snapshot_of_a_wal_database() {
+        let dir = temp_dir();
+        let db = storage::Database::open(dir.join("habistat.db")).unwrap();
+        let mut conn = db.conn();
+        let add = |conn: &Connection, name: &str| {
+            calendars::create(
+                conn,
+                NewCalendar {
+                    name: name.into(),
+                    color_theme: "blue".into(),
+                    user_id: None,
+                    position: None,
+                },
+            )
+            .unwrap()
+        };
+        add(&conn, "Health");
+
+        let backups = dir.join(BACKUPS_DIR);
+        let taken = run_due(
+            &conn,
+            None,
+            &backups,
+            &AutoBackupSettings::default(),
+            now_millis(),
+        )
+        .unwrap()
+        .unwrap();
+        // Not due again within the interval
+        assert!(run_due(
+            &conn,
+            None,
+            &backups,
+            &AutoBackupSettings::default(),
+            now_millis()
+        )
+        .unwrap()
+        .is_none());
+
+        add(&conn, "Scratch");
+        let result = restore(&mut conn, None, &backups, &taken.file_name).unwrap();
+        assert_eq!(names(&conn), vec!["Health"]);
+        assert_eq!(list(&backups).unwrap().len(), 2);
+
+        restore(&mut conn, None, &backups, &result.previous.file_name).unwrap();
+        assert_eq!(names(&conn), vec!["Health", "Scratch"]);
+        assert!(matches!(
+            restore(&mut conn, None, &backups, "../habistat.db"),
+            Err(Error::NotFound { .. })
+        ));
+        drop(conn);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn failed_snapshot_leaves_no_partial_file() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let dir = temp_dir();
+        let now = now_millis();
+        let taken = snapshot(&db.conn(), None, &dir, now).unwrap();
+        // A directory in the way makes the final rename fail
+        fs::remove_file(&taken.path).unwrap();
+        fs::create_dir(&taken.path).unwrap();
+        assert!(snapshot(&db.conn(), None, &dir, now).is_err());
+        let left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(left, vec![taken.file_name.as_str()]);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rotation_keeps_daily_and_weekly_generations() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let date = |s: &str| s.parse::<NaiveDate>().unwrap();
+        let last = date("2024-03-20");
+        // Two snapshots a day at local 11:00 and noon, for 30 days through
+        // Wednesday 2024-03-20
+        let fill = || {
+            let dir = temp_dir();
+            for day in last.iter_days().rev().take(30) {
+                let noon = days::local_day_bounds(day).0 + DAY / 2;
+                for hour in [0, 1] {
+                    snapshot(&db.conn(), None, &dir, noon - hour * 60 * 60 * 1000).unwrap();
+                }
+            }
+            dir
+        };
+        let kept_dates = |dir: &Path| -> Vec<NaiveDate> {
+            list(dir)
+                .unwrap()
+                .iter()
+                .map(|backup| days::local_date_of(backup.created_at))
+                .collect()
+        };
+
+        // The 3 latest days are all in the week of Mar 18; the previous
+        // week adds its Sunday
+        let dir = fill();
+        rotate(&dir, 3, 2).unwrap();
+        assert_eq!(
+            kept_dates(&dir),
+            vec![
+                date("2024-03-20"),
+                date("2024-03-19"),
+                date("2024-03-18"),
+                date("2024-03-17"),
+            ]
+        );
+        let newest = &list(&dir).unwrap()[0];
+        assert_eq!(newest.created_at, days::local_day_bounds(last).0 + DAY / 2);
+
+        let _ = fs::remove_dir_all(dir);
+
+        // One daily, three weekly: the last day of each of the three newest weeks
+        let dir = fill();
+        rotate(&dir, 1, 3).unwrap();
+        assert_eq!(
+            kept_dates(&dir),
+            vec![date("2024-03-20"), date("2024-03-17"), date("2024-03-10")]
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+}
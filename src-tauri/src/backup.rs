@@ -0,0 +1,473 @@
+// Portable JSON backups of the native database.
+//
+// A backup holds every user-data table as rows of `column: value`, tagged with
+// the migrations the data was written under and a SHA-256 checksum of the
+// `tables` object. Restoring either replaces all local data or merges by
+// primary key, keeping whichever side of a row was updated last.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+use sha2::{Digest, Sha256};
+use tauri::State;
+
+use crate::error::{Error, Result};
+use crate::migrations;
+use crate::storage::{self, now_millis, Database};
+
+pub const FORMAT: &str = "habistat-backup";
+pub const FORMAT_VERSION: u32 = 1;
+
+pub type BackupRow = Map<String, Value>;
+
+struct Table {
+    name: &'static str,
+    key: &'static str,
+    /// Column compared when merging; rows without one never overwrite.
+    version: Option<&'static str>,
+}
+
+// Parents before children so foreign keys hold while restoring
+const TABLES: &[Table] = &[
+    Table {
+        name: "calendars",
+        key: "id",
+        version: Some("updatedAt"),
+    },
+    Table {
+        name: "habits",
+        key: "id",
+        version: Some("updatedAt"),
+    },
+    Table {
+        name: "completions",
+        key: "id",
+        version: Some("clientUpdatedAt"),
+    },
+    Table {
+        name: "activeTimers",
+        key: "id",
+        version: Some("updatedAt"),
+    },
+    Table {
+        name: "reminders",
+        key: "id",
+        version: Some("updatedAt"),
+    },
+    Table {
+        name: "activityHistory",
+        key: "id",
+        version: None,
+    },
+    Table {
+        name: "userProfile",
+        key: "id",
+        version: Some("updatedAt"),
+    },
+    Table {
+        name: "settings",
+        key: "key",
+        version: Some("updatedAt"),
+    },
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Backup {
+    pub format: String,
+    pub format_version: u32,
+    /// Migrations applied to the exporting database.
+    pub schema: Vec<String>,
+    pub app_version: String,
+    pub exported_at: i64,
+    /// Lowercase hex SHA-256 of the serialized `tables` object.
+    pub checksum: String,
+    pub tables: BTreeMap<String, Vec<BackupRow>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportMode {
+    /// Wipe local data and load the backup as-is.
+    Replace,
+    /// Add missing rows and overwrite local rows the backup has newer versions of.
+    Merge,
+}
+
+/// Result of [`export_to`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSummary {
+    pub exported_at: i64,
+    pub checksum: String,
+    pub row_counts: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableImport {
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+/// Result of [`import`], per table.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    pub tables: BTreeMap<String, TableImport>,
+}
+
+fn checksum(tables: &BTreeMap<String, Vec<BackupRow>>) -> Result<String> {
+    let digest = Sha256::digest(serde_json::to_vec(tables)?);
+    Ok(digest.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+fn to_json(value: ValueRef<'_>) -> Result<Value> {
+    Ok(match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+        ValueRef::Text(text) => Value::String(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(_) => {
+            return Err(Error::InvalidInput(
+                "binary columns cannot be backed up".into(),
+            ))
+        }
+    })
+}
+
+fn to_sql(value: &Value) -> Result<SqlValue> {
+    Ok(match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(i64::from(*b)),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        Value::Array(_) | Value::Object(_) => {
+            return Err(Error::InvalidInput("backup values must be scalars".into()))
+        }
+    })
+}
+
+/// Column names of `table`.
+pub(crate) fn columns(conn: &Connection, table: &str) -> Result<HashSet<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let names = stmt.query_map([], |row| row.get(1))?;
+    Ok(names.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Reads every backed-up table into a [`Backup`].
+pub fn export(conn: &Connection) -> Result<Backup> {
+    let mut tables = BTreeMap::new();
+    for table in TABLES {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT * FROM {} ORDER BY {}",
+            table.name, table.key
+        ))?;
+        let names: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+        let mut rows = stmt.query([])?;
+        let mut out = Vec::new();
+        while let Some(row) = rows.next()? {
+            let mut object = Map::new();
+            for (index, name) in names.iter().enumerate() {
+                object.insert(name.clone(), to_json(row.get_ref(index)?)?);
+            }
+            out.push(object);
+        }
+        tables.insert(table.name.to_string(), out);
+    }
+
+    Ok(Backup {
+        format: FORMAT.into(),
+        format_version: FORMAT_VERSION,
+        schema: migrations::applied(conn)?,
+        app_version: env!("CARGO_PKG_VERSION").into(),
+        exported_at: now_millis(),
+        checksum: checksum(&tables)?,
+        tables,
+    })
+}
+
+/// Checks that `backup` is a Habistat backup this build can restore.
+pub fn validate(backup: &Backup) -> Result<()> {
+    if backup.format != FORMAT {
+        return Err(Error::InvalidInput("not a Habistat backup file".into()));
+    }
+    if backup.format_version > FORMAT_VERSION {
+        return Err(Error::SchemaTooNew(format!(
+            "backup format version {}",
+            backup.format_version
+        )));
+    }
+    migrations::check_known(&backup.schema)?;
+    if checksum(&backup.tables)? != backup.checksum {
+        return Err(Error::InvalidInput(
+            "backup checksum mismatch; the file is damaged or was edited".into(),
+        ));
+    }
+    if let Some(unknown) = backup
+        .tables
+        .keys()
+        .find(|name| !TABLES.iter().any(|t| t.name == name.as_str()))
+    {
+        return Err(Error::InvalidInput(format!(
+            "backup contains unknown table `{unknown}`"
+        )));
+    }
+    Ok(())
+}
+
+/// Restores `backup` in a single transaction.
+pub fn import(conn: &mut Connection, backup: &Backup, mode: ImportMode) -> Result<ImportSummary> {
+    validate(backup)?;
+    let tx = conn.transaction()?;
+    if mode == ImportMode::Replace {
+        for table in TABLES.iter().rev() {
+            tx.execute(&format!("DELETE FROM {}", table.name), [])?;
+        }
+    }
+
+    let mut summary = ImportSummary::default();
+    for table in TABLES {
+        let rows = match backup.tables.get(table.name) {
+            Some(rows) => rows,
+            None => continue,
+        };
+        let known = columns(&tx, table.name)?;
+        let mut result = TableImport::default();
+        for row in rows {
+            // Column names are spliced into SQL, so only accept real ones
+            if let Some(column) = row.keys().find(|column| !known.contains(*column)) {
+                return Err(Error::InvalidInput(format!(
+                    "backup column `{}.{column}` does not exist",
+                    table.name
+                )));
+            }
+            let key = row.get(table.key).ok_or_else(|| {
+                Error::InvalidInput(format!("{} row without `{}`", table.name, table.key))
+            })?;
+            let names: Vec<&String> = row.keys().collect();
+            let values = row.values().map(to_sql).collect::<Result<Vec<_>>>()?;
+
+            let existing: Option<Option<i64>> = tx
+                .query_row(
+                    &format!(
+                        "SELECT {} FROM {} WHERE {} = ?1",
+                        table.version.unwrap_or("NULL"),
+                        table.name,
+                        table.key
+                    ),
+                    [to_sql(key)?],
+                    |r| r.get(0),
+                )
+                .optional()?;
+
+            match existing {
+                None => {
+                    let placeholders = vec!["?"; names.len()].join(", ");
+                    let column_list = names.iter().map(|n| n.as_str()).collect::<Vec<_>>();
+                    // Rows clashing on another unique index (e.g. one activity row per day) are kept local
+                    let inserted = tx.execute(
+                        &format!(
+                            "INSERT OR IGNORE INTO {} ({}) VALUES ({placeholders})",
+                            table.name,
+                            column_list.join(", ")
+                        ),
+                        params_from_iter(values),
+                    )?;
+                    if inserted > 0 {
+                        result.inserted += 1;
+                    } else {
+                        result.skipped += 1;
+                    }
+                }
+                Some(local_version) => {
+                    let incoming = table
+                        .version
+                        .and_then(|column| row.get(column))
+                        .and_then(Value::as_i64);
+                    let newer = match (incoming, local_version) {
+                        (Some(theirs), Some(ours)) => theirs > ours,
+                        (Some(_), None) => true,
+                        _ => false,
+                    };
+                    if !newer {
+                        result.skipped += 1;
+                        continue;
+                    }
+                    let assignments = names
+                        .iter()
+                        .enumerate()
+                        .map(|(index, name)| format!("{name} = ?{}", index + 1))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let mut params = values;
+                    params.push(to_sql(key)?);
+                    tx.execute(
+                        &format!(
+                            "UPDATE {} SET {assignments} WHERE {} = ?{}",
+                            table.name,
+                            table.key,
+                            params.len()
+                        ),
+                        params_from_iter(params),
+                    )?;
+                    result.updated += 1;
+                }
+            }
+        }
+        summary.tables.insert(table.name.to_string(), result);
+    }
+    // Restored rows are no longer deleted; without this the next sync would
+    // delete them (and a habit's completions) on the server
+    tx.execute(
+        "DELETE FROM pendingDeletions WHERE
+             (tableName = 'calendars' AND localUuid IN (SELECT localUuid FROM calendars))
+             OR (tableName = 'habits' AND localUuid IN (SELECT localUuid FROM habits))
+             OR (tableName = 'completions' AND localUuid IN (SELECT id FROM completions))",
+        [],
+    )?;
+    tx.commit()?;
+    Ok(summary)
+}
+
+/// Exports the database to `path`, replacing the file atomically.
+pub fn export_to(conn: &Connection, path: &Path) -> Result<ExportSummary> {
+    let backup = export(conn)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_vec_pretty(&backup)?;
+    storage::write_atomically(path, |partial| Ok(fs::write(partial, json)?))?;
+    Ok(ExportSummary {
+        exported_at: backup.exported_at,
+        checksum: backup.checksum,
+        row_counts: backup
+            .tables
+            .iter()
+            .map(|(name, rows)| (name.clone(), rows.len()))
+            .collect(),
+    })
+}
+
+pub fn read(path: &Path) -> Result<Backup> {
+    let backup = serde_json::from_slice(&fs::read(path)?)?;
+    validate(&backup)?;
+    Ok(backup)
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn export_backup(db: State<'_, Database>, path: String) -> Result<ExportSummary> {
+    export_to(&db.conn(), Path::new(&path))
+}
+
+#[tauri::command]
+pub fn import_backup(
+    db: State<'_, Database>,
+    path: String,
+    mode: ImportMode,
+) -> Result<ImportSummary> {
+    let backup = read(Path::new(&path))?;
+    import(&mut db.conn(), &backup, mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendars::{self, CalendarPatch, NewCalendar};
+    use crate::storage;
+
+    fn calendar(conn: &Connection, name: &str) -> calendars::Calendar {
+        calendars::create(
+            conn,
+            NewCalendar {
+                name: name.into(),
+                color_theme: "blue".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trips_through_a_file() {
+        let source = storage::Database::open_in_memory().unwrap();
+        calendar(&source.conn(), "Health");
+        let path = std::env::temp_dir()
+            .join(format!("habistat-backup-{}", storage::new_id()))
+            .join("backup.json");
+        let exported = export_to(&source.conn(), &path).unwrap();
+        assert_eq!(exported.row_counts["calendars"], 1);
+
+        let target = storage::Database::open_in_memory().unwrap();
+        calendar(&target.conn(), "Scratch");
+        let backup = read(&path).unwrap();
+        let summary = import(&mut target.conn(), &backup, ImportMode::Replace).unwrap();
+        assert_eq!(summary.tables["calendars"].inserted, 1);
+        let names: Vec<String> = calendars::list(&target.conn())
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(names, vec!["Health"]);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn merge_keeps_the_newer_row() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let health = calendar(&db.conn(), "Health");
+        let backup = export(&db.conn()).unwrap();
+
+        // Renamed after the backup was taken, so the local row wins
+        let patch = CalendarPatch {
+            name: Some("Fitness".into()),
+            ..CalendarPatch::default()
+        };
+        calendars::update(&db.conn(), &health.id, patch).unwrap();
+
+        let summary = import(&mut db.conn(), &backup, ImportMode::Merge).unwrap();
+        assert_eq!(
+            summary.tables["calendars"],
+            TableImport {
+                inserted: 0,
+                updated: 0,
+                skipped: 1
+            }
+        );
+        assert_eq!(
+            calendars::get(&db.conn(), &health.id).unwrap().name,
+            "Fitness"
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_backups() {
+        let db = storage::Database::open_in_memory().unwrap();
+        calendar(&db.conn(), "Health");
+        let mut backup = export(&db.conn()).unwrap();
+        backup.tables.get_mut("calendars").unwrap()[0].insert("name".into(), Value::from("Edited"));
+        assert!(matches!(
+            import(&mut db.conn(), &backup, ImportMode::Merge),
+            Err(Error::InvalidInput(_))
+        ));
+
+        let mut backup = export(&db.conn()).unwrap();
+        backup.schema.push("9999_from_the_future".into());
+        assert!(matches!(
+            import(&mut db.conn(), &backup, ImportMode::Merge),
+            Err(Error::SchemaTooNew(_))
+        ));
+    }
+}
@@ -0,0 +1,362 @@
+// Calendar CRUD on the native database.
+// Calendars are the organizational containers habits live in; deleting one
+// cascades to its habits (and their completions/timers) via foreign keys.
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::error::{Error, Result};
+use crate::storage::{new_id, now_millis, Database};
+
+/// Allowed `colorTheme` values. Keep in sync with `ALLOWED_CALENDAR_COLORS`
+/// in `src/convex/constants.ts` (which backs `calendarColorValidator`).
+pub const ALLOWED_CALENDAR_COLORS: [&str; 17] = [
+    "lime", "green", "emerald", "teal", "cyan", "sky", "blue", "indigo", "violet", "purple",
+    "fuchsia", "pink", "rose", "red", "orange", "amber", "yellow",
+];
+
+const COLUMNS: &str =
+    "id, userId, localUuid, name, colorTheme, position, isEnabled, createdAt, updatedAt";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Calendar {
+    pub id: String,
+    pub user_id: Option<String>,
+    pub local_uuid: String,
+    pub name: String,
+    pub color_theme: String,
+    pub position: i64,
+    pub is_enabled: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl Calendar {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            local_uuid: row.get(2)?,
+            name: row.get(3)?,
+            color_theme: row.get(4)?,
+            position: row.get(5)?,
+            is_enabled: row.get(6)?,
+            created_at: row.get(7)?,
+            updated_at: row.get(8)?,
+        })
+    }
+}
+
+/// Input for [`create`]. `position` defaults to the end of the list.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewCalendar {
+    pub name: String,
+    pub color_theme: String,
+    pub user_id: Option<String>,
+    pub position: Option<i64>,
+}
+
+/// Fields [`update`] may change; `None` leaves the column untouched.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalendarPatch {
+    pub name: Option<String>,
+    pub color_theme: Option<String>,
+}
+
+fn validate_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::InvalidInput(
+            "calendar name must not be empty".into(),
+        ));
+    }
+    Ok(name.to_string())
+}
+
+fn validate_color(color: &str) -> Result<()> {
+    if ALLOWED_CALENDAR_COLORS.contains(&color) {
+        Ok(())
+    } else {
+        Err(Error::InvalidInput(format!(
+            "unsupported colorTheme `{color}` (expected one of: {})",
+            ALLOWED_CALENDAR_COLORS.join(", ")
+        )))
+    }
+}
+
+/// All calendars ordered by position.
+pub fn list(conn: &Connection) -> Result<Vec<Calendar>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {COLUMNS} FROM calendars ORDER BY position, createdAt"
+    ))?;
+    let rows = stmt.query_map([], Calendar::from_row)?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+pub fn get(conn: &Connection, id: &str) -> Result<Calendar> {
+    conn.query_row(
+        &format!("SELECT {COLUMNS} FROM calendars WHERE id = ?1"),
+        [id],
+        Calendar::from_row,
+    )
+    .optional()?
+    .ok_or_else(|| Error::not_found("calendar", id))
+}
+
+pub fn create(conn: &Connection, input: NewCalendar) -> Result<Calendar> {
+    let name = validate_name(&input.name)?;
+    validate_color(&input.color_theme)?;
+
+    let position = match input.position {
+        Some(position) => position,
+        None => conn.query_row(
+            "SELECT COALESCE(MAX(position), -1) + 1 FROM calendars",
+            [],
+            |row| row.get(0),
+        )?,
+    };
+
+    // Like the webview, the local id doubles as the sync correlation id
+    let id = new_id();
+    let now = now_millis();
+    conn.execute(
+        &format!("INSERT INTO calendars ({COLUMNS}) VALUES (?1, ?2, ?1, ?3, ?4, ?5, 1, ?6, ?6)"),
+        params![id, input.user_id, name, input.color_theme, position, now],
+    )?;
+    get(conn, &id)
+}
+
+pub fn update(conn: &Connection, id: &str, patch: CalendarPatch) -> Result<Calendar> {
+    let mut calendar = get(conn, id)?;
+    if let Some(name) = patch.name {
+        calendar.name = validate_name(&name)?;
+    }
+    if let Some(color) = patch.color_theme {
+        validate_color(&color)?;
+        calendar.color_theme = color;
+    }
+    calendar.updated_at = now_millis();
+
+    conn.execute(
+        "UPDATE calendars SET name = ?2, colorTheme = ?3, updatedAt = ?4 WHERE id = ?1",
+        params![id, calendar.name, calendar.color_theme, calendar.updated_at],
+    )?;
+    Ok(calendar)
+}
+
+/// Moves the given calendars to the front in the given order.
+///
+/// Calendars missing from `ordered_ids` keep their relative order after them.
+/// Only rows whose position actually changes get a new `updatedAt`, so a
+/// reorder does not win Last-Write-Wins conflicts for untouched calendars.
+pub fn reorder(conn: &mut Connection, ordered_ids: &[String]) -> Result<Vec<Calendar>> {
+    let tx = conn.transaction()?;
+    let mut remaining = list(&tx)?;
+
+    let mut ordered = Vec::with_capacity(remaining.len());
+    for id in ordered_ids {
+        let index = remaining
+            .iter()
+            .position(|c| &c.id == id)
+            .ok_or_else(|| Error::not_found("calendar", id))?;
+        ordered.push(remaining.remove(index));
+    }
+    ordered.append(&mut remaining);
+
+    let now = now_millis();
+    for (position, calendar) in ordered.iter_mut().enumerate() {
+        let position = position as i64;
+        if calendar.position != position {
+            calendar.position = position;
+            calendar.updated_at = now;
+            tx.execute(
+                "UPDATE calendars SET position = ?2, updatedAt = ?3 WHERE id = ?1",
+                params![calendar.id, position, now],
+            )?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(ordered)
+}
+
+pub fn set_enabled(conn: &Connection, id: &str, enabled: bool) -> Result<Calendar> {
+    let mut calendar = get(conn, id)?;
+    calendar.is_enabled = enabled;
+    calendar.updated_at = now_millis();
+    conn.execute(
+        "UPDATE calendars SET isEnabled = ?2, updatedAt = ?3 WHERE id = ?1",
+        params![id, enabled, calendar.updated_at],
+    )?;
+    Ok(calendar)
+}
+
+/// Deletes a calendar; its habits, completions and timers go with it (ON DELETE CASCADE).
+pub fn delete(conn: &Connection, id: &str) -> Result<()> {
+    let deleted = conn.execute("DELETE FROM calendars WHERE id = ?1", [id])?;
+    if deleted == 0 {
+        return Err(Error::not_found("calendar", id));
+    }
+    Ok(())
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn list_calendars(db: State<'_, Database>) -> Result<Vec<Calendar>> {
+    list(&db.conn())
+}
+
+#[tauri::command]
+pub fn create_calendar(db: State<'_, Database>, calendar: NewCalendar) -> Result<Calendar> {
+    create(&db.conn(), calendar)
+}
+
+#[tauri::command]
+pub fn update_calendar(
+    db: State<'_, Database>,
+    id: String,
+    patch: CalendarPatch,
+) -> Result<Calendar> {
+    update(&db.conn(), &id, patch)
+}
+
+#[tauri::command]
+pub fn reorder_calendars(
+    db: State<'_, Database>,
+    ordered_ids: Vec<String>,
+) -> Result<Vec<Calendar>> {
+    reorder(&mut db.conn(), &ordered_ids)
+}
+
+#[tauri::command]
+pub fn set_calendar_enabled(
+    db: State<'_, Database>,
+    id: String,
+    enabled: bool,
+) -> Result<Calendar> {
+    set_enabled(&db.conn(), &id, enabled)
+}
+
+#[tauri::command]
+pub fn delete_calendar(db: State<'_, Database>, id: String) -> Result<()> {
+    delete(&db.conn(), &id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{completions, habits};
+
+    fn new_calendar(conn: &Connection, name: &str) -> Calendar {
+        create(
+            conn,
+            NewCalendar {
+                name: name.into(),
+                color_theme: "blue".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap()
+    }
+
+    fn names(conn: &Connection) -> Vec<String> {
+        list(conn).unwrap().into_iter().map(|c| c.name).collect()
+    }
+
+    #[test]
+    fn rejects_unknown_colors_and_blank_names() {
+        let db = Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let input = |name: &str, color: &str| NewCalendar {
+            name: name.into(),
+            color_theme: color.into(),
+            user_id: None,
+            position: None,
+        };
+        assert!(matches!(
+            create(&conn, input("Work", "chartreuse")),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            create(&conn, input("  ", "blue")),
+            Err(Error::InvalidInput(_))
+        ));
+
+        let calendar = create(&conn, input("  Work ", "teal")).unwrap();
+        assert_eq!(calendar.name, "Work");
+        assert_eq!(calendar.position, 0);
+        let patch = CalendarPatch {
+            color_theme: Some("#ff0000".into()),
+            ..CalendarPatch::default()
+        };
+        assert!(matches!(
+            update(&conn, &calendar.id, patch),
+            Err(Error::InvalidInput(_))
+        ));
+        assert_eq!(get(&conn, &calendar.id).unwrap().color_theme, "teal");
+    }
+
+    #[test]
+    fn reorder_moves_listed_calendars_first() {
+        let db = Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        let a = new_calendar(&conn, "A");
+        let b = new_calendar(&conn, "B");
+        let c = new_calendar(&conn, "C");
+
+        let ordered = reorder(&mut conn, &[c.id.clone(), a.id.clone()]).unwrap();
+        let positions: Vec<i64> = ordered.iter().map(|c| c.position).collect();
+        assert_eq!(positions, vec![0, 1, 2]);
+        assert_eq!(names(&conn), vec!["C", "A", "B"]);
+        assert!(get(&conn, &b.id).unwrap().updated_at >= b.updated_at);
+
+        // Nothing moves, so no calendar gets a newer updatedAt
+        let before = list(&conn).unwrap();
+        let after = reorder(&mut conn, std::slice::from_ref(&c.id)).unwrap();
+        let stamps = |calendars: &[Calendar]| -> Vec<i64> {
+            calendars.iter().map(|c| c.updated_at).collect()
+        };
+        assert_eq!(stamps(&after), stamps(&before));
+
+        assert!(matches!(
+            reorder(&mut conn, &["missing".into()]),
+            Err(Error::NotFound { .. })
+        ));
+        assert_eq!(names(&conn), vec!["C", "A", "B"]);
+    }
+
+    #[test]
+    fn enabling_and_deleting() {
+        let db = Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let calendar = new_calendar(&conn, "Health");
+        assert!(calendar.is_enabled);
+        assert!(!set_enabled(&conn, &calendar.id, false).unwrap().is_enabled);
+        assert!(!get(&conn, &calendar.id).unwrap().is_enabled);
+        assert!(set_enabled(&conn, &calendar.id, true).unwrap().is_enabled);
+
+        let habit = habits::tests_support::new_habit(&conn, &calendar.id, "Walk");
+        completions::log(&conn, &habit.id, None, None).unwrap();
+
+        delete(&conn, &calendar.id).unwrap();
+        assert!(list(&conn).unwrap().is_empty());
+        assert!(matches!(
+            habits::get(&conn, &habit.id),
+            Err(Error::NotFound { .. })
+        ));
+        let completions: i64 = conn
+            .query_row("SELECT count(*) FROM completions", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(completions, 0);
+        assert!(matches!(
+            delete(&conn, &calendar.id),
+            Err(Error::NotFound { .. })
+        ));
+    }
+}
@@ -0,0 +1,557 @@
+// CSV export and import of completions.
+//
+// Exports are one row per completion, joined with the habit and calendar
+// names, with `completedAt` as an RFC 3339 timestamp in local time. Importing
+// works from a column mapping (suggested by `preview`) so spreadsheets from
+// other tools can be brought in; a dry run reports what would happen without
+// writing anything. Exported files import back unchanged.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::calendars;
+use crate::completions::{self, Completion};
+use crate::days;
+use crate::error::{Error, Result};
+use crate::habits::{self, Habit};
+use crate::storage::{self, new_id, now_millis, Database};
+
+pub const HEADERS: [&str; 10] = [
+    "id",
+    "habit_id",
+    "habit_name",
+    "calendar_id",
+    "calendar_name",
+    "completed_at",
+    "quantity",
+    "unit",
+    "user_id",
+    "duration_seconds",
+];
+
+/// Rows shown by [`preview`].
+const PREVIEW_ROWS: usize = 5;
+
+/// Which CSV header feeds each completion field. Either `habit_id` or
+/// `habit_name` must be set, and `completed_at` always.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvMapping {
+    pub id: Option<String>,
+    pub habit_id: Option<String>,
+    pub habit_name: Option<String>,
+    /// Narrows `habit_name` lookups when several calendars share a habit name.
+    pub calendar_name: Option<String>,
+    pub completed_at: Option<String>,
+    pub quantity: Option<String>,
+    pub unit: Option<String>,
+    pub user_id: Option<String>,
+    pub duration_seconds: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvPreview {
+    pub headers: Vec<String>,
+    pub sample_rows: Vec<Vec<String>>,
+    pub total_rows: usize,
+    pub suggested_mapping: CsvMapping,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvRowError {
+    /// 1-based line in the file (the header is line 1).
+    pub line: u64,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvImportReport {
+    pub dry_run: bool,
+    pub total_rows: usize,
+    pub imported: usize,
+    /// Rows whose id, or habit and timestamp, already exist.
+    pub duplicates: usize,
+    pub errors: Vec<CsvRowError>,
+}
+
+/// Writes every completion as CSV, oldest first. Returns the number of rows.
+pub fn export<W: Write>(conn: &Connection, writer: W) -> Result<usize> {
+    let mut stmt = conn.prepare(
+        "SELECT c.id, c.habitId, h.name, h.calendarId, cal.name, c.completedAt,
+                c.quantity, c.unit, c.userId, c.durationSeconds
+         FROM completions c
+         JOIN habits h ON h.id = c.habitId
+         JOIN calendars cal ON cal.id = h.calendarId
+         ORDER BY c.completedAt, c.id",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record(HEADERS)?;
+    let mut count = 0;
+    while let Some(row) = rows.next()? {
+        let completed_at: i64 = row.get(5)?;
+        let quantity: f64 = row.get(6)?;
+        csv.write_record([
+            row.get::<_, String>(0)?,
+            row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+            format_timestamp(completed_at)?,
+            quantity.to_string(),
+            row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+            row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+            row.get::<_, Option<i64>>(9)?
+                .map(|seconds| seconds.to_string())
+                .unwrap_or_default(),
+        ])?;
+        count += 1;
+    }
+    csv.flush()?;
+    Ok(count)
+}
+
+/// Reads the headers and first rows of a CSV file and guesses a mapping.
+pub fn preview<R: Read>(reader: R) -> Result<CsvPreview> {
+    let mut csv = reader_builder().from_reader(reader);
+    let headers: Vec<String> = csv.headers()?.iter().map(str::to_string).collect();
+    let mut sample_rows = Vec::new();
+    let mut total_rows = 0;
+    for record in csv.records() {
+        let record = record?;
+        if sample_rows.len() < PREVIEW_ROWS {
+            sample_rows.push(record.iter().map(str::to_string).collect());
+        }
+        total_rows += 1;
+    }
+    Ok(CsvPreview {
+        suggested_mapping: suggest_mapping(&headers),
+        headers,
+        sample_rows,
+        total_rows,
+    })
+}
+
+/// Imports completions from CSV using `mapping`. Rows that fail to parse or
+/// resolve are reported and skipped; the rest are inserted. With `dry_run`
+/// the report is identical but nothing is written.
+pub fn import<R: Read>(
+    conn: &mut Connection,
+    reader: R,
+    mapping: &CsvMapping,
+    dry_run: bool,
+) -> Result<CsvImportReport> {
+    let mut csv = reader_builder().from_reader(reader);
+    let columns = Columns::resolve(csv.headers()?, mapping)?;
+    let tx = conn.transaction()?;
+    let habits = habits::list(&tx, None)?;
+    let calendars = calendars::list(&tx)?;
+    let now = now_millis();
+
+    let mut report = CsvImportReport {
+        dry_run,
+        ..CsvImportReport::default()
+    };
+    let mut seen = HashSet::new();
+    for record in csv.records() {
+        let record = record?;
+        report.total_rows += 1;
+        let line = record.position().map_or(0, |p| p.line());
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|i| record.get(i))
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+
+        let completion = (|| {
+            let habit = resolve_habit(
+                &habits,
+                &calendars,
+                field(columns.habit_id),
+                field(columns.habit_name),
+                field(columns.calendar_name),
+            )?;
+            let completed_at = parse_timestamp(field(columns.completed_at).unwrap_or_default())?;
+            let quantity = match field(columns.quantity) {
+                Some(value) => value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|q| q.is_finite() && *q > 0.0)
+                    .ok_or_else(|| format!("invalid quantity `{value}`"))?,
+                None => 1.0,
+            };
+            let duration_seconds = field(columns.duration_seconds)
+                .map(|value| {
+                    value
+                        .parse::<i64>()
+                        .ok()
+                        .filter(|seconds| *seconds >= 0)
+                        .ok_or_else(|| format!("invalid duration `{value}`"))
+                })
+                .transpose()?;
+            Ok::<_, String>(Completion {
+                id: field(columns.id).map_or_else(new_id, str::to_string),
+                user_id: field(columns.user_id).map(str::to_string),
+                habit_id: habit.id.clone(),
+                completed_at,
+                client_updated_at: now,
+                quantity,
+                unit: habits::clean_unit(field(columns.unit).map(str::to_string))
+                    .or_else(|| habit.unit.clone()),
+                duration_seconds,
+            })
+        })();
+
+        let completion = match completion {
+            Ok(completion) => completion,
+            Err(message) => {
+                report.errors.push(CsvRowError { line, message });
+                continue;
+            }
+        };
+        if !seen.insert(completion.id.clone())
+            || completions::exists(&tx, &completion.id)?
+            || is_logged(&tx, &completion.habit_id, completion.completed_at)?
+        {
+            report.duplicates += 1;
+            continue;
+        }
+        completions::insert(&tx, &completion)?;
+        report.imported += 1;
+    }
+
+    // Dropping the transaction rolls the dry run back
+    if !dry_run {
+        tx.commit()?;
+    }
+    Ok(report)
+}
+
+fn reader_builder() -> csv::ReaderBuilder {
+    let mut builder = csv::ReaderBuilder::new();
+    builder.flexible(true).trim(csv::Trim::Headers);
+    builder
+}
+
+/// Header indices for each mapped field.
+struct Columns {
+    id: Option<usize>,
+    habit_id: Option<usize>,
+    habit_name: Option<usize>,
+    calendar_name: Option<usize>,
+    completed_at: Option<usize>,
+    quantity: Option<usize>,
+    unit: Option<usize>,
+    user_id: Option<usize>,
+    duration_seconds: Option<usize>,
+}
+
+impl Columns {
+    fn resolve(headers: &csv::StringRecord, mapping: &CsvMapping) -> Result<Self> {
+        let index = |header: &Option<String>| -> Result<Option<usize>> {
+            match header {
+                None => Ok(None),
+                Some(name) => headers
+                    .iter()
+                    .position(|h| h == name)
+                    .map(Some)
+                    .ok_or_else(|| Error::InvalidInput(format!("no `{name}` column in the file"))),
+            }
+        };
+        let columns = Self {
+            id: index(&mapping.id)?,
+            habit_id: index(&mapping.habit_id)?,
+            habit_name: index(&mapping.habit_name)?,
+            calendar_name: index(&mapping.calendar_name)?,
+            completed_at: index(&mapping.completed_at)?,
+            quantity: index(&mapping.quantity)?,
+            unit: index(&mapping.unit)?,
+            user_id: index(&mapping.user_id)?,
+            duration_seconds: index(&mapping.duration_seconds)?,
+        };
+        if columns.habit_id.is_none() && columns.habit_name.is_none() {
+            return Err(Error::InvalidInput(
+                "map a habit id or habit name column".into(),
+            ));
+        }
+        if columns.completed_at.is_none() {
+            return Err(Error::InvalidInput("map a completed-at column".into()));
+        }
+        Ok(columns)
+    }
+}
+
+/// Matches headers to fields, ignoring case, spaces and punctuation.
+fn suggest_mapping(headers: &[String]) -> CsvMapping {
+    let find = |aliases: &[&str]| {
+        aliases.iter().find_map(|alias| {
+            headers
+                .iter()
+                .find(|header| normalize(header) == *alias)
+                .cloned()
+        })
+    };
+    CsvMapping {
+        id: find(&["id", "completionid"]),
+        habit_id: find(&["habitid"]),
+        habit_name: find(&["habitname", "habit", "name"]),
+        calendar_name: find(&["calendarname", "calendar", "category"]),
+        completed_at: find(&["completedat", "timestamp", "datetime", "date", "time"]),
+        quantity: find(&["quantity", "amount", "value", "count"]),
+        unit: find(&["unit", "units"]),
+        user_id: find(&["userid"]),
+        duration_seconds: find(&["durationseconds", "duration"]),
+    }
+}
+
+fn normalize(header: &str) -> String {
+    header
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn resolve_habit<'a>(
+    habits: &'a [Habit],
+    calendars: &[calendars::Calendar],
+    habit_id: Option<&str>,
+    habit_name: Option<&str>,
+    calendar_name: Option<&str>,
+) -> std::result::Result<&'a Habit, String> {
+    if let Some(id) = habit_id {
+        if let Some(habit) = habits.iter().find(|h| h.id == id) {
+            return Ok(habit);
+        }
+        if habit_name.is_none() {
+            return Err(format!("unknown habit `{id}`"));
+        }
+    }
+    let Some(name) = habit_name else {
+        return Err("missing habit".into());
+    };
+    let calendar_ids: Option<Vec<&str>> = calendar_name.map(|calendar| {
+        calendars
+            .iter()
+            .filter(|c| c.name.eq_ignore_ascii_case(calendar))
+            .map(|c| c.id.as_str())
+            .collect()
+    });
+    let mut matches = habits.iter().filter(|h| {
+        h.name.trim().eq_ignore_ascii_case(name)
+            && calendar_ids
+                .as_ref()
+                .is_none_or(|ids| ids.contains(&h.calendar_id.as_str()))
+    });
+    match (matches.next(), matches.next()) {
+        (Some(habit), None) => Ok(habit),
+        (None, _) => Err(format!("unknown habit `{name}`")),
+        (Some(_), Some(_)) => Err(format!(
+            "habit name `{name}` is ambiguous; map a calendar column"
+        )),
+    }
+}
+
+fn is_logged(conn: &Connection, habit_id: &str, completed_at: i64) -> Result<bool> {
+    Ok(!completions::list_between(conn, habit_id, completed_at, completed_at + 1)?.is_empty())
+}
+
+fn format_timestamp(millis: i64) -> Result<String> {
+    Local
+        .timestamp_millis_opt(millis)
+        .single()
+        .map(|at| at.to_rfc3339_opts(SecondsFormat::Millis, false))
+        .ok_or_else(|| Error::InvalidInput(format!("timestamp {millis} is out of range")))
+}
+
+/// Accepts RFC 3339, a local `YYYY-MM-DD HH:MM[:SS]`, or a bare local date
+/// (taken as noon so it stays on that day).
+fn parse_timestamp(value: &str) -> std::result::Result<i64, String> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Ok(at.timestamp_millis());
+    }
+    for format in [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(value, format) {
+            return Local
+                .from_local_datetime(&naive)
+                .earliest()
+                .map(|at| at.timestamp_millis())
+                .ok_or_else(|| format!("`{value}` does not exist in local time"));
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(days::local_day_bounds(date).0 + 12 * 60 * 60 * 1000);
+    }
+    if value.is_empty() {
+        return Err("missing completion time".into());
+    }
+    Err(format!("unrecognized timestamp `{value}`"))
+}
+
+/// Exports to `path`, replacing the file atomically.
+pub fn export_to_path(conn: &Connection, path: &Path) -> Result<usize> {
+    storage::write_atomically(path, |partial| export(conn, File::create(partial)?))
+}
+
+// --- Tauri commands ---
+
+/// Writes all completions to `path` as CSV, returning the row count.
+#[tauri::command]
+pub fn export_completions_csv(db: State<'_, Database>, path: String) -> Result<usize> {
+    export_to_path(&db.conn(), Path::new(&path))
+}
+
+#[tauri::command]
+pub fn preview_csv_import(path: String) -> Result<CsvPreview> {
+    preview(File::open(path)?)
+}
+
+#[tauri::command]
+pub fn import_completions_csv(
+    db: State<'_, Database>,
+    path: String,
+    mapping: CsvMapping,
+    dry_run: bool,
+) -> Result<CsvImportReport> {
+    import(&mut db.conn(), File::open(path)?, &mapping, dry_run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::calendars::NewCalendar;
+    use crate::habits::NewHabit;
+
+    fn habit(conn: &Connection, calendar: &str, name: &str) -> Habit {
+        let calendar = calendars::create(
+            conn,
+            NewCalendar {
+                name: calendar.into(),
+                color_theme: "blue".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap();
+        habits::create(
+            conn,
+            NewHabit {
+                unit: Some("km".into()),
+                ..NewHabit::named(&calendar.id, name)
+            },
+        )
+        .unwrap()
+    }
+
+    fn export_string(conn: &Connection) -> String {
+        let mut out = Vec::new();
+        export(conn, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn exported_csv_imports_back_unchanged() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        let run = habit(&conn, "Health", "Run");
+        completions::log_quantity(
+            &conn,
+            &run.id,
+            Some(1_700_000_000_123),
+            Some(5.5),
+            None,
+            None,
+        )
+        .unwrap();
+        completions::log(&conn, &run.id, Some(1_700_086_400_000), Some("user-1")).unwrap();
+        completions::log_timed(&conn, &run.id, 1_700_100_000_000, 1800, None).unwrap();
+        let exported = export_string(&conn);
+
+        let preview = preview(exported.as_bytes()).unwrap();
+        assert_eq!(preview.total_rows, 3);
+        let mapping = preview.suggested_mapping;
+        assert_eq!(mapping.habit_id.as_deref(), Some("habit_id"));
+        assert_eq!(mapping.completed_at.as_deref(), Some("completed_at"));
+
+        // Re-importing the same file finds only duplicates
+        let report = import(&mut conn, exported.as_bytes(), &mapping, false).unwrap();
+        assert_eq!((report.imported, report.duplicates), (0, 3));
+
+        conn.execute("DELETE FROM completions", []).unwrap();
+        let report = import(&mut conn, exported.as_bytes(), &mapping, false).unwrap();
+        assert_eq!((report.imported, report.errors.len()), (3, 0));
+        assert_eq!(export_string(&conn), exported);
+    }
+
+    #[test]
+    fn dry_run_reports_without_writing() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        let run = habit(&conn, "Health", "Run");
+        habit(&conn, "Work", "Run");
+        let file = "Date,Habit,Calendar,Amount\n\
+                    2024-03-01,run,health,3\n\
+                    2024-03-02 07:30,Run,,2\n\
+                    2024-03-03,Swim,Health,1\n\
+                    yesterday,Run,Health,\n\
+                    2024-03-04T08:00:00+02:00,Run,Health,-1\n";
+        let mapping = preview(file.as_bytes()).unwrap().suggested_mapping;
+        assert_eq!(mapping.habit_name.as_deref(), Some("Habit"));
+        assert_eq!(mapping.quantity.as_deref(), Some("Amount"));
+
+        let report = import(&mut conn, file.as_bytes(), &mapping, true).unwrap();
+        assert_eq!((report.total_rows, report.imported), (5, 1));
+        let lines: Vec<u64> = report.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![3, 4, 5, 6]);
+        assert!(completions::list_all(&conn, &run.id).unwrap().is_empty());
+
+        let report = import(&mut conn, file.as_bytes(), &mapping, false).unwrap();
+        assert_eq!(report.imported, 1);
+        let logged = completions::list_all(&conn, &run.id).unwrap();
+        assert_eq!(logged[0].quantity, 3.0);
+        assert_eq!(logged[0].unit.as_deref(), Some("km"));
+    }
+
+    #[test]
+    fn failed_export_leaves_no_partial_file() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let run = habit(&conn, "Fitness", "Run");
+        completions::log(&conn, &run.id, Some(1_700_000_000_000), None).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("habistat-csv-{}", new_id()));
+        fs::create_dir_all(&dir).unwrap();
+        // A file that merely shares the stem is not ours to overwrite
+        fs::write(dir.join("export.partial"), "keep me").unwrap();
+        assert_eq!(export_to_path(&conn, &dir.join("export.csv")).unwrap(), 1);
+        assert_eq!(
+            fs::read_to_string(dir.join("export.partial")).unwrap(),
+            "keep me"
+        );
+        assert!(!dir.join("export.csv.partial").exists());
+
+        // A directory in the way makes the final rename fail
+        fs::create_dir(dir.join("taken.csv")).unwrap();
+        assert!(export_to_path(&conn, &dir.join("taken.csv")).is_err());
+        assert!(!dir.join("taken.csv.partial").exists());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,455 @@
+// Completion logging on the native database.
+// Day-scoped operations take a local calendar date and resolve it to
+// `[start, end)` millisecond bounds in Rust (see `days.rs`), matching the
+// bounds the webview sends to Convex's `deleteLatestCompletionForDay`.
+// Completions of quantitative habits carry an amount; `daily_totals` sums it
+// per day against the habit's `targetQuantity`.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::days;
+use crate::error::{Error, Result};
+use crate::habits;
+use crate::storage::{new_id, now_millis, Database};
+
+const COLUMNS: &str =
+    "id, userId, habitId, completedAt, clientUpdatedAt, quantity, unit, durationSeconds";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Completion {
+    pub id: String,
+    pub user_id: Option<String>,
+    pub habit_id: String,
+    pub completed_at: i64,
+    pub client_updated_at: i64,
+    /// How much this completion counts for; 1 for plain check-offs.
+    pub quantity: f64,
+    pub unit: Option<String>,
+    /// Length of the timer session this completion ended, if it came from one.
+    pub duration_seconds: Option<i64>,
+}
+
+impl Completion {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            habit_id: row.get(2)?,
+            completed_at: row.get(3)?,
+            client_updated_at: row.get(4)?,
+            quantity: row.get(5)?,
+            unit: row.get(6)?,
+            duration_seconds: row.get(7)?,
+        })
+    }
+}
+
+/// Records a completion for `habit_id` at `completed_at` (default: now).
+pub fn log(
+    conn: &Connection,
+    habit_id: &str,
+    completed_at: Option<i64>,
+    user_id: Option<&str>,
+) -> Result<Completion> {
+    log_quantity(conn, habit_id, completed_at, None, None, user_id)
+}
+
+/// [`log`] with an amount: `quantity` defaults to 1 and `unit` to the habit's unit.
+pub fn log_quantity(
+    conn: &Connection,
+    habit_id: &str,
+    completed_at: Option<i64>,
+    quantity: Option<f64>,
+    unit: Option<&str>,
+    user_id: Option<&str>,
+) -> Result<Completion> {
+    let completion = prepare(conn, habit_id, completed_at, quantity, unit, user_id)?;
+    insert(conn, &completion)?;
+    Ok(completion)
+}
+
+/// [`log`] for the end of a timer session lasting `duration_seconds`.
+pub fn log_timed(
+    conn: &Connection,
+    habit_id: &str,
+    completed_at: i64,
+    duration_seconds: i64,
+    user_id: Option<&str>,
+) -> Result<Completion> {
+    let mut completion = prepare(conn, habit_id, Some(completed_at), None, None, user_id)?;
+    completion.duration_seconds = Some(duration_seconds);
+    insert(conn, &completion)?;
+    Ok(completion)
+}
+
+// Builds (but does not insert) a completion with the defaults of `log_quantity`
+fn prepare(
+    conn: &Connection,
+    habit_id: &str,
+    completed_at: Option<i64>,
+    quantity: Option<f64>,
+    unit: Option<&str>,
+    user_id: Option<&str>,
+) -> Result<Completion> {
+    let habit = habits::get(conn, habit_id)?;
+    let quantity = quantity.unwrap_or(1.0);
+    if !(quantity.is_finite() && quantity > 0.0) {
+        return Err(Error::InvalidInput("quantity must be positive".into()));
+    }
+    let now = now_millis();
+    Ok(Completion {
+        id: new_id(),
+        user_id: user_id.map(str::to_string),
+        habit_id: habit_id.to_string(),
+        completed_at: completed_at.unwrap_or(now),
+        client_updated_at: now,
+        quantity,
+        unit: habits::clean_unit(unit.map(str::to_string)).or(habit.unit),
+        duration_seconds: None,
+    })
+}
+
+/// Inserts `completion` as-is, keeping its id and timestamps. Used by importers.
+pub(crate) fn insert(conn: &Connection, completion: &Completion) -> Result<()> {
+    conn.execute(
+        &format!("INSERT INTO completions ({COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"),
+        params![
+            completion.id,
+            completion.user_id,
+            completion.habit_id,
+            completion.completed_at,
+            completion.client_updated_at,
+            completion.quantity,
+            completion.unit,
+            completion.duration_seconds
+        ],
+    )?;
+    Ok(())
+}
+
+/// Whether a completion with this id exists.
+pub(crate) fn exists(conn: &Connection, id: &str) -> Result<bool> {
+    Ok(conn
+        .query_row("SELECT 1 FROM completions WHERE id = ?1", [id], |_| Ok(()))
+        .optional()?
+        .is_some())
+}
+
+/// Completions of a habit within `[start, end)` milliseconds, oldest first.
+pub fn list_between(
+    conn: &Connection,
+    habit_id: &str,
+    start: i64,
+    end: i64,
+) -> Result<Vec<Completion>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {COLUMNS} FROM completions
+         WHERE habitId = ?1 AND completedAt >= ?2 AND completedAt < ?3
+         ORDER BY completedAt"
+    ))?;
+    let rows = stmt.query_map(params![habit_id, start, end], Completion::from_row)?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Completions of a habit on the local days `from..=to`, oldest first.
+pub fn list_days(
+    conn: &Connection,
+    habit_id: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<Completion>> {
+    if to < from {
+        return Err(Error::InvalidInput(format!(
+            "date range is reversed ({from} > {to})"
+        )));
+    }
+    let (start, _) = days::local_day_bounds(from);
+    let (_, end) = days::local_day_bounds(to);
+    list_between(conn, habit_id, start, end)
+}
+
+/// Every completion of a habit, oldest first.
+pub fn list_all(conn: &Connection, habit_id: &str) -> Result<Vec<Completion>> {
+    list_between(conn, habit_id, i64::MIN, i64::MAX)
+}
+
+/// Removes the most recent completion on the given local day, if any.
+pub fn undo_last(conn: &Connection, habit_id: &str, date: NaiveDate) -> Result<Option<Completion>> {
+    let (start, end) = days::local_day_bounds(date);
+    let latest = conn
+        .query_row(
+            &format!(
+                "SELECT {COLUMNS} FROM completions
+                 WHERE habitId = ?1 AND completedAt >= ?2 AND completedAt < ?3
+                 ORDER BY completedAt DESC LIMIT 1"
+            ),
+            params![habit_id, start, end],
+            Completion::from_row,
+        )
+        .optional()?;
+
+    if let Some(completion) = &latest {
+        conn.execute("DELETE FROM completions WHERE id = ?1", [&completion.id])?;
+    }
+    Ok(latest)
+}
+
+/// Removes all completions of a habit on the given local day, returning how many were deleted.
+pub fn delete_for_day(conn: &Connection, habit_id: &str, date: NaiveDate) -> Result<usize> {
+    let (start, end) = days::local_day_bounds(date);
+    Ok(conn.execute(
+        "DELETE FROM completions WHERE habitId = ?1 AND completedAt >= ?2 AND completedAt < ?3",
+        params![habit_id, start, end],
+    )?)
+}
+
+/// Amount logged on one local day, relative to the habit's target.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyTotal {
+    pub date: NaiveDate,
+    pub total_quantity: f64,
+    pub completion_count: u32,
+    /// `totalQuantity` as a percentage of the habit's `targetQuantity`; may
+    /// exceed 100. `None` when the habit has no target.
+    pub percent_of_target: Option<f64>,
+}
+
+/// Per-day totals of one habit over `from..=to`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HabitTotals {
+    pub habit_id: String,
+    pub unit: Option<String>,
+    pub target_quantity: Option<f64>,
+    pub days: Vec<DailyTotal>,
+}
+
+/// Sums a habit's completion quantities per local day, one entry for every
+/// day in `from..=to` (days without completions total 0).
+pub fn daily_totals(
+    conn: &Connection,
+    habit_id: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<HabitTotals> {
+    let habit = habits::get(conn, habit_id)?;
+    let mut by_day: BTreeMap<NaiveDate, (f64, u32)> = BTreeMap::new();
+    for completion in list_days(conn, habit_id, from, to)? {
+        let day = by_day
+            .entry(days::local_date_of(completion.completed_at))
+            .or_default();
+        day.0 += completion.quantity;
+        day.1 += 1;
+    }
+
+    let days = from
+        .iter_days()
+        .take_while(|date| *date <= to)
+        .map(|date| {
+            let (total_quantity, completion_count) = by_day.get(&date).copied().unwrap_or_default();
+            DailyTotal {
+                date,
+                total_quantity,
+                completion_count,
+                percent_of_target: habit
+                    .target_quantity
+                    .map(|target| total_quantity / target * 100.0),
+            }
+        })
+        .collect();
+    Ok(HabitTotals {
+        habit_id: habit.id,
+        unit: habit.unit,
+        target_quantity: habit.target_quantity,
+        days,
+    })
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn log_completion(
+    db: State<'_, Database>,
+    habit_id: String,
+    completed_at: Option<i64>,
+    quantity: Option<f64>,
+    unit: Option<String>,
+    user_id: Option<String>,
+) -> Result<Completion> {
+    log_quantity(
+        &db.conn(),
+        &habit_id,
+        completed_at,
+        quantity,
+        unit.as_deref(),
+        user_id.as_deref(),
+    )
+}
+
+/// Undoes the latest completion on `date` (default: today).
+#[tauri::command]
+pub fn undo_last_completion(
+    db: State<'_, Database>,
+    habit_id: String,
+    date: Option<NaiveDate>,
+) -> Result<Option<Completion>> {
+    undo_last(&db.conn(), &habit_id, date.unwrap_or_else(days::today))
+}
+
+#[tauri::command]
+pub fn delete_completions_for_day(
+    db: State<'_, Database>,
+    habit_id: String,
+    date: NaiveDate,
+) -> Result<usize> {
+    delete_for_day(&db.conn(), &habit_id, date)
+}
+
+/// Lists completions on the local days `from..=to` (`YYYY-MM-DD`).
+#[tauri::command]
+pub fn list_completions(
+    db: State<'_, Database>,
+    habit_id: String,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<Completion>> {
+    list_days(&db.conn(), &habit_id, from, to)
+}
+
+/// Per-day quantity totals for each habit (all habits when `habit_ids` is
+/// `None`) on the local days `from..=to`.
+#[tauri::command]
+pub fn get_daily_totals(
+    db: State<'_, Database>,
+    habit_ids: Option<Vec<String>>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<HabitTotals>> {
+    let conn = db.conn();
+    let habit_ids = match habit_ids {
+        Some(ids) => ids,
+        None => habits::list(&conn, None)?
+            .into_iter()
+            .map(|habit| habit.id)
+            .collect(),
+    };
+    habit_ids
+        .iter()
+        .map(|id| daily_totals(&conn, id, from, to))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendars::{self, NewCalendar};
+    use crate::habits::NewHabit;
+    use crate::storage;
+
+    #[test]
+    fn totals_per_day_against_target() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let calendar = calendars::create(
+            &conn,
+            NewCalendar {
+                name: "Health".into(),
+                color_theme: "blue".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap();
+        let habit = habits::create(
+            &conn,
+            NewHabit {
+                target_quantity: Some(8.0),
+                unit: Some(" glasses ".into()),
+                ..NewHabit::named(&calendar.id, "Water")
+            },
+        )
+        .unwrap();
+        assert_eq!(habit.unit.as_deref(), Some("glasses"));
+
+        let day = |d: &str| d.parse::<NaiveDate>().unwrap();
+        let noon = |d: &str| days::local_day_bounds(day(d)).0 + 12 * 60 * 60 * 1000;
+        let first = log_quantity(
+            &conn,
+            &habit.id,
+            Some(noon("2024-03-01")),
+            Some(3.0),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(first.unit.as_deref(), Some("glasses"));
+        log(&conn, &habit.id, Some(noon("2024-03-01")), None).unwrap();
+        log_quantity(
+            &conn,
+            &habit.id,
+            Some(noon("2024-03-03")),
+            Some(10.0),
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(log_quantity(&conn, &habit.id, None, Some(0.0), None, None).is_err());
+
+        let totals = daily_totals(&conn, &habit.id, day("2024-03-01"), day("2024-03-03")).unwrap();
+        let summary: Vec<_> = totals
+            .days
+            .iter()
+            .map(|d| (d.total_quantity, d.completion_count, d.percent_of_target))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (4.0, 2, Some(50.0)),
+                (0.0, 0, Some(0.0)),
+                (10.0, 1, Some(125.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn delete_for_day_stays_within_local_day() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let calendar = calendars::create(
+            &conn,
+            NewCalendar {
+                name: "Health".into(),
+                color_theme: "blue".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap();
+        let walk = habits::tests_support::new_habit(&conn, &calendar.id, "Walk");
+        let read = habits::tests_support::new_habit(&conn, &calendar.id, "Read");
+
+        let day: NaiveDate = "2024-03-02".parse().unwrap();
+        let (start, end) = days::local_day_bounds(day);
+        for completed_at in [start - 1, start, end - 1, end] {
+            log(&conn, &walk.id, Some(completed_at), None).unwrap();
+        }
+        log(&conn, &read.id, Some(start), None).unwrap();
+
+        assert_eq!(delete_for_day(&conn, &walk.id, day).unwrap(), 2);
+        let left: Vec<i64> = list_all(&conn, &walk.id)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.completed_at)
+            .collect();
+        assert_eq!(left, vec![start - 1, end]);
+        assert_eq!(list_all(&conn, &read.id).unwrap().len(), 1);
+        assert_eq!(delete_for_day(&conn, &walk.id, day).unwrap(), 0);
+    }
+}
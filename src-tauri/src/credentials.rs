@@ -0,0 +1,298 @@
+// Storage for the sync auth token outside the webview.
+//
+// The Clerk-issued Convex token goes into the OS credential store (Keychain,
+// Windows Credential Manager, Secret Service). Linux desktops without a
+// Secret Service provider fall back to `<app data>/credentials.json`, sealed
+// with XChaCha20-Poly1305 under a key derived from the machine id. That keeps
+// the token useless in copies of the file taken off the machine (backups,
+// synced home folders); like an unlocked keyring, it does not hide the token
+// from other programs of the same user.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+use zeroize::Zeroizing;
+
+use crate::encryption::{from_hex, to_hex, KeyDerivation};
+use crate::error::{Error, Result};
+
+const SERVICE: &str = "com.habistat.app";
+const TOKEN_ACCOUNT: &str = "convex-auth-token";
+const FALLBACK_FILE_NAME: &str = "credentials.json";
+const MACHINE_ID_PATHS: [&str; 2] = ["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+/// Where a token ended up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TokenStore {
+    Keyring,
+    EncryptedFile,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SealedToken {
+    kdf: KeyDerivation,
+    /// Hex-encoded, like `ciphertext`.
+    nonce: String,
+    ciphertext: String,
+}
+
+fn entry() -> keyring::Result<Entry> {
+    Entry::new(SERVICE, TOKEN_ACCOUNT)
+}
+
+// No credential store to talk to, as opposed to a store that refused the call.
+// Only Linux may lack one; elsewhere this is a real failure.
+fn unavailable(e: &keyring::Error) -> bool {
+    cfg!(target_os = "linux")
+        && matches!(
+            e,
+            keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_)
+        )
+}
+
+/// Saves `token`, replacing any earlier one.
+pub fn store_token(fallback: &Path, token: &str) -> Result<TokenStore> {
+    match entry().and_then(|entry| entry.set_password(token)) {
+        Ok(()) => {
+            // A token sealed while the keyring was missing is now stale
+            remove_sealed(fallback)?;
+            Ok(TokenStore::Keyring)
+        }
+        Err(e) if unavailable(&e) => {
+            seal(fallback, &machine_id()?, token)?;
+            Ok(TokenStore::EncryptedFile)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The saved token, if any.
+pub fn read_token(fallback: &Path) -> Result<Option<String>> {
+    match entry().and_then(|entry| entry.get_password()) {
+        Ok(token) => Ok(Some(token)),
+        Err(keyring::Error::NoEntry) => unseal_if_present(fallback),
+        Err(e) if unavailable(&e) => unseal_if_present(fallback),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Forgets the token wherever it is stored.
+pub fn clear_token(fallback: &Path) -> Result<()> {
+    match entry().and_then(|entry| entry.delete_credential()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => {}
+        Err(e) if unavailable(&e) => {}
+        Err(e) => return Err(e.into()),
+    }
+    remove_sealed(fallback)
+}
+
+fn machine_id() -> Result<Zeroizing<String>> {
+    MACHINE_ID_PATHS
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .map(|id| Zeroizing::new(id.trim().to_string()))
+        .find(|id| !id.is_empty())
+        .ok_or_else(|| {
+            Error::InvalidInput("no machine id to derive the credential key from".into())
+        })
+}
+
+// The machine id is already random, so the KDF only has to bind it to the salt
+fn sealing_kdf() -> KeyDerivation {
+    KeyDerivation {
+        memory_kib: 19 * 1024,
+        iterations: 2,
+        ..KeyDerivation::generate()
+    }
+}
+
+fn cipher(kdf: &KeyDerivation, secret: &str) -> Result<XChaCha20Poly1305> {
+    let key = kdf.derive(secret)?;
+    Ok(XChaCha20Poly1305::new(key.as_bytes().into()))
+}
+
+/// Encrypts `token` into the file at `path`, readable only by the owner.
+fn seal(path: &Path, secret: &str, token: &str) -> Result<()> {
+    let kdf = sealing_kdf();
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher(&kdf, secret)?
+        .encrypt(&nonce, token.as_bytes())
+        .map_err(|_| Error::InvalidInput("the token could not be encrypted".into()))?;
+    let sealed = SealedToken {
+        kdf,
+        nonce: to_hex(&nonce),
+        ciphertext: to_hex(&ciphertext),
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = path.with_extension("partial");
+    // `mode` only applies when the file is created, so never reuse a stale one
+    if let Err(e) = fs::remove_file(&partial) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            return Err(e.into());
+        }
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&partial)?
+        .write_all(&serde_json::to_vec(&sealed)?)?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// Decrypts the token sealed at `path`. A file that no longer opens (the
+/// machine id changed, or it was tampered with) reads as no token, so the
+/// user simply signs in again.
+fn unseal(path: &Path, secret: &str) -> Result<Option<String>> {
+    let sealed: SealedToken = match fs::read(path) {
+        Ok(bytes) => match serde_json::from_slice(&bytes) {
+            Ok(sealed) => sealed,
+            Err(_) => return Ok(None),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let (Some(nonce), Some(ciphertext)) = (from_hex(&sealed.nonce), from_hex(&sealed.ciphertext))
+    else {
+        return Ok(None);
+    };
+    // Only our own parameters are accepted, so a tampered file cannot pick
+    // the Argon2 cost; only the salt differs between sealed files
+    let expected = KeyDerivation {
+        salt: sealed.kdf.salt.clone(),
+        ..sealing_kdf()
+    };
+    if nonce.len() != 24 || sealed.kdf != expected {
+        return Ok(None);
+    }
+    let Ok(cipher) = cipher(&sealed.kdf, secret) else {
+        return Ok(None);
+    };
+    let plaintext = cipher
+        .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+        .ok()
+        .map(Zeroizing::new);
+    Ok(plaintext.and_then(|bytes| String::from_utf8(bytes.to_vec()).ok()))
+}
+
+fn unseal_if_present(path: &Path) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    unseal(path, &machine_id()?)
+}
+
+fn remove_sealed(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Location of the encrypted fallback file.
+pub fn fallback_path<R: Runtime>(app: &AppHandle<R>) -> tauri::Result<PathBuf> {
+    Ok(app.path().app_data_dir()?.join(FALLBACK_FILE_NAME))
+}
+
+// `fallback_path` with the crate error type
+pub(crate) fn fallback<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf> {
+    fallback_path(app).map_err(|e| Error::Io(std::io::Error::other(e.to_string())))
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn store_auth_token<R: Runtime>(app: AppHandle<R>, token: String) -> Result<TokenStore> {
+    let token = Zeroizing::new(token);
+    store_token(&fallback(&app)?, &token)
+}
+
+#[tauri::command]
+pub fn get_auth_token<R: Runtime>(app: AppHandle<R>) -> Result<Option<String>> {
+    read_token(&fallback(&app)?)
+}
+
+#[tauri::command]
+pub fn clear_auth_token<R: Runtime>(app: AppHandle<R>) -> Result<()> {
+    clear_token(&fallback(&app)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::new_id;
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_partial_file_does_not_widen_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("habistat-credentials-{}", new_id()));
+        let path = dir.join(FALLBACK_FILE_NAME);
+        let partial = path.with_extension("partial");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&partial, b"left over").unwrap();
+        fs::set_permissions(&partial, fs::Permissions::from_mode(0o644)).unwrap();
+
+        seal(&path, "machine-a", "eyJhbGciOi.token").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn sealed_token_opens_only_with_the_same_machine_id() {
+        let dir = std::env::temp_dir().join(format!("habistat-credentials-{}", new_id()));
+        let path = dir.join(FALLBACK_FILE_NAME);
+        assert_eq!(unseal(&path, "machine-a").unwrap(), None);
+
+        seal(&path, "machine-a", "eyJhbGciOi.token").unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("token"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        assert_eq!(
+            unseal(&path, "machine-a").unwrap().as_deref(),
+            Some("eyJhbGciOi.token")
+        );
+        assert_eq!(unseal(&path, "machine-b").unwrap(), None);
+
+        fs::write(&path, b"{ not json").unwrap();
+        assert_eq!(unseal(&path, "machine-a").unwrap(), None);
+
+        seal(&path, "machine-a", "eyJhbGciOi.token").unwrap();
+        let sealed: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        for (field, value) in [
+            ("salt", serde_json::json!("not hex")),
+            ("algorithm", serde_json::json!("scrypt")),
+            ("memoryKib", serde_json::json!(u32::MAX)),
+        ] {
+            let mut tampered = sealed.clone();
+            tampered["kdf"][field] = value;
+            fs::write(&path, serde_json::to_vec(&tampered).unwrap()).unwrap();
+            assert_eq!(unseal(&path, "machine-a").unwrap(), None, "{field}");
+        }
+
+        remove_sealed(&path).unwrap();
+        remove_sealed(&path).unwrap();
+        assert_eq!(unseal(&path, "machine-a").unwrap(), None);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,140 @@
+// Local-day helpers. Mirrors `getLocalDayRange` / `formatLocalDate` in
+// `src/lib/utils/date.ts` so the native side and the calendar heatmap agree on
+// which day a completion belongs to.
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+
+/// `[start, end)` bounds of a local calendar day as Unix milliseconds.
+pub fn day_bounds<Tz: TimeZone>(tz: &Tz, date: NaiveDate) -> (i64, i64) {
+    let next = date.succ_opt().unwrap_or(date);
+    (start_of_day(tz, date), start_of_day(tz, next))
+}
+
+/// Local calendar day a Unix-millisecond timestamp falls on.
+pub fn date_of<Tz: TimeZone>(tz: &Tz, millis: i64) -> NaiveDate {
+    DateTime::from_timestamp_millis(millis)
+        .unwrap_or_default()
+        .with_timezone(tz)
+        .date_naive()
+}
+
+/// Source of the current time for background schedulers, so tests can move
+/// it by hand.
+pub trait Clock {
+    type Tz: TimeZone;
+
+    fn now(&self) -> DateTime<Self::Tz>;
+}
+
+/// The wall clock in the user's local timezone.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    type Tz = Local;
+
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+/// Today's date in the user's local timezone.
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+/// [`day_bounds`] in the user's local timezone.
+pub fn local_day_bounds(date: NaiveDate) -> (i64, i64) {
+    day_bounds(&Local, date)
+}
+
+/// [`date_of`] in the user's local timezone.
+pub fn local_date_of(millis: i64) -> NaiveDate {
+    date_of(&Local, millis)
+}
+
+/// Serde helpers for a local time of day written as `HH:MM`, for use with
+/// `#[serde(with = "days::hh_mm")]`.
+pub mod hh_mm {
+    use chrono::NaiveTime;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn parse(time: &str) -> Option<NaiveTime> {
+        NaiveTime::parse_from_str(time.trim(), "%H:%M").ok()
+    }
+
+    pub fn serialize<S: Serializer>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&time.format("%H:%M"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+        let time = String::deserialize(deserializer)?;
+        parse(&time)
+            .ok_or_else(|| de::Error::custom(format!("invalid time `{time}`, expected HH:MM")))
+    }
+}
+
+// Midnight does not exist on some DST transition days (e.g. America/Santiago);
+// the day then starts at the first valid local time after it.
+fn start_of_day<Tz: TimeZone>(tz: &Tz, date: NaiveDate) -> i64 {
+    let mut time = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    for _ in 0..24 * 4 {
+        if let Some(start) = tz.from_local_datetime(&time).earliest() {
+            return start.timestamp_millis();
+        }
+        time += chrono::Duration::minutes(15);
+    }
+    time.and_utc().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::America::{New_York, Santiago};
+
+    const HOUR: i64 = 60 * 60 * 1000;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn utc(s: &str) -> i64 {
+        s.parse::<DateTime<chrono::Utc>>()
+            .unwrap()
+            .timestamp_millis()
+    }
+
+    #[test]
+    fn spring_forward_day_is_23_hours() {
+        let (start, end) = day_bounds(&New_York, date("2024-03-10"));
+        assert_eq!(start, utc("2024-03-10T05:00:00Z"));
+        assert_eq!(end - start, 23 * HOUR);
+        assert_eq!(date_of(&New_York, end - 1), date("2024-03-10"));
+        assert_eq!(date_of(&New_York, end), date("2024-03-11"));
+    }
+
+    #[test]
+    fn fall_back_day_is_25_hours() {
+        let (start, end) = day_bounds(&New_York, date("2024-11-03"));
+        assert_eq!(start, utc("2024-11-03T04:00:00Z"));
+        assert_eq!(end - start, 25 * HOUR);
+        // 01:30 happens twice; both belong to the same day
+        assert_eq!(
+            date_of(&New_York, utc("2024-11-03T05:30:00Z")),
+            date("2024-11-03")
+        );
+        assert_eq!(
+            date_of(&New_York, utc("2024-11-03T06:30:00Z")),
+            date("2024-11-03")
+        );
+    }
+
+    #[test]
+    fn day_without_midnight_starts_at_first_valid_time() {
+        // Clocks jump from 00:00 to 01:00
+        let (start, end) = day_bounds(&Santiago, date("2024-09-08"));
+        assert_eq!(start, utc("2024-09-08T04:00:00Z"));
+        assert_eq!(end - start, 23 * HOUR);
+        let (_, previous_end) = day_bounds(&Santiago, date("2024-09-07"));
+        assert_eq!(previous_end, start);
+    }
+}
@@ -0,0 +1,558 @@
+// Opt-in encryption at rest for the native database (SQLCipher).
+//
+// The key is derived from a passphrase with Argon2id. The salt and cost
+// parameters are not secret and live next to the database in
+// `encryption.json`; whether the file is encrypted is read from the file
+// itself (a plaintext SQLite file starts with a fixed header), so a crash
+// between writing one and the other never locks the user out.
+//
+// Changing the passphrase is an in-place `PRAGMA rekey`. SQLCipher cannot
+// rekey between plaintext and encrypted, so enabling and disabling export the
+// data into a new file that then replaces the old one. Snapshots in
+// `backups/` always share the database's key and are rekeyed along with it,
+// leaving no plaintext copy behind.
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use rusqlite::{Connection, ErrorCode};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+use zeroize::Zeroizing;
+
+use crate::auto_backup;
+use crate::error::{Error, Result};
+use crate::storage::{self, Database};
+
+pub const UNLOCKED_EVENT: &str = "database://unlocked";
+
+/// KDF parameters, stored next to the database file.
+pub const KDF_FILE_NAME: &str = "encryption.json";
+
+const KEY_LENGTH: usize = 32;
+const SALT_LENGTH: usize = 16;
+const MIN_PASSPHRASE_CHARS: usize = 8;
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Raw SQLCipher key, wiped from memory when dropped.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DatabaseKey(Zeroizing<[u8; KEY_LENGTH]>);
+
+impl DatabaseKey {
+    pub(crate) fn as_bytes(&self) -> &[u8; KEY_LENGTH] {
+        &self.0
+    }
+
+    // SQLCipher takes a raw key as the string `x'<hex>'`, skipping its own KDF
+    fn pragma_value(&self) -> Zeroizing<String> {
+        Zeroizing::new(format!("x'{}'", to_hex(self.0.as_slice())))
+    }
+}
+
+impl std::fmt::Debug for DatabaseKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DatabaseKey(..)")
+    }
+}
+
+/// How the key is derived from the passphrase.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyDerivation {
+    pub algorithm: String,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    /// Hex-encoded.
+    pub salt: String,
+}
+
+impl KeyDerivation {
+    /// Argon2id at 64 MiB and 3 passes with a fresh salt; about half a second
+    /// on a laptop, paid once per unlock.
+    pub fn generate() -> Self {
+        let mut salt = [0u8; SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        Self {
+            algorithm: "argon2id".into(),
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+            salt: to_hex(&salt),
+        }
+    }
+
+    pub fn derive(&self, passphrase: &str) -> Result<DatabaseKey> {
+        if self.algorithm != "argon2id" {
+            return Err(Error::InvalidInput(format!(
+                "unsupported key derivation `{}`",
+                self.algorithm
+            )));
+        }
+        let salt = from_hex(&self.salt)
+            .ok_or_else(|| Error::InvalidInput("key derivation salt is not hex".into()))?;
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LENGTH),
+        )
+        .map_err(kdf_error)?;
+        let mut key = Zeroizing::new([0u8; KEY_LENGTH]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut_slice())
+            .map_err(kdf_error)?;
+        Ok(DatabaseKey(key))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(Error::InvalidInput(
+                "the database is encrypted but its key parameters are missing".into(),
+            )),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let partial = path.with_extension("partial");
+        fs::write(&partial, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+}
+
+fn kdf_error(e: argon2::Error) -> Error {
+    Error::InvalidInput(format!("key derivation failed: {e}"))
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn check_passphrase(passphrase: &str) -> Result<()> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_CHARS {
+        return Err(Error::InvalidInput(format!(
+            "the passphrase needs at least {MIN_PASSPHRASE_CHARS} characters"
+        )));
+    }
+    Ok(())
+}
+
+/// Whether the file at `path` is encrypted. Missing and empty files are not.
+pub fn is_encrypted(path: &Path) -> Result<bool> {
+    let mut header = [0u8; SQLITE_HEADER.len()];
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    let mut read = 0;
+    while read < header.len() {
+        match file.read(&mut header[read..])? {
+            0 => return Ok(false),
+            n => read += n,
+        }
+    }
+    Ok(&header != SQLITE_HEADER)
+}
+
+/// Keys a freshly opened connection; must run before anything reads the file.
+/// A wrong key surfaces here as [`Error::WrongPassphrase`].
+pub fn apply_key(conn: &Connection, key: Option<&DatabaseKey>) -> Result<()> {
+    let Some(key) = key else {
+        return Ok(());
+    };
+    conn.pragma_update(None, "key", &*key.pragma_value())?;
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+        .map_err(|e| match e.sqlite_error_code() {
+            Some(ErrorCode::NotADatabase) => Error::WrongPassphrase,
+            _ => e.into(),
+        })
+}
+
+/// Changes the key of an encrypted database in place.
+pub fn rekey(conn: &Connection, key: &DatabaseKey) -> Result<()> {
+    conn.pragma_update(None, "rekey", &*key.pragma_value())?;
+    Ok(())
+}
+
+/// Rewrites the closed database file at `path` from key `from` to key `to`,
+/// where `None` is plaintext. The new file replaces the old one only once
+/// it is complete.
+pub fn rewrite_file(
+    path: &Path,
+    from: Option<&DatabaseKey>,
+    to: Option<&DatabaseKey>,
+) -> Result<()> {
+    let conn = Connection::open(path)?;
+    apply_key(&conn, from)?;
+    if let (Some(_), Some(to)) = (from, to) {
+        return rekey(&conn, to);
+    }
+    let partial = export_copy(&conn, path, to)?;
+    if let Err((_, e)) = conn.close() {
+        let _ = fs::remove_file(&partial);
+        return Err(e.into());
+    }
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// Copies the database open on `conn` next to `path` under key `to` and
+/// returns the copy's path. The copy is only kept if it opens with `to`;
+/// `conn` itself is left as it was either way.
+pub fn export_copy(conn: &Connection, path: &Path, to: Option<&DatabaseKey>) -> Result<PathBuf> {
+    let partial = path.with_extension("rekey");
+    let _ = fs::remove_file(&partial);
+    let copied = export_into(conn, &partial, to).and_then(|()| {
+        let copy = Connection::open(&partial)?;
+        apply_key(&copy, to)?;
+        copy.close().map_err(|(_, e)| Error::from(e))
+    });
+    if let Err(e) = copied {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    Ok(partial)
+}
+
+fn export_into(conn: &Connection, partial: &Path, to: Option<&DatabaseKey>) -> Result<()> {
+    let target_key = to.map(DatabaseKey::pragma_value).unwrap_or_default();
+    conn.execute(
+        "ATTACH DATABASE ?1 AS rekeyed KEY ?2",
+        (partial.to_string_lossy(), &*target_key),
+    )?;
+    // Rows are copied table by table, so children may land before parents
+    let foreign_keys: bool = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+    conn.pragma_update(None, "foreign_keys", false)?;
+    let exported = conn
+        .query_row("SELECT sqlcipher_export('rekeyed')", [], |_| Ok(()))
+        .and_then(|()| {
+            // sqlcipher_export copies schema and rows but not the header fields
+            let user_version: i64 =
+                conn.query_row("PRAGMA main.user_version", [], |row| row.get(0))?;
+            conn.pragma_update(Some("rekeyed"), "user_version", user_version)
+        });
+    // Leave a live connection as we found it, whether or not the export worked
+    conn.pragma_update(None, "foreign_keys", foreign_keys)?;
+    conn.execute_batch("DETACH DATABASE rekeyed")?;
+    exported.map_err(Error::from)
+}
+
+/// Moves every snapshot in `dir` from key `from` to key `to`. Snapshots that
+/// cannot be opened with `from` (e.g. left over from an interrupted rekey)
+/// are left alone and returned.
+fn rekey_snapshots(
+    dir: &Path,
+    from: Option<&DatabaseKey>,
+    to: Option<&DatabaseKey>,
+) -> Result<Vec<String>> {
+    let mut skipped = Vec::new();
+    for backup in auto_backup::list(dir)? {
+        if let Err(e) = rewrite_file(&backup.path, from, to) {
+            eprintln!("Could not rekey snapshot {}: {e}", backup.file_name);
+            skipped.push(backup.file_name);
+        }
+    }
+    Ok(skipped)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionStatus {
+    pub enabled: bool,
+    /// `false` while an encrypted database waits for `unlock_database`.
+    pub unlocked: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionChange {
+    pub enabled: bool,
+    /// Snapshots that could not be rekeyed and still use the previous key.
+    pub skipped_backups: Vec<String>,
+}
+
+fn kdf_path(db_path: &Path) -> PathBuf {
+    db_path.with_file_name(KDF_FILE_NAME)
+}
+
+fn db_path<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf> {
+    storage::db_path(app).map_err(|e| Error::Io(std::io::Error::other(e.to_string())))
+}
+
+// The live database must be file-backed to be encrypted
+fn file_path(db: &Database) -> Result<PathBuf> {
+    db.path()
+        .map(Path::to_path_buf)
+        .ok_or_else(|| Error::InvalidInput("in-memory databases cannot be encrypted".into()))
+}
+
+// Re-derives the key from `passphrase` and checks it against the open database
+fn verify(db: &Database, passphrase: &str) -> Result<DatabaseKey> {
+    let current = db
+        .key()
+        .ok_or_else(|| Error::InvalidInput("the database is not encrypted".into()))?;
+    let key = KeyDerivation::load(&kdf_path(&file_path(db)?))?.derive(passphrase)?;
+    if key != current {
+        return Err(Error::WrongPassphrase);
+    }
+    Ok(key)
+}
+
+/// Encrypts the open database and its snapshots under `passphrase`.
+pub fn enable(
+    db: &Database,
+    backups: &Path,
+    kdf: &KeyDerivation,
+    passphrase: &str,
+) -> Result<EncryptionChange> {
+    check_passphrase(passphrase)?;
+    if db.key().is_some() {
+        return Err(Error::InvalidInput(
+            "the database is already encrypted".into(),
+        ));
+    }
+    let key = kdf.derive(passphrase)?;
+    // Parameters first: until the database is rewritten they are simply unused
+    kdf.save(&kdf_path(&file_path(db)?))?;
+    db.set_key(Some(key.clone()))?;
+    Ok(EncryptionChange {
+        enabled: true,
+        skipped_backups: rekey_snapshots(backups, None, Some(&key))?,
+    })
+}
+
+/// Rekeys the open database and its snapshots from `current` to `new`.
+/// The salt stays, so the stored parameters never disagree with the file.
+pub fn change(db: &Database, backups: &Path, current: &str, new: &str) -> Result<EncryptionChange> {
+    check_passphrase(new)?;
+    let old_key = verify(db, current)?;
+    let key = KeyDerivation::load(&kdf_path(&file_path(db)?))?.derive(new)?;
+    db.set_key(Some(key.clone()))?;
+    Ok(EncryptionChange {
+        enabled: true,
+        skipped_backups: rekey_snapshots(backups, Some(&old_key), Some(&key))?,
+    })
+}
+
+/// Decrypts the open database and its snapshots.
+pub fn disable(db: &Database, backups: &Path, passphrase: &str) -> Result<EncryptionChange> {
+    let old_key = verify(db, passphrase)?;
+    db.set_key(None)?;
+    let skipped_backups = rekey_snapshots(backups, Some(&old_key), None)?;
+    let _ = fs::remove_file(kdf_path(&file_path(db)?));
+    Ok(EncryptionChange {
+        enabled: false,
+        skipped_backups,
+    })
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn get_encryption_status<R: Runtime>(app: AppHandle<R>) -> Result<EncryptionStatus> {
+    Ok(EncryptionStatus {
+        enabled: is_encrypted(&db_path(&app)?)?,
+        unlocked: app.try_state::<Database>().is_some(),
+    })
+}
+
+/// Opens an encrypted database and starts the services that were waiting on it.
+#[tauri::command]
+pub fn unlock_database<R: Runtime>(app: AppHandle<R>, passphrase: String) -> Result<()> {
+    let passphrase = Zeroizing::new(passphrase);
+    if app.try_state::<Database>().is_some() {
+        return Ok(());
+    }
+    let path = db_path(&app)?;
+    let key = KeyDerivation::load(&kdf_path(&path))?.derive(&passphrase)?;
+    app.manage(Database::open_with_key(&path, Some(key))?);
+    crate::start_services(&app).map_err(|e| Error::Io(std::io::Error::other(e.to_string())))?;
+    let _ = app.emit(UNLOCKED_EVENT, ());
+    Ok(())
+}
+
+#[tauri::command]
+pub fn enable_encryption<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    passphrase: String,
+) -> Result<EncryptionChange> {
+    let passphrase = Zeroizing::new(passphrase);
+    enable(
+        &db,
+        &auto_backup::dir(&app)?,
+        &KeyDerivation::generate(),
+        &passphrase,
+    )
+}
+
+#[tauri::command]
+pub fn change_passphrase<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    current: String,
+    new: String,
+) -> Result<EncryptionChange> {
+    let (current, new) = (Zeroizing::new(current), Zeroizing::new(new));
+    change(&db, &auto_backup::dir(&app)?, &current, &new)
+}
+
+#[tauri::command]
+pub fn disable_encryption<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    passphrase: String,
+) -> Result<EncryptionChange> {
+    let passphrase = Zeroizing::new(passphrase);
+    disable(&db, &auto_backup::dir(&app)?, &passphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendars::{self, NewCalendar};
+    use crate::storage::{new_id, now_millis};
+
+    // Far below production cost so the test stays fast
+    fn cheap_kdf() -> KeyDerivation {
+        KeyDerivation {
+            memory_kib: 64,
+            iterations: 1,
+            ..KeyDerivation::generate()
+        }
+    }
+
+    fn names(db: &Database) -> Vec<String> {
+        calendars::list(&db.conn())
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect()
+    }
+
+    #[test]
+    fn enables_rekeys_and_disables_with_snapshots() {
+        let dir = std::env::temp_dir().join(format!("habistat-encryption-{}", new_id()));
+        let path = dir.join(storage::DB_FILE_NAME);
+        let backups = dir.join("backups");
+        let db = Database::open(&path).unwrap();
+        calendars::create(
+            &db.conn(),
+            NewCalendar {
+                name: "Sobriety".into(),
+                color_theme: "blue".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap();
+        let plain = auto_backup::snapshot(&db.conn(), None, &backups, now_millis()).unwrap();
+
+        assert!(matches!(
+            enable(&db, &backups, &cheap_kdf(), "short"),
+            Err(Error::InvalidInput(_))
+        ));
+        let enabled = enable(&db, &backups, &cheap_kdf(), "correct horse").unwrap();
+        assert!(enabled.skipped_backups.is_empty());
+        assert!(is_encrypted(&path).unwrap());
+        assert!(is_encrypted(&plain.path).unwrap());
+        // The open connection keeps working after the file was swapped
+        assert_eq!(names(&db), vec!["Sobriety"]);
+
+        let snapshot =
+            auto_backup::snapshot(&db.conn(), db.key().as_ref(), &backups, now_millis() + 1)
+                .unwrap();
+        assert!(is_encrypted(&snapshot.path).unwrap());
+
+        assert!(matches!(
+            change(&db, &backups, "wrong horse", "battery staple"),
+            Err(Error::WrongPassphrase)
+        ));
+        change(&db, &backups, "correct horse", "battery staple").unwrap();
+        let key = KeyDerivation::load(&kdf_path(&path))
+            .unwrap()
+            .derive("battery staple")
+            .unwrap();
+        {
+            let mut conn = db.conn();
+            let key = db.key();
+            auto_backup::restore(&mut conn, key.as_ref(), &backups, &plain.file_name).unwrap();
+        }
+        drop(db);
+
+        let old = KeyDerivation::load(&kdf_path(&path))
+            .unwrap()
+            .derive("correct horse")
+            .unwrap();
+        assert!(matches!(
+            Database::open_with_key(&path, Some(old)),
+            Err(Error::WrongPassphrase)
+        ));
+        let db = Database::open_with_key(&path, Some(key)).unwrap();
+        assert_eq!(names(&db), vec!["Sobriety"]);
+
+        disable(&db, &backups, "battery staple").unwrap();
+        assert!(!is_encrypted(&path).unwrap());
+        assert!(!kdf_path(&path).exists());
+        assert!(auto_backup::list(&backups)
+            .unwrap()
+            .iter()
+            .all(|backup| !is_encrypted(&backup.path).unwrap()));
+        drop(db);
+        assert_eq!(names(&Database::open(&path).unwrap()), vec!["Sobriety"]);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn failed_rewrite_keeps_the_file_open() {
+        let dir = std::env::temp_dir().join(format!("habistat-encryption-{}", new_id()));
+        let path = dir.join(storage::DB_FILE_NAME);
+        let db = Database::open(&path).unwrap();
+        // A directory where the rewrite wants its partial file makes it fail
+        fs::create_dir_all(path.with_extension("rekey").join("blocked")).unwrap();
+
+        let key = cheap_kdf().derive("correct horse").unwrap();
+        assert!(db.set_key(Some(key)).is_err());
+        assert!(db.key().is_none());
+        assert!(!is_encrypted(&path).unwrap());
+        // Still the file, not a placeholder connection
+        calendars::create(
+            &db.conn(),
+            NewCalendar {
+                name: "Sobriety".into(),
+                color_theme: "blue".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap();
+        drop(db);
+        assert_eq!(names(&Database::open(&path).unwrap()), vec!["Sobriety"]);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        assert_eq!(to_hex(&[0, 15, 255]), "000fff");
+        assert_eq!(from_hex("000fff"), Some(vec![0, 15, 255]));
+        assert_eq!(from_hex("0g"), None);
+    }
+}
@@ -0,0 +1,65 @@
+// Shared error type for the native (Rust) side of Habistat
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+/// Errors raised by the native storage layer and returned by commands.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("csv error: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("credential store error: {0}")]
+    Keyring(#[from] keyring::Error),
+    #[error("database was created by a newer version of Habistat (unknown migration `{0}`)")]
+    SchemaTooNew(String),
+    #[error("{entity} `{id}` not found")]
+    NotFound { entity: &'static str, id: String },
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+    #[error("wrong passphrase")]
+    WrongPassphrase,
+    #[error("sync failed: {0}")]
+    Sync(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn not_found(entity: &'static str, id: impl Into<String>) -> Self {
+        Error::NotFound {
+            entity,
+            id: id.into(),
+        }
+    }
+
+    /// Stable, machine-readable identifier the webview can switch on.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Database(_) => "database",
+            Error::Io(_) => "io",
+            Error::Serialization(_) => "serialization",
+            Error::Csv(_) => "csv",
+            Error::Keyring(_) => "credentialStore",
+            Error::SchemaTooNew(_) => "schemaTooNew",
+            Error::NotFound { .. } => "notFound",
+            Error::InvalidInput(_) => "invalidInput",
+            Error::WrongPassphrase => "wrongPassphrase",
+            Error::Sync(_) => "sync",
+        }
+    }
+}
+
+// Commands reject with `{ kind, message }` instead of a bare string
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Error", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
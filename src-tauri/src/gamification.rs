@@ -0,0 +1,316 @@
+// Points and level calculation, ported from `src/lib/stores/gamification.ts`.
+//
+// Habit points are folded into a per-day ledger that is cached between calls
+// and only extended with completions inserted since the last call; it is
+// rebuilt from scratch when `Database::rewrites` reports a habit change or a
+// completion updated or deleted, whoever made it (commands, sync, backup
+// restores). Activity points are derived from `activityHistory` day markers.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Mutex;
+
+use chrono::{Duration, NaiveDate};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use tauri::State;
+
+use crate::days;
+use crate::error::Result;
+use crate::habits::HabitType;
+use crate::storage::Database;
+
+pub const POINTS_FOR_ACTIVE_DAY: i64 = 15;
+pub const POINTS_FOR_INACTIVE_DAY: i64 = -10;
+pub const POINTS_PER_LEVEL: i64 = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GamificationState {
+    pub total_points: i64,
+    pub weekly_points_delta: i64,
+    pub level: i64,
+}
+
+/// Computes points from per-day habit points and the days the app was opened.
+///
+/// Matches the webview: "this week" is the last 7 days plus today, "last week"
+/// the 7 days before that. Without a `tracking_start` no activity points are given.
+pub fn compute(
+    habit_points_by_day: &BTreeMap<NaiveDate, i64>,
+    active_days: &BTreeSet<NaiveDate>,
+    tracking_start: Option<NaiveDate>,
+    today: NaiveDate,
+) -> GamificationState {
+    let this_week_start = today - Duration::days(7);
+    let last_week_start = today - Duration::days(14);
+
+    let mut total = 0;
+    let mut this_week = 0;
+    let mut last_week = 0;
+    let mut add = |day: NaiveDate, points: i64| {
+        total += points;
+        if day >= this_week_start {
+            this_week += points;
+        } else if day >= last_week_start {
+            last_week += points;
+        }
+    };
+
+    for (&day, &points) in habit_points_by_day {
+        add(day, points);
+    }
+
+    if let Some(start) = tracking_start {
+        for day in start.iter_days().take_while(|day| *day <= today) {
+            // Today always counts as active: the app is open right now
+            let points = if day == today || active_days.contains(&day) {
+                POINTS_FOR_ACTIVE_DAY
+            } else {
+                POINTS_FOR_INACTIVE_DAY
+            };
+            add(day, points);
+        }
+    }
+
+    GamificationState {
+        total_points: total,
+        weekly_points_delta: this_week - last_week,
+        level: total.div_euclid(POINTS_PER_LEVEL).max(1),
+    }
+}
+
+/// Points a single completion is worth: `pointsValue`, negated for negative habits.
+pub fn completion_points(habit_type: HabitType, points_value: i64) -> i64 {
+    match habit_type {
+        HabitType::Positive => points_value,
+        HabitType::Negative => -points_value,
+    }
+}
+
+/// Cached habit-points ledger, registered as managed state in `run()`.
+#[derive(Default)]
+pub struct GamificationCache(Mutex<Option<Ledger>>);
+
+#[derive(Debug)]
+struct Ledger {
+    /// `Database::rewrites()` the ledger was built at.
+    rewrites: u64,
+    points_by_habit: HashMap<String, i64>,
+    points_by_day: BTreeMap<NaiveDate, i64>,
+    /// Highest completions rowid folded in so far.
+    last_rowid: i64,
+}
+
+impl Ledger {
+    fn build(conn: &Connection, rewrites: u64) -> Result<Self> {
+        let mut ledger = Ledger {
+            rewrites,
+            points_by_habit: HashMap::new(),
+            points_by_day: BTreeMap::new(),
+            last_rowid: 0,
+        };
+
+        let mut stmt = conn.prepare("SELECT id, type, COALESCE(pointsValue, 0) FROM habits")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let points = completion_points(row.get(1)?, row.get(2)?);
+            ledger.points_by_habit.insert(row.get(0)?, points);
+        }
+
+        ledger.apply_new_completions(conn)?;
+        Ok(ledger)
+    }
+
+    // Folds completions inserted after `last_rowid` into the ledger
+    fn apply_new_completions(&mut self, conn: &Connection) -> Result<()> {
+        let mut stmt = conn.prepare(
+            "SELECT rowid, habitId, completedAt FROM completions
+             WHERE rowid > ?1 ORDER BY rowid",
+        )?;
+        let mut rows = stmt.query(params![self.last_rowid])?;
+        while let Some(row) = rows.next()? {
+            let habit_id: String = row.get(1)?;
+            self.last_rowid = row.get(0)?;
+
+            // Completions of habits that no longer exist are worth nothing
+            if let Some(points) = self.points_by_habit.get(&habit_id) {
+                *self
+                    .points_by_day
+                    .entry(days::local_date_of(row.get(2)?))
+                    .or_default() += points;
+            }
+        }
+        Ok(())
+    }
+
+    /// Brings the ledger up to date, or returns `false` if it must be rebuilt.
+    fn refresh(&mut self, conn: &Connection, rewrites: u64) -> Result<bool> {
+        if rewrites != self.rewrites {
+            return Ok(false);
+        }
+        // Rowids only go back when the table was emptied, which SQLite may do
+        // without telling the update hook
+        let max_rowid: i64 = conn.query_row(
+            "SELECT COALESCE(MAX(rowid), 0) FROM completions",
+            [],
+            |row| row.get(0),
+        )?;
+        if max_rowid < self.last_rowid {
+            return Ok(false);
+        }
+        self.apply_new_completions(conn)?;
+        Ok(true)
+    }
+}
+
+// Days the app was opened, plus the first-open day used as the activity baseline
+fn activity(conn: &Connection) -> Result<(BTreeSet<NaiveDate>, Option<NaiveDate>)> {
+    let mut stmt = conn.prepare("SELECT DISTINCT date FROM activityHistory")?;
+    let active_days: BTreeSet<NaiveDate> = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .filter_map(|date| date.ok()?.parse().ok())
+        .collect();
+
+    let first_open: Option<i64> =
+        conn.query_row("SELECT MIN(firstAppOpenAt) FROM userProfile", [], |row| {
+            row.get(0)
+        })?;
+    let tracking_start = first_open
+        .map(days::local_date_of)
+        .or_else(|| active_days.first().copied());
+    Ok((active_days, tracking_start))
+}
+
+impl GamificationCache {
+    /// Current state as of `today`, reusing the cached ledger where possible.
+    pub fn state(&self, db: &Database, today: NaiveDate) -> Result<GamificationState> {
+        // Holding the connection keeps the counter still while we read
+        let conn = db.conn();
+        let rewrites = db.rewrites();
+        let mut cached = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let up_to_date = match cached.as_mut() {
+            Some(ledger) => ledger.refresh(&conn, rewrites)?,
+            None => false,
+        };
+        if !up_to_date {
+            *cached = Some(Ledger::build(&conn, rewrites)?);
+        }
+        let ledger = cached.as_ref().expect("ledger was just built");
+
+        let (active_days, tracking_start) = activity(&conn)?;
+        Ok(compute(
+            &ledger.points_by_day,
+            &active_days,
+            tracking_start,
+            today,
+        ))
+    }
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn get_gamification_state(
+    db: State<'_, Database>,
+    cache: State<'_, GamificationCache>,
+) -> Result<GamificationState> {
+    cache.state(&db, days::today())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendars::{self, NewCalendar};
+    use crate::habits::{self, NewHabit};
+    use crate::{completions, settings, storage};
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn counts_habit_and_activity_points_per_week() {
+        let today = date("2024-03-20");
+        let habit_points = BTreeMap::from([
+            (date("2024-03-19"), 10), // this week
+            (date("2024-03-10"), -5), // last week
+            (date("2024-01-01"), 7),  // before both windows
+        ]);
+        // Opened on the 18th and 19th, tracked since the 17th; today is implicitly active
+        let active = BTreeSet::from([date("2024-03-18"), date("2024-03-19")]);
+
+        let state = compute(&habit_points, &active, Some(date("2024-03-17")), today);
+
+        let activity = POINTS_FOR_INACTIVE_DAY + 3 * POINTS_FOR_ACTIVE_DAY;
+        assert_eq!(state.total_points, 12 + activity);
+        assert_eq!(state.weekly_points_delta, 10 + activity - (-5));
+        assert_eq!(state.level, 1);
+    }
+
+    #[test]
+    fn level_is_never_below_one() {
+        let habit_points = BTreeMap::from([(date("2024-03-01"), -2500)]);
+        let state = compute(&habit_points, &BTreeSet::new(), None, date("2024-03-20"));
+        assert_eq!(state.level, 1);
+
+        let habit_points = BTreeMap::from([(date("2024-03-01"), 2999)]);
+        let state = compute(&habit_points, &BTreeSet::new(), None, date("2024-03-20"));
+        assert_eq!(state.level, 2);
+    }
+
+    #[test]
+    fn cached_ledger_tracks_every_row_change() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let calendar = calendars::create(
+            &conn,
+            NewCalendar {
+                name: "Health".into(),
+                color_theme: "green".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap();
+        let habit = habits::create(
+            &conn,
+            NewHabit {
+                habit_type: HabitType::Negative,
+                points_value: 20,
+                ..NewHabit::named(&calendar.id, "Smoke")
+            },
+        )
+        .unwrap();
+        drop(conn);
+
+        let cache = GamificationCache::default();
+        let today = days::today();
+        assert_eq!(cache.state(&db, today).unwrap().total_points, 0);
+
+        completions::log(&db.conn(), &habit.id, None, None).unwrap();
+        completions::log(&db.conn(), &habit.id, None, None).unwrap();
+        assert_eq!(cache.state(&db, today).unwrap().total_points, -40);
+
+        completions::undo_last(&db.conn(), &habit.id, today).unwrap();
+        assert_eq!(cache.state(&db, today).unwrap().total_points, -20);
+
+        // A rewrite that leaves updatedAt behind (e.g. pulled by sync) still counts
+        db.conn()
+            .execute(
+                "UPDATE habits SET pointsValue = 50, updatedAt = 0 WHERE id = ?1",
+                [&habit.id],
+            )
+            .unwrap();
+        assert_eq!(cache.state(&db, today).unwrap().total_points, -50);
+
+        // Unrelated writes and new completions extend the ledger in place; a
+        // rebuild would drop this marker
+        let marker = date("2000-01-01");
+        if let Some(ledger) = cache.0.lock().unwrap().as_mut() {
+            ledger.points_by_day.insert(marker, 1000);
+        }
+        settings::set(&db.conn(), "unrelated", &true).unwrap();
+        completions::log(&db.conn(), &habit.id, None, None).unwrap();
+        assert_eq!(cache.state(&db, today).unwrap().total_points, 1000 - 100);
+    }
+}
@@ -0,0 +1,526 @@
+// Importer for Habitica data exports.
+//
+// Habitica's "Export data" JSON groups tasks as `tasks.habits`/`tasks.dailys`;
+// the API returns them as one array tagged with `type`. Both are accepted.
+// Habits scored up become positive habits and habits scored down negative
+// ones (a habit with both buttons becomes one of each). Dailies become
+// positive habits with their repeat schedule. To-dos and rewards have no
+// Habistat equivalent and are skipped.
+//
+// Every id is derived from the Habitica task id (see `storage::derived_id`),
+// so importing a newer export of the same account only adds what is new.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Duration};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::State;
+
+use crate::calendars::{self, NewCalendar};
+use crate::completions::{self, Completion};
+use crate::days;
+use crate::error::{Error, Result};
+use crate::habits::{self, Habit, HabitType, NewHabit};
+use crate::loop_import::ImportNote;
+use crate::recurrence::{Recurrence, RecurrenceInput};
+use crate::storage::{derived_id, now_millis, Database};
+
+pub const CALENDAR_NAME: &str = "Habitica";
+const CALENDAR_COLOR: &str = "violet";
+
+// Habitica's `repeat` keys, indexed by weekday (0 = Sunday)
+const WEEKDAY_KEYS: [&str; 7] = ["su", "m", "t", "w", "th", "f", "s"];
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HabiticaImportSummary {
+    pub calendar_id: String,
+    pub habits_created: usize,
+    /// Habits found from an earlier import; their new history is still added.
+    pub habits_existing: usize,
+    pub completions_created: usize,
+    pub completions_existing: usize,
+    pub skipped: Vec<ImportNote>,
+    pub approximated: Vec<ImportNote>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Task {
+    #[serde(alias = "_id")]
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+    notes: Option<String>,
+    #[serde(default)]
+    up: bool,
+    #[serde(default)]
+    down: bool,
+    frequency: Option<String>,
+    every_x: Option<u32>,
+    #[serde(default)]
+    repeat: BTreeMap<String, bool>,
+    #[serde(default)]
+    days_of_month: Vec<u32>,
+    #[serde(default)]
+    history: Vec<HistoryEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HistoryEntry {
+    date: Value,
+    value: Option<f64>,
+    scored_up: Option<u32>,
+    scored_down: Option<u32>,
+    completed: Option<bool>,
+}
+
+impl HistoryEntry {
+    /// `date` is epoch milliseconds, sometimes as a string, or an ISO timestamp.
+    fn millis(&self) -> Option<i64> {
+        match &self.date {
+            Value::Number(number) => number.as_f64().map(|ms| ms as i64),
+            Value::String(text) => text.parse::<i64>().ok().or_else(|| {
+                DateTime::parse_from_rfc3339(text)
+                    .ok()
+                    .map(|at| at.timestamp_millis())
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Which side of a Habitica habit a Habistat habit tracks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Up,
+    Down,
+    Daily,
+}
+
+/// Parses a Habitica export into its tasks, plus a note for every task that
+/// could not be read.
+fn tasks(export: &Value) -> Result<(Vec<Task>, Vec<ImportNote>)> {
+    let tasks = match export {
+        Value::Array(tasks) => tasks.clone(),
+        Value::Object(export) => match export.get("tasks") {
+            Some(Value::Array(tasks)) => tasks.clone(),
+            Some(Value::Object(groups)) => groups
+                .values()
+                .filter_map(Value::as_array)
+                .flatten()
+                .cloned()
+                .collect(),
+            _ => return Err(not_an_export()),
+        },
+        _ => return Err(not_an_export()),
+    };
+    let mut parsed = Vec::with_capacity(tasks.len());
+    let mut unreadable = Vec::new();
+    for task in tasks {
+        let name = task
+            .get("text")
+            .and_then(Value::as_str)
+            .filter(|text| !text.trim().is_empty())
+            .unwrap_or("(unnamed task)")
+            .to_string();
+        match serde_json::from_value::<Task>(task) {
+            Ok(task) => parsed.push(task),
+            Err(e) => unreadable.push(ImportNote {
+                habit: name,
+                message: format!("task could not be read: {e}"),
+            }),
+        }
+    }
+    // Habits before dailies, whatever order the export lists them in
+    parsed.sort_by_key(|task| match task.kind.as_str() {
+        "habit" => 0,
+        "daily" => 1,
+        _ => 2,
+    });
+    Ok((parsed, unreadable))
+}
+
+fn not_an_export() -> Error {
+    Error::InvalidInput("file is not a Habitica data export".into())
+}
+
+/// Imports the habits and dailies of a Habitica export.
+pub fn import(conn: &mut Connection, export: &Value) -> Result<HabiticaImportSummary> {
+    let (tasks, unreadable) = tasks(export)?;
+    let tx = conn.transaction()?;
+    let calendar_id = habitica_calendar(&tx)?;
+    let mut summary = HabiticaImportSummary {
+        calendar_id: calendar_id.clone(),
+        skipped: unreadable,
+        ..HabiticaImportSummary::default()
+    };
+
+    for task in &tasks {
+        let note = |message: &str| ImportNote {
+            habit: task.text.clone(),
+            message: message.into(),
+        };
+        let sides: Vec<Side> = match task.kind.as_str() {
+            "habit" => [(task.up, Side::Up), (task.down, Side::Down)]
+                .into_iter()
+                .filter_map(|(enabled, side)| enabled.then_some(side))
+                .collect(),
+            "daily" => vec![Side::Daily],
+            "todo" | "reward" => continue,
+            _ => {
+                summary.skipped.push(note("unknown task type"));
+                continue;
+            }
+        };
+        if task.text.trim().is_empty() {
+            summary.skipped.push(note("task has no name"));
+            continue;
+        }
+        if sides.is_empty() {
+            summary
+                .skipped
+                .push(note("habit has neither + nor - enabled"));
+            continue;
+        }
+
+        let (recurrence, exact) = recurrence(task);
+        if !exact {
+            summary.approximated.push(note(&format!(
+                "{} schedule imported as {}",
+                task.frequency.as_deref().unwrap_or("custom"),
+                recurrence.describe()
+            )));
+        }
+        if task.history.iter().any(|entry| {
+            entry.scored_up.is_none() && entry.scored_down.is_none() && entry.completed.is_none()
+        }) {
+            summary
+                .approximated
+                .push(note("older history entries were read from score changes"));
+        }
+
+        for &side in &sides {
+            let name = match (side, sides.len()) {
+                (Side::Up, 2) => format!("{} (+)", task.text.trim()),
+                (Side::Down, 2) => format!("{} (−)", task.text.trim()),
+                _ => task.text.trim().to_string(),
+            };
+            let local_uuid = derived_id(&format!("habitica:{}:{side:?}", task.id));
+            let habit = match find_by_local_uuid(&tx, &local_uuid)? {
+                Some(habit) => {
+                    summary.habits_existing += 1;
+                    habit
+                }
+                None => {
+                    summary.habits_created += 1;
+                    create_habit(
+                        &tx,
+                        &calendar_id,
+                        task,
+                        side,
+                        name,
+                        &recurrence,
+                        &local_uuid,
+                    )?
+                }
+            };
+            for completion in completions_for(task, side, &habit) {
+                if completions::exists(&tx, &completion.id)? {
+                    summary.completions_existing += 1;
+                } else {
+                    completions::insert(&tx, &completion)?;
+                    summary.completions_created += 1;
+                }
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(summary)
+}
+
+fn create_habit(
+    conn: &Connection,
+    calendar_id: &str,
+    task: &Task,
+    side: Side,
+    name: String,
+    recurrence: &Recurrence,
+    local_uuid: &str,
+) -> Result<Habit> {
+    let habit = habits::create(
+        conn,
+        NewHabit {
+            calendar_id: calendar_id.to_string(),
+            name,
+            description: task.notes.clone().filter(|n| !n.trim().is_empty()),
+            habit_type: match side {
+                Side::Down => HabitType::Negative,
+                Side::Up | Side::Daily => HabitType::Positive,
+            },
+            timer_enabled: false,
+            target_duration_seconds: None,
+            points_value: 0,
+            user_id: None,
+            position: None,
+            recurrence: Some(RecurrenceInput::Model(recurrence.clone())),
+            target_quantity: None,
+            unit: None,
+        },
+    )?;
+    conn.execute(
+        "UPDATE habits SET localUuid = ?2 WHERE id = ?1",
+        [&habit.id, local_uuid],
+    )?;
+    habits::get(conn, &habit.id)
+}
+
+fn find_by_local_uuid(conn: &Connection, local_uuid: &str) -> Result<Option<Habit>> {
+    let id: Option<String> = conn
+        .query_row(
+            "SELECT id FROM habits WHERE localUuid = ?1",
+            [local_uuid],
+            |row| row.get(0),
+        )
+        .optional()?;
+    id.map(|id| habits::get(conn, &id)).transpose()
+}
+
+/// The calendar imported habits go to, created on first use.
+fn habitica_calendar(conn: &Connection) -> Result<String> {
+    let local_uuid = derived_id("habitica:calendar");
+    let existing = conn
+        .query_row(
+            "SELECT id FROM calendars WHERE localUuid = ?1",
+            [&local_uuid],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+    let calendar = calendars::create(
+        conn,
+        NewCalendar {
+            name: CALENDAR_NAME.into(),
+            color_theme: CALENDAR_COLOR.into(),
+            user_id: None,
+            position: None,
+        },
+    )?;
+    conn.execute(
+        "UPDATE calendars SET localUuid = ?2 WHERE id = ?1",
+        [&calendar.id, &local_uuid],
+    )?;
+    Ok(calendar.id)
+}
+
+/// Maps a daily's schedule onto a [`Recurrence`], and whether the mapping is
+/// exact. Habitica habits have no schedule.
+fn recurrence(task: &Task) -> (Recurrence, bool) {
+    if task.kind != "daily" {
+        return (Recurrence::Daily, true);
+    }
+    let every = task.every_x.unwrap_or(1).max(1);
+    match task.frequency.as_deref().unwrap_or("weekly") {
+        "daily" if every == 1 => (Recurrence::Daily, true),
+        "daily" => (Recurrence::EveryNDays { interval: every }, true),
+        "weekly" => {
+            let days: Vec<u8> = (0u8..7)
+                .filter(|&day| task.repeat.get(WEEKDAY_KEYS[day as usize]) == Some(&true))
+                .collect();
+            match days.len() {
+                0 => (Recurrence::Daily, false),
+                7 => (Recurrence::Daily, every == 1),
+                _ => (Recurrence::Weekdays { days }, every == 1),
+            }
+        }
+        "monthly" => (
+            Recurrence::TimesPerMonth {
+                count: (task.days_of_month.len() as u32).clamp(1, 28),
+            },
+            false,
+        ),
+        "yearly" => (
+            Recurrence::EveryNDays {
+                interval: 365 * every,
+            },
+            false,
+        ),
+        _ => (Recurrence::Daily, false),
+    }
+}
+
+/// Completions recorded in a task's history for one side. Ids are derived
+/// from the task, side and entry so repeated imports produce the same rows.
+fn completions_for(task: &Task, side: Side, habit: &Habit) -> Vec<Completion> {
+    let now = now_millis();
+    let mut seen = HashSet::new();
+    let mut previous_value = 0.0;
+    let mut completions = Vec::new();
+    for entry in &task.history {
+        let value = entry.value.unwrap_or(previous_value);
+        let delta = value - previous_value;
+        previous_value = value;
+        let Some(millis) = entry.millis() else {
+            continue;
+        };
+
+        let (count, completed_at) = match side {
+            Side::Up => (entry.scored_up.unwrap_or((delta > 0.0) as u32), millis),
+            Side::Down => (entry.scored_down.unwrap_or((delta < 0.0) as u32), millis),
+            // Dailies are logged at cron, the start of the day after the one
+            // they describe, so they land at noon of the previous day
+            Side::Daily => {
+                let day = days::local_date_of(millis) - Duration::days(1);
+                (
+                    entry.completed.unwrap_or(delta > 0.0) as u32,
+                    days::local_day_bounds(day).0 + 12 * 60 * 60 * 1000,
+                )
+            }
+        };
+        for n in 0..count {
+            let id = derived_id(&format!("habitica:{}:{side:?}:{millis}:{n}", task.id));
+            if seen.insert(id.clone()) {
+                completions.push(Completion {
+                    id,
+                    user_id: None,
+                    habit_id: habit.id.clone(),
+                    completed_at,
+                    client_updated_at: now,
+                    quantity: 1.0,
+                    unit: None,
+                    duration_seconds: None,
+                });
+            }
+        }
+    }
+    completions
+}
+
+pub fn read(path: &Path) -> Result<Value> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn import_habitica_export(
+    db: State<'_, Database>,
+    path: String,
+) -> Result<HabiticaImportSummary> {
+    let export = read(Path::new(&path))?;
+    import(&mut db.conn(), &export)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage;
+    use serde_json::json;
+
+    fn export() -> Value {
+        json!({
+            "tasks": {
+                "habits": [{
+                    "_id": "h-1", "type": "habit", "text": "Drink water", "up": true, "down": true,
+                    "history": [
+                        { "date": 1709290800000_i64, "value": 1.5, "scoredUp": 2, "scoredDown": 1 }
+                    ]
+                }],
+                "dailys": [{
+                    "id": "d-1", "type": "daily", "text": "Stretch", "frequency": "weekly",
+                    "everyX": 1,
+                    "repeat": { "su": false, "m": true, "t": false, "w": true, "th": false, "f": true, "s": false },
+                    "history": [
+                        { "date": 1709290800000_i64, "value": 1.0, "completed": true },
+                        { "date": 1709377200000_i64, "value": 0.5, "completed": false }
+                    ]
+                }],
+                "todos": [{ "id": "t-1", "type": "todo", "text": "File taxes" }]
+            }
+        })
+    }
+
+    #[test]
+    fn imports_habits_and_dailies_idempotently() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+
+        let summary = import(&mut conn, &export()).unwrap();
+        assert_eq!(summary.habits_created, 3);
+        assert_eq!(summary.completions_created, 4);
+        assert!(summary.skipped.is_empty() && summary.approximated.is_empty());
+
+        let imported = habits::list(&conn, Some(&summary.calendar_id)).unwrap();
+        let kinds: Vec<(&str, HabitType)> = imported
+            .iter()
+            .map(|h| (h.name.as_str(), h.habit_type))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("Drink water (+)", HabitType::Positive),
+                ("Drink water (−)", HabitType::Negative),
+                ("Stretch", HabitType::Positive),
+            ]
+        );
+        assert_eq!(
+            imported[2].recurrence,
+            Recurrence::Weekdays {
+                days: vec![1, 3, 5]
+            }
+        );
+        assert_eq!(imported[2].local_uuid, derived_id("habitica:d-1:Daily"));
+
+        let again = import(&mut conn, &export()).unwrap();
+        assert_eq!(again.calendar_id, summary.calendar_id);
+        assert_eq!((again.habits_created, again.habits_existing), (0, 3));
+        assert_eq!(
+            (again.completions_created, again.completions_existing),
+            (0, 4)
+        );
+    }
+
+    #[test]
+    fn rejects_unrelated_json() {
+        let db = storage::Database::open_in_memory().unwrap();
+        assert!(matches!(
+            import(&mut db.conn(), &json!("hello")),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            import(&mut db.conn(), &json!({ "name": "not habitica" })),
+            Err(Error::InvalidInput(_))
+        ));
+        // Nothing was created for the rejected files
+        assert!(calendars::list(&db.conn()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_unreadable_tasks() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let export = json!({
+            "tasks": [
+                { "id": "h-1", "type": "habit", "text": "Walk", "up": true },
+                { "id": "h-2", "type": "habit", "text": "Floss", "up": "sometimes" },
+                { "type": "daily", "text": "No id" }
+            ]
+        });
+
+        let summary = import(&mut db.conn(), &export).unwrap();
+        assert_eq!(summary.habits_created, 1);
+        let skipped: Vec<&str> = summary.skipped.iter().map(|n| n.habit.as_str()).collect();
+        assert_eq!(skipped, vec!["Floss", "No id"]);
+        assert!(summary.skipped[0]
+            .message
+            .starts_with("task could not be read"));
+    }
+}
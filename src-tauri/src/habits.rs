@@ -0,0 +1,689 @@
+// Habit CRUD on the native database.
+// Multi-row changes (moves, reorders, deletes) run in a single transaction so
+// the webview never observes half-applied positions or orphaned completions.
+
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction};
+use serde::{Deserialize, Deserializer, Serialize};
+use tauri::State;
+
+use crate::calendars;
+use crate::error::{Error, Result};
+use crate::recurrence::{Recurrence, RecurrenceInput};
+use crate::storage::{new_id, now_millis, Database};
+
+/// Positive habits are things to do, negative habits are things to avoid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HabitType {
+    Positive,
+    Negative,
+}
+
+impl HabitType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HabitType::Positive => "positive",
+            HabitType::Negative => "negative",
+        }
+    }
+}
+
+impl ToSql for HabitType {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+impl FromSql for HabitType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "positive" => Ok(HabitType::Positive),
+            "negative" => Ok(HabitType::Negative),
+            other => Err(FromSqlError::Other(
+                format!("unknown habit type `{other}`").into(),
+            )),
+        }
+    }
+}
+
+const COLUMNS: &str = "id, userId, localUuid, calendarId, name, description, type, timerEnabled, \
+     targetDurationSeconds, COALESCE(pointsValue, 0), position, isEnabled, createdAt, updatedAt, \
+     recurrence, targetQuantity, unit";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Habit {
+    pub id: String,
+    pub user_id: Option<String>,
+    pub local_uuid: String,
+    pub calendar_id: String,
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(rename = "type")]
+    pub habit_type: HabitType,
+    pub timer_enabled: bool,
+    pub target_duration_seconds: Option<i64>,
+    pub points_value: i64,
+    pub position: i64,
+    pub is_enabled: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub recurrence: Recurrence,
+    /// Daily amount aimed for, in `unit` (e.g. 8 glasses); `None` for plain habits.
+    pub target_quantity: Option<f64>,
+    /// Default unit of the habit's completions.
+    pub unit: Option<String>,
+}
+
+impl Habit {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            local_uuid: row.get(2)?,
+            calendar_id: row.get(3)?,
+            name: row.get(4)?,
+            description: row.get(5)?,
+            habit_type: row.get(6)?,
+            timer_enabled: row.get(7)?,
+            target_duration_seconds: row.get(8)?,
+            points_value: row.get(9)?,
+            position: row.get(10)?,
+            is_enabled: row.get(11)?,
+            created_at: row.get(12)?,
+            updated_at: row.get(13)?,
+            recurrence: row.get(14)?,
+            target_quantity: row.get(15)?,
+            unit: row.get(16)?,
+        })
+    }
+}
+
+/// Input for [`create`]. `position` defaults to the end of the calendar.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewHabit {
+    pub calendar_id: String,
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(rename = "type")]
+    pub habit_type: HabitType,
+    #[serde(default)]
+    pub timer_enabled: bool,
+    pub target_duration_seconds: Option<i64>,
+    #[serde(default)]
+    pub points_value: i64,
+    pub user_id: Option<String>,
+    pub position: Option<i64>,
+    /// Defaults to daily.
+    pub recurrence: Option<RecurrenceInput>,
+    pub target_quantity: Option<f64>,
+    pub unit: Option<String>,
+}
+
+#[cfg(test)]
+impl NewHabit {
+    /// A daily positive habit with every optional field left unset.
+    pub fn named(calendar_id: &str, name: &str) -> Self {
+        NewHabit {
+            calendar_id: calendar_id.into(),
+            name: name.into(),
+            description: None,
+            habit_type: HabitType::Positive,
+            timer_enabled: false,
+            target_duration_seconds: None,
+            points_value: 0,
+            user_id: None,
+            position: None,
+            recurrence: None,
+            target_quantity: None,
+            unit: None,
+        }
+    }
+}
+
+/// Fields [`update`] may change. A missing field is left untouched; for the
+/// nullable columns an explicit `null` clears the value.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HabitPatch {
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub description: Option<Option<String>>,
+    #[serde(rename = "type")]
+    pub habit_type: Option<HabitType>,
+    pub timer_enabled: Option<bool>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub target_duration_seconds: Option<Option<i64>>,
+    pub points_value: Option<i64>,
+    pub recurrence: Option<RecurrenceInput>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub target_quantity: Option<Option<f64>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub unit: Option<Option<String>>,
+}
+
+// Distinguishes `"field": null` (Some(None)) from a missing field (None)
+fn double_option<'de, D, T>(deserializer: D) -> std::result::Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+fn validate_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::InvalidInput("habit name must not be empty".into()));
+    }
+    Ok(name.to_string())
+}
+
+fn validate_numbers(
+    target_duration_seconds: Option<i64>,
+    points_value: i64,
+    target_quantity: Option<f64>,
+) -> Result<()> {
+    if matches!(target_duration_seconds, Some(seconds) if seconds <= 0) {
+        return Err(Error::InvalidInput(
+            "targetDurationSeconds must be positive".into(),
+        ));
+    }
+    if matches!(target_quantity, Some(quantity) if !(quantity.is_finite() && quantity > 0.0)) {
+        return Err(Error::InvalidInput(
+            "targetQuantity must be positive".into(),
+        ));
+    }
+    if points_value < 0 {
+        return Err(Error::InvalidInput(
+            "pointsValue must not be negative".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Trims a unit, treating a blank one as no unit.
+pub(crate) fn clean_unit(unit: Option<String>) -> Option<String> {
+    unit.map(|u| u.trim().to_string()).filter(|u| !u.is_empty())
+}
+
+/// Habits ordered by calendar position, optionally limited to one calendar.
+pub fn list(conn: &Connection, calendar_id: Option<&str>) -> Result<Vec<Habit>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {COLUMNS} FROM habits
+         WHERE ?1 IS NULL OR calendarId = ?1
+         ORDER BY calendarId, position, createdAt"
+    ))?;
+    let rows = stmt.query_map([calendar_id], Habit::from_row)?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+pub fn get(conn: &Connection, id: &str) -> Result<Habit> {
+    conn.query_row(
+        &format!("SELECT {COLUMNS} FROM habits WHERE id = ?1"),
+        [id],
+        Habit::from_row,
+    )
+    .optional()?
+    .ok_or_else(|| Error::not_found("habit", id))
+}
+
+pub fn create(conn: &Connection, input: NewHabit) -> Result<Habit> {
+    let name = validate_name(&input.name)?;
+    validate_numbers(
+        input.target_duration_seconds,
+        input.points_value,
+        input.target_quantity,
+    )?;
+    let description = input.description.filter(|d| !d.trim().is_empty());
+    let unit = clean_unit(input.unit);
+    calendars::get(conn, &input.calendar_id)?;
+
+    let recurrence = match input.recurrence {
+        Some(recurrence) => recurrence.into_recurrence()?,
+        None => Recurrence::Daily,
+    };
+
+    let position = match input.position {
+        Some(position) => position,
+        None => next_position(conn, &input.calendar_id)?,
+    };
+
+    let id = new_id();
+    let now = now_millis();
+    conn.execute(
+        "INSERT INTO habits (id, userId, localUuid, calendarId, name, description, type,
+            timerEnabled, targetDurationSeconds, pointsValue, position, isEnabled, createdAt, updatedAt,
+            recurrence, targetQuantity, unit)
+         VALUES (?1, ?2, ?1, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, 1, ?11, ?11, ?12, ?13, ?14)",
+        params![
+            id,
+            input.user_id,
+            input.calendar_id,
+            name,
+            description,
+            input.habit_type,
+            input.timer_enabled,
+            input.target_duration_seconds,
+            input.points_value,
+            position,
+            now,
+            recurrence,
+            input.target_quantity,
+            unit
+        ],
+    )?;
+    get(conn, &id)
+}
+
+pub fn update(conn: &Connection, id: &str, patch: HabitPatch) -> Result<Habit> {
+    let mut habit = get(conn, id)?;
+    if let Some(name) = patch.name {
+        habit.name = validate_name(&name)?;
+    }
+    if let Some(description) = patch.description {
+        habit.description = description.filter(|d| !d.trim().is_empty());
+    }
+    if let Some(habit_type) = patch.habit_type {
+        habit.habit_type = habit_type;
+    }
+    if let Some(timer_enabled) = patch.timer_enabled {
+        habit.timer_enabled = timer_enabled;
+    }
+    if let Some(target) = patch.target_duration_seconds {
+        habit.target_duration_seconds = target;
+    }
+    if let Some(points) = patch.points_value {
+        habit.points_value = points;
+    }
+    if let Some(recurrence) = patch.recurrence {
+        habit.recurrence = recurrence.into_recurrence()?;
+    }
+    if let Some(target) = patch.target_quantity {
+        habit.target_quantity = target;
+    }
+    if let Some(unit) = patch.unit {
+        habit.unit = clean_unit(unit);
+    }
+    validate_numbers(
+        habit.target_duration_seconds,
+        habit.points_value,
+        habit.target_quantity,
+    )?;
+    habit.updated_at = now_millis();
+
+    conn.execute(
+        "UPDATE habits SET name = ?2, description = ?3, type = ?4, timerEnabled = ?5,
+            targetDurationSeconds = ?6, pointsValue = ?7, updatedAt = ?8, recurrence = ?9,
+            targetQuantity = ?10, unit = ?11
+         WHERE id = ?1",
+        params![
+            id,
+            habit.name,
+            habit.description,
+            habit.habit_type,
+            habit.timer_enabled,
+            habit.target_duration_seconds,
+            habit.points_value,
+            habit.updated_at,
+            habit.recurrence,
+            habit.target_quantity,
+            habit.unit
+        ],
+    )?;
+    Ok(habit)
+}
+
+pub fn set_enabled(conn: &Connection, id: &str, enabled: bool) -> Result<Habit> {
+    let mut habit = get(conn, id)?;
+    habit.is_enabled = enabled;
+    habit.updated_at = now_millis();
+    conn.execute(
+        "UPDATE habits SET isEnabled = ?2, updatedAt = ?3 WHERE id = ?1",
+        params![id, enabled, habit.updated_at],
+    )?;
+    Ok(habit)
+}
+
+/// Reorders the habits of one calendar. Habits missing from `ordered_ids`
+/// keep their relative order after the listed ones.
+pub fn reorder(
+    conn: &mut Connection,
+    calendar_id: &str,
+    ordered_ids: &[String],
+) -> Result<Vec<Habit>> {
+    let tx = conn.transaction()?;
+    calendars::get(&tx, calendar_id)?;
+    let habits = resequence(&tx, calendar_id, ordered_ids, now_millis())?;
+    tx.commit()?;
+    Ok(habits)
+}
+
+/// Moves a habit into another calendar at `position` (default: the end) and
+/// closes the gap it leaves behind in the source calendar.
+pub fn move_to_calendar(
+    conn: &mut Connection,
+    id: &str,
+    calendar_id: &str,
+    position: Option<i64>,
+) -> Result<Habit> {
+    let tx = conn.transaction()?;
+    let habit = get(&tx, id)?;
+    calendars::get(&tx, calendar_id)?;
+    let now = now_millis();
+
+    // Build the destination order with the moved habit spliced in
+    let mut destination: Vec<String> = list(&tx, Some(calendar_id))?
+        .into_iter()
+        .map(|h| h.id)
+        .filter(|other| other != id)
+        .collect();
+    let index = position
+        .map(|p| p.clamp(0, destination.len() as i64) as usize)
+        .unwrap_or(destination.len());
+    destination.insert(index, habit.id.clone());
+
+    tx.execute(
+        "UPDATE habits SET calendarId = ?2, updatedAt = ?3 WHERE id = ?1",
+        params![id, calendar_id, now],
+    )?;
+    resequence(&tx, calendar_id, &destination, now)?;
+    if habit.calendar_id != calendar_id {
+        resequence(&tx, &habit.calendar_id, &[], now)?;
+    }
+
+    let moved = get(&tx, id)?;
+    tx.commit()?;
+    Ok(moved)
+}
+
+/// Deletes a habit together with its completions and active timers.
+///
+/// The schema declares ON DELETE CASCADE, but rows written while foreign keys
+/// were not enforced (older webview databases) would survive it, so the
+/// dependents are removed explicitly in the same transaction.
+pub fn delete(conn: &mut Connection, id: &str) -> Result<()> {
+    let tx = conn.transaction()?;
+    let habit = get(&tx, id)?;
+    tx.execute("DELETE FROM completions WHERE habitId = ?1", [id])?;
+    tx.execute("DELETE FROM activeTimers WHERE habitId = ?1", [id])?;
+    tx.execute("DELETE FROM reminders WHERE habitId = ?1", [id])?;
+    tx.execute("DELETE FROM habits WHERE id = ?1", [id])?;
+    resequence(&tx, &habit.calendar_id, &[], now_millis())?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn next_position(conn: &Connection, calendar_id: &str) -> Result<i64> {
+    Ok(conn.query_row(
+        "SELECT COALESCE(MAX(position), -1) + 1 FROM habits WHERE calendarId = ?1",
+        [calendar_id],
+        |row| row.get(0),
+    )?)
+}
+
+// Assigns sequential positions within a calendar, touching only rows that change
+fn resequence(
+    tx: &Transaction<'_>,
+    calendar_id: &str,
+    ordered_ids: &[String],
+    now: i64,
+) -> Result<Vec<Habit>> {
+    let mut remaining = list(tx, Some(calendar_id))?;
+    let mut ordered = Vec::with_capacity(remaining.len());
+    for id in ordered_ids {
+        let index = remaining
+            .iter()
+            .position(|h| &h.id == id)
+            .ok_or_else(|| Error::not_found("habit", id))?;
+        ordered.push(remaining.remove(index));
+    }
+    ordered.append(&mut remaining);
+
+    for (position, habit) in ordered.iter_mut().enumerate() {
+        let position = position as i64;
+        if habit.position != position {
+            habit.position = position;
+            habit.updated_at = now;
+            tx.execute(
+                "UPDATE habits SET position = ?2, updatedAt = ?3 WHERE id = ?1",
+                params![habit.id, position, now],
+            )?;
+        }
+    }
+    Ok(ordered)
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn list_habits(db: State<'_, Database>, calendar_id: Option<String>) -> Result<Vec<Habit>> {
+    list(&db.conn(), calendar_id.as_deref())
+}
+
+#[tauri::command]
+pub fn create_habit(db: State<'_, Database>, habit: NewHabit) -> Result<Habit> {
+    create(&db.conn(), habit)
+}
+
+#[tauri::command]
+pub fn update_habit(db: State<'_, Database>, id: String, patch: HabitPatch) -> Result<Habit> {
+    update(&db.conn(), &id, patch)
+}
+
+#[tauri::command]
+pub fn set_habit_enabled(db: State<'_, Database>, id: String, enabled: bool) -> Result<Habit> {
+    set_enabled(&db.conn(), &id, enabled)
+}
+
+#[tauri::command]
+pub fn reorder_habits(
+    db: State<'_, Database>,
+    calendar_id: String,
+    ordered_ids: Vec<String>,
+) -> Result<Vec<Habit>> {
+    reorder(&mut db.conn(), &calendar_id, &ordered_ids)
+}
+
+#[tauri::command]
+pub fn move_habit(
+    db: State<'_, Database>,
+    id: String,
+    calendar_id: String,
+    position: Option<i64>,
+) -> Result<Habit> {
+    move_to_calendar(&mut db.conn(), &id, &calendar_id, position)
+}
+
+#[tauri::command]
+pub fn delete_habit(db: State<'_, Database>, id: String) -> Result<()> {
+    delete(&mut db.conn(), &id)
+}
+
+/// Habit fixtures shared by the other modules' tests.
+#[cfg(test)]
+pub mod tests_support {
+    use super::*;
+
+    /// Creates [`NewHabit::named`] in `calendar_id`.
+    pub fn new_habit(conn: &Connection, calendar_id: &str, name: &str) -> Habit {
+        create(conn, NewHabit::named(calendar_id, name)).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendars::NewCalendar;
+    use crate::reminders::{self, NewReminder};
+    use crate::{completions, timers};
+
+    fn calendar(conn: &Connection, name: &str) -> String {
+        calendars::create(
+            conn,
+            NewCalendar {
+                name: name.into(),
+                color_theme: "blue".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap()
+        .id
+    }
+
+    fn habit(conn: &Connection, calendar_id: &str, name: &str) -> String {
+        tests_support::new_habit(conn, calendar_id, name).id
+    }
+
+    // Names and positions of a calendar's habits, in order
+    fn order(conn: &Connection, calendar_id: &str) -> Vec<(String, i64)> {
+        list(conn, Some(calendar_id))
+            .unwrap()
+            .into_iter()
+            .map(|h| (h.name, h.position))
+            .collect()
+    }
+
+    fn count(conn: &Connection, table: &str, habit_id: &str) -> i64 {
+        conn.query_row(
+            &format!("SELECT count(*) FROM {table} WHERE habitId = ?1"),
+            [habit_id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn validates_input() {
+        let db = Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let calendar_id = calendar(&conn, "Health");
+        let invalid = [
+            NewHabit {
+                name: " ".into(),
+                ..NewHabit::named(&calendar_id, "")
+            },
+            NewHabit {
+                target_duration_seconds: Some(0),
+                ..NewHabit::named(&calendar_id, "Read")
+            },
+            NewHabit {
+                points_value: -5,
+                ..NewHabit::named(&calendar_id, "Read")
+            },
+            NewHabit {
+                target_quantity: Some(f64::NAN),
+                ..NewHabit::named(&calendar_id, "Read")
+            },
+        ];
+        for habit in invalid {
+            assert!(matches!(create(&conn, habit), Err(Error::InvalidInput(_))));
+        }
+        assert!(matches!(
+            create(&conn, NewHabit::named("missing", "Read")),
+            Err(Error::NotFound { .. })
+        ));
+
+        let habit = create(
+            &conn,
+            NewHabit {
+                description: Some("   ".into()),
+                unit: Some(" ".into()),
+                ..NewHabit::named(&calendar_id, " Read ")
+            },
+        )
+        .unwrap();
+        assert_eq!(habit.name, "Read");
+        assert_eq!(habit.description, None);
+        assert_eq!(habit.unit, None);
+
+        let patch = HabitPatch {
+            points_value: Some(-1),
+            ..HabitPatch::default()
+        };
+        assert!(matches!(
+            update(&conn, &habit.id, patch),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn reorder_and_move_keep_positions_sequential() {
+        let db = Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        let health = calendar(&conn, "Health");
+        let work = calendar(&conn, "Work");
+        let a = habit(&conn, &health, "A");
+        let b = habit(&conn, &health, "B");
+        let c = habit(&conn, &health, "C");
+        habit(&conn, &work, "D");
+
+        reorder(&mut conn, &health, &[c.clone(), a.clone()]).unwrap();
+        assert_eq!(
+            order(&conn, &health),
+            vec![("C".into(), 0), ("A".into(), 1), ("B".into(), 2)]
+        );
+        assert!(matches!(
+            reorder(&mut conn, &health, &["missing".into()]),
+            Err(Error::NotFound { .. })
+        ));
+
+        // Moving A out closes its gap; it lands where asked in Work
+        let moved = move_to_calendar(&mut conn, &a, &work, Some(0)).unwrap();
+        assert_eq!(moved.calendar_id, work);
+        assert_eq!(
+            order(&conn, &health),
+            vec![("C".into(), 0), ("B".into(), 1)]
+        );
+        assert_eq!(order(&conn, &work), vec![("A".into(), 0), ("D".into(), 1)]);
+
+        // Out-of-range positions clamp to the end
+        move_to_calendar(&mut conn, &b, &work, Some(99)).unwrap();
+        assert_eq!(order(&conn, &health), vec![("C".into(), 0)]);
+        assert_eq!(
+            order(&conn, &work),
+            vec![("A".into(), 0), ("D".into(), 1), ("B".into(), 2)]
+        );
+    }
+
+    #[test]
+    fn delete_removes_dependents_and_closes_the_gap() {
+        let db = Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        let health = calendar(&conn, "Health");
+        let a = create(
+            &conn,
+            NewHabit {
+                timer_enabled: true,
+                ..NewHabit::named(&health, "A")
+            },
+        )
+        .unwrap()
+        .id;
+        let b = habit(&conn, &health, "B");
+        completions::log(&conn, &a, None, None).unwrap();
+        timers::start(&conn, &a, None).unwrap();
+        reminders::create(
+            &conn,
+            NewReminder {
+                habit_id: a.clone(),
+                time: "08:00".into(),
+                weekdays: None,
+            },
+        )
+        .unwrap();
+        completions::log(&conn, &b, None, None).unwrap();
+
+        delete(&mut conn, &a).unwrap();
+        assert!(matches!(get(&conn, &a), Err(Error::NotFound { .. })));
+        for table in ["completions", "activeTimers", "reminders"] {
+            assert_eq!(count(&conn, table, &a), 0, "{table}");
+        }
+        assert_eq!(count(&conn, "completions", &b), 1);
+        assert_eq!(order(&conn, &health), vec![("B".into(), 0)]);
+        assert!(matches!(delete(&mut conn, &a), Err(Error::NotFound { .. })));
+    }
+}
@@ -0,0 +1,363 @@
+// iCalendar (RFC 5545) export of habit history and schedules.
+//
+// Each Habistat calendar becomes its own VCALENDAR file so it can be
+// subscribed to or imported as a separate layer in calendar clients.
+// Completions are VEVENTs spanning the timer session when one was recorded.
+// Schedules that RRULE can express (daily, every N days, weekdays) are
+// recurring all-day VEVENTs; "N times per week/month" schedules become
+// recurring VTODOs due at the end of each period.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rusqlite::Connection;
+use serde::Serialize;
+use tauri::State;
+
+use crate::calendars::{self, Calendar};
+use crate::completions;
+use crate::days;
+use crate::error::{Error, Result};
+use crate::habits::{self, Habit, HabitType};
+use crate::recurrence::Recurrence;
+use crate::storage::{now_millis, Database};
+
+const PRODID: &str = concat!("-//Habistat//Habistat ", env!("CARGO_PKG_VERSION"), "//EN");
+const UID_DOMAIN: &str = "habistat";
+// RFC 5545 content lines are folded after 75 octets
+const MAX_LINE_OCTETS: usize = 75;
+
+/// One calendar rendered as iCalendar text.
+#[derive(Debug, Clone)]
+pub struct IcsCalendar {
+    pub content: String,
+    pub events: usize,
+    pub schedules: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IcsExport {
+    pub calendar_id: String,
+    pub calendar_name: String,
+    pub path: PathBuf,
+    pub events: usize,
+    pub schedules: usize,
+}
+
+/// Builds iCalendar content lines, escaping and folding as it goes.
+#[derive(Default)]
+struct Writer(String);
+
+impl Writer {
+    fn line(&mut self, name: &str, value: &str) {
+        let line = format!("{name}:{value}");
+        let mut width = 0;
+        for c in line.chars() {
+            if width + c.len_utf8() > MAX_LINE_OCTETS {
+                self.0.push_str("\r\n ");
+                width = 1;
+            }
+            self.0.push(c);
+            width += c.len_utf8();
+        }
+        self.0.push_str("\r\n");
+    }
+
+    fn text(&mut self, name: &str, value: &str) {
+        let escaped = value
+            .replace('\\', "\\\\")
+            .replace(';', "\\;")
+            .replace(',', "\\,")
+            .replace("\r\n", "\\n")
+            .replace('\n', "\\n");
+        self.line(name, &escaped);
+    }
+}
+
+fn utc(millis: i64) -> String {
+    DateTime::<Utc>::from_timestamp_millis(millis)
+        .unwrap_or_default()
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+fn date(date: NaiveDate) -> String {
+    date.format("%Y%m%d").to_string()
+}
+
+/// Renders the completions and schedules of one calendar. `stamp` is the
+/// DTSTAMP of every component, normally the export time.
+pub fn render(conn: &Connection, calendar: &Calendar, stamp: i64) -> Result<IcsCalendar> {
+    let stamp = utc(stamp);
+    let mut out = Writer::default();
+    out.line("BEGIN", "VCALENDAR");
+    out.line("VERSION", "2.0");
+    out.line("PRODID", PRODID);
+    out.line("CALSCALE", "GREGORIAN");
+    out.line("METHOD", "PUBLISH");
+    out.text("X-WR-CALNAME", &calendar.name);
+
+    let (mut events, mut schedules) = (0, 0);
+    for habit in habits::list(conn, Some(&calendar.id))? {
+        if schedule(&mut out, &habit, &stamp) {
+            schedules += 1;
+        }
+        for completion in completions::list_all(conn, &habit.id)? {
+            let duration = completion.duration_seconds.filter(|seconds| *seconds > 0);
+            let summary = match (&completion.unit, completion.quantity) {
+                (Some(unit), quantity) => format!("{} ({quantity} {unit})", habit.name),
+                (None, quantity) if quantity != 1.0 => format!("{} (×{quantity})", habit.name),
+                _ => habit.name.clone(),
+            };
+            out.line("BEGIN", "VEVENT");
+            out.line("UID", &format!("{}@{UID_DOMAIN}", completion.id));
+            out.line("DTSTAMP", &stamp);
+            // Without DTEND an event starting at a date-time takes no time,
+            // which is what a plain check-off is
+            out.line(
+                "DTSTART",
+                &utc(completion.completed_at - duration.unwrap_or_default() * 1000),
+            );
+            if duration.is_some() {
+                out.line("DTEND", &utc(completion.completed_at));
+            }
+            out.text("SUMMARY", &summary);
+            out.text("CATEGORIES", &calendar.name);
+            out.line("TRANSP", "TRANSPARENT");
+            out.line("END", "VEVENT");
+            events += 1;
+        }
+    }
+    out.line("END", "VCALENDAR");
+    Ok(IcsCalendar {
+        content: out.0,
+        events,
+        schedules,
+    })
+}
+
+/// Writes the recurring entry for an enabled positive habit. Negative habits
+/// have nothing to schedule.
+fn schedule(out: &mut Writer, habit: &Habit, stamp: &str) -> bool {
+    if !habit.is_enabled || habit.habit_type == HabitType::Negative {
+        return false;
+    }
+    let anchor = days::local_date_of(habit.created_at);
+    let uid = format!("{}-schedule@{UID_DOMAIN}", habit.local_uuid);
+
+    if let Some(rule) = habit.recurrence.to_rrule() {
+        // The first occurrence must match the rule (e.g. a chosen weekday)
+        let Some(first) = anchor
+            .iter_days()
+            .take(7)
+            .find(|day| habit.recurrence.period_at(*day, anchor).is_some())
+        else {
+            return false;
+        };
+        out.line("BEGIN", "VEVENT");
+        out.line("UID", &uid);
+        out.line("DTSTAMP", stamp);
+        out.line("DTSTART;VALUE=DATE", &date(first));
+        out.line("DTEND;VALUE=DATE", &date(first + Duration::days(1)));
+        out.line("RRULE", &rule);
+        out.text("SUMMARY", &habit.name);
+        if let Some(description) = &habit.description {
+            out.text("DESCRIPTION", description);
+        }
+        out.line("TRANSP", "TRANSPARENT");
+        out.line("END", "VEVENT");
+        return true;
+    }
+
+    let (frequency, period) = match &habit.recurrence {
+        Recurrence::TimesPerWeek { .. } => ("WEEKLY", habit.recurrence.period_at(anchor, anchor)),
+        Recurrence::TimesPerMonth { .. } => ("MONTHLY", habit.recurrence.period_at(anchor, anchor)),
+        _ => return false,
+    };
+    let Some(period) = period else {
+        return false;
+    };
+    out.line("BEGIN", "VTODO");
+    out.line("UID", &uid);
+    out.line("DTSTAMP", stamp);
+    out.line("DTSTART;VALUE=DATE", &date(period.start));
+    out.line("DUE;VALUE=DATE", &date(period.end + Duration::days(1)));
+    out.line("RRULE", &format!("FREQ={frequency}"));
+    out.text(
+        "SUMMARY",
+        &format!("{} ({})", habit.name, habit.recurrence.describe()),
+    );
+    if let Some(description) = &habit.description {
+        out.text("DESCRIPTION", description);
+    }
+    out.line("END", "VTODO");
+    true
+}
+
+/// File name for a calendar: its name reduced to `[a-z0-9-]`.
+fn file_stem(name: &str) -> String {
+    let stem: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let stem = stem
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if stem.is_empty() {
+        "calendar".into()
+    } else {
+        stem
+    }
+}
+
+/// Writes one `.ics` file per calendar into `dir`.
+pub fn export(conn: &Connection, dir: &Path) -> Result<Vec<IcsExport>> {
+    if dir.is_file() {
+        return Err(Error::InvalidInput(format!(
+            "`{}` is a file, not a folder",
+            dir.display()
+        )));
+    }
+    fs::create_dir_all(dir)?;
+    let stamp = now_millis();
+    let mut used = HashSet::new();
+    let mut exports = Vec::new();
+    for calendar in calendars::list(conn)? {
+        let rendered = render(conn, &calendar, stamp)?;
+        let stem = file_stem(&calendar.name);
+        let stem = (1..)
+            .map(|n| {
+                if n == 1 {
+                    stem.clone()
+                } else {
+                    format!("{stem}-{n}")
+                }
+            })
+            .find(|candidate| used.insert(candidate.clone()))
+            .unwrap_or(stem);
+        let path = dir.join(format!("{stem}.ics"));
+        fs::write(&path, rendered.content)?;
+        exports.push(IcsExport {
+            calendar_id: calendar.id,
+            calendar_name: calendar.name,
+            path,
+            events: rendered.events,
+            schedules: rendered.schedules,
+        });
+    }
+    Ok(exports)
+}
+
+// --- Tauri commands ---
+
+/// Exports every calendar as `<name>.ics` into the folder `dir`.
+#[tauri::command]
+pub fn export_ics(db: State<'_, Database>, dir: String) -> Result<Vec<IcsExport>> {
+    export(&db.conn(), Path::new(&dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendars::NewCalendar;
+    use crate::habits::NewHabit;
+    use crate::recurrence::RecurrenceInput;
+    use crate::storage;
+
+    fn habit(conn: &Connection, calendar_id: &str, name: &str, rule: &str) -> Habit {
+        habits::create(
+            conn,
+            NewHabit {
+                description: Some("Line one\nsecond; part".into()),
+                timer_enabled: true,
+                recurrence: Some(RecurrenceInput::Rule(rule.into())),
+                ..NewHabit::named(calendar_id, name)
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn renders_events_and_schedules() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let calendar = calendars::create(
+            &conn,
+            NewCalendar {
+                name: "Health, body & mind".into(),
+                color_theme: "blue".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap();
+        let yoga = habit(
+            &conn,
+            &calendar.id,
+            "Yoga with a very long name that needs folding across several content lines",
+            "FREQ=WEEKLY;BYDAY=MO,WE",
+        );
+        let read = habit(&conn, &calendar.id, "Read", "FREQ=DAILY");
+        conn.execute(
+            "UPDATE habits SET recurrence = ?2 WHERE id = ?1",
+            rusqlite::params![read.id, &Recurrence::TimesPerWeek { count: 3 }],
+        )
+        .unwrap();
+        // 2023-11-14 22:13:20 UTC, after a 30 minute session
+        completions::log_timed(&conn, &yoga.id, 1_700_000_000_000, 1800, None).unwrap();
+
+        let ics = render(&conn, &calendar, 1_700_000_000_000).unwrap();
+        assert_eq!((ics.events, ics.schedules), (1, 2));
+        assert!(ics.content.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.content.ends_with("END:VCALENDAR\r\n"));
+        assert!(ics
+            .content
+            .lines()
+            .all(|line| line.trim_end_matches('\r').len() <= MAX_LINE_OCTETS));
+
+        let unfolded = ics.content.replace("\r\n ", "");
+        assert!(unfolded.contains("X-WR-CALNAME:Health\\, body & mind\r\n"));
+        assert!(unfolded.contains("DTSTART:20231114T214320Z\r\nDTEND:20231114T221320Z\r\n"));
+        assert!(unfolded.contains("RRULE:FREQ=WEEKLY;BYDAY=MO,WE\r\n"));
+        assert!(unfolded.contains("DESCRIPTION:Line one\\nsecond\\; part\r\n"));
+        assert!(unfolded.contains("BEGIN:VTODO\r\n"));
+        assert!(unfolded.contains("SUMMARY:Read (3 times per week)\r\n"));
+    }
+
+    #[test]
+    fn plain_check_off_has_no_end() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let calendar = calendars::create(
+            &conn,
+            NewCalendar {
+                name: "Health".into(),
+                color_theme: "blue".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap();
+        let read = habit(&conn, &calendar.id, "Read", "FREQ=DAILY");
+        completions::log(&conn, &read.id, Some(1_700_000_000_000), None).unwrap();
+
+        let ics = render(&conn, &calendar, 1_700_000_000_000).unwrap();
+        assert_eq!(ics.events, 1);
+        assert!(ics
+            .content
+            .contains("DTSTART:20231114T221320Z\r\nSUMMARY:Read\r\n"));
+        assert!(!ics.content.contains("DTEND:"));
+    }
+
+    #[test]
+    fn file_names_are_slugs() {
+        assert_eq!(file_stem("Health, body & mind"), "health-body-mind");
+        assert_eq!(file_stem("🙂"), "calendar");
+    }
+}
@@ -0,0 +1,327 @@
+// Consistency checks and repairs for the native database.
+//
+// `check` runs SQLite's own `integrity_check` and `foreign_key_check`, then
+// looks for the app-level damage older builds could leave behind (rows
+// written while foreign keys were not enforced, or by the webview's sql.js
+// copy): completions, timers and reminders of deleted habits, habits whose
+// calendar is gone, and several activityHistory rows for one day.
+//
+// `repair` deletes orphans, keeps the earliest activityHistory row of each
+// day, and moves homeless habits into a "Recovered habits" calendar rather
+// than deleting their history. Page-level corruption cannot be fixed in place;
+// indexes are rebuilt, anything else needs a snapshot restore.
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use tauri::{AppHandle, Runtime, State};
+
+use crate::auto_backup::{self, BackupInfo};
+use crate::calendars::{self, NewCalendar};
+use crate::error::Result;
+use crate::storage::{now_millis, Database};
+
+pub const RECOVERY_CALENDAR_NAME: &str = "Recovered habits";
+const RECOVERY_CALENDAR_COLOR: &str = "amber";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForeignKeyViolation {
+    pub table: String,
+    pub rowid: Option<i64>,
+    pub parent: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateDay {
+    pub user_id: Option<String>,
+    pub date: String,
+    /// Rows for the day, the one a repair keeps first.
+    pub ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseReport {
+    /// Problems reported by `PRAGMA integrity_check`; empty when it says "ok".
+    pub integrity_errors: Vec<String>,
+    pub foreign_key_violations: Vec<ForeignKeyViolation>,
+    pub orphaned_completions: Vec<String>,
+    pub orphaned_timers: Vec<String>,
+    pub orphaned_reminders: Vec<String>,
+    pub duplicate_activity_days: Vec<DuplicateDay>,
+    pub habits_missing_calendar: Vec<String>,
+}
+
+impl DatabaseReport {
+    pub fn is_healthy(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairSummary {
+    pub deleted_completions: usize,
+    pub deleted_timers: usize,
+    pub deleted_reminders: usize,
+    pub deleted_activity_rows: usize,
+    pub rehomed_habits: usize,
+    /// Calendar the homeless habits were moved to, if any.
+    pub recovery_calendar_id: Option<String>,
+    pub reindexed: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseCheck {
+    pub found: DatabaseReport,
+    /// Set when a repair was requested and needed.
+    pub repaired: Option<RepairSummary>,
+    /// Snapshot taken before repairing.
+    pub backup: Option<BackupInfo>,
+    /// What is left after the repair.
+    pub remaining: Option<DatabaseReport>,
+}
+
+fn ids(conn: &Connection, sql: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+const ORPHANED_COMPLETIONS: &str = "SELECT id FROM completions
+     WHERE habitId NOT IN (SELECT id FROM habits) ORDER BY id";
+const ORPHANED_TIMERS: &str = "SELECT id FROM activeTimers
+     WHERE habitId NOT IN (SELECT id FROM habits) ORDER BY id";
+const ORPHANED_REMINDERS: &str = "SELECT id FROM reminders
+     WHERE habitId NOT IN (SELECT id FROM habits) ORDER BY id";
+const HABITS_MISSING_CALENDAR: &str = "SELECT id FROM habits
+     WHERE calendarId NOT IN (SELECT id FROM calendars) ORDER BY id";
+
+/// Runs every check without changing anything.
+pub fn check(conn: &Connection) -> Result<DatabaseReport> {
+    let integrity_errors = {
+        let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|message| message != "ok")
+            .collect()
+    };
+
+    let foreign_key_violations = {
+        let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(ForeignKeyViolation {
+                table: row.get(0)?,
+                rowid: row.get(1)?,
+                parent: row.get(2)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+
+    // The unique index treats NULL users as distinct, so group on the value
+    // itself. Rows come oldest first, the order a repair relies on.
+    let duplicate_activity_days = {
+        let mut stmt = conn.prepare(
+            "SELECT a.userId, a.date, a.id FROM activityHistory a
+             JOIN (
+                 SELECT userId, date FROM activityHistory
+                 GROUP BY userId, date HAVING count(*) > 1
+             ) d ON a.userId IS d.userId AND a.date = d.date
+             ORDER BY a.date, a.userId, a.rowid",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, Option<String>>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        let mut days: Vec<DuplicateDay> = Vec::new();
+        for row in rows {
+            let (user_id, date, id) = row?;
+            match days.last_mut() {
+                Some(day) if day.user_id == user_id && day.date == date => day.ids.push(id),
+                _ => days.push(DuplicateDay {
+                    user_id,
+                    date,
+                    ids: vec![id],
+                }),
+            }
+        }
+        days
+    };
+
+    Ok(DatabaseReport {
+        integrity_errors,
+        foreign_key_violations,
+        orphaned_completions: ids(conn, ORPHANED_COMPLETIONS)?,
+        orphaned_timers: ids(conn, ORPHANED_TIMERS)?,
+        orphaned_reminders: ids(conn, ORPHANED_REMINDERS)?,
+        duplicate_activity_days,
+        habits_missing_calendar: ids(conn, HABITS_MISSING_CALENDAR)?,
+    })
+}
+
+/// Fixes what [`check`] reported, in one transaction.
+pub fn repair(conn: &mut Connection, report: &DatabaseReport) -> Result<RepairSummary> {
+    let mut summary = RepairSummary::default();
+    // REINDEX rebuilds damaged indexes, the one kind of corruption fixable in place
+    if !report.integrity_errors.is_empty() {
+        conn.execute_batch("REINDEX")?;
+        summary.reindexed = true;
+    }
+
+    let tx = conn.transaction()?;
+    // Habits are rehomed first so their completions stop counting as orphans
+    if !report.habits_missing_calendar.is_empty() {
+        let calendar = calendars::create(
+            &tx,
+            NewCalendar {
+                name: RECOVERY_CALENDAR_NAME.into(),
+                color_theme: RECOVERY_CALENDAR_COLOR.into(),
+                user_id: None,
+                position: None,
+            },
+        )?;
+        for habit_id in &report.habits_missing_calendar {
+            summary.rehomed_habits += tx.execute(
+                "UPDATE habits SET calendarId = ?2, updatedAt = ?3 WHERE id = ?1",
+                params![habit_id, calendar.id, now_millis()],
+            )?;
+        }
+        summary.recovery_calendar_id = Some(calendar.id);
+    }
+    summary.deleted_completions = tx.execute(
+        "DELETE FROM completions WHERE habitId NOT IN (SELECT id FROM habits)",
+        [],
+    )?;
+    summary.deleted_timers = tx.execute(
+        "DELETE FROM activeTimers WHERE habitId NOT IN (SELECT id FROM habits)",
+        [],
+    )?;
+    summary.deleted_reminders = tx.execute(
+        "DELETE FROM reminders WHERE habitId NOT IN (SELECT id FROM habits)",
+        [],
+    )?;
+    if !report.duplicate_activity_days.is_empty() {
+        // Keeps the earliest row of each day
+        summary.deleted_activity_rows = tx.execute(
+            "DELETE FROM activityHistory WHERE rowid NOT IN (
+                 SELECT min(rowid) FROM activityHistory GROUP BY userId, date
+             )",
+            [],
+        )?;
+    }
+    tx.commit()?;
+    Ok(summary)
+}
+
+// --- Tauri commands ---
+
+/// Checks the database and, with `repair`, fixes what it can after taking a
+/// snapshot (see `auto_backup`).
+#[tauri::command]
+pub fn check_database<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    repair: bool,
+) -> Result<DatabaseCheck> {
+    let mut conn = db.conn();
+    let found = check(&conn)?;
+    if !repair || found.is_healthy() {
+        return Ok(DatabaseCheck {
+            found,
+            repaired: None,
+            backup: None,
+            remaining: None,
+        });
+    }
+    let backup = auto_backup::snapshot(
+        &conn,
+        db.key().as_ref(),
+        &auto_backup::dir(&app)?,
+        now_millis(),
+    )?;
+    let repaired = self::repair(&mut conn, &found)?;
+    let remaining = check(&conn)?;
+    drop(conn);
+    db.mark_changed();
+    Ok(DatabaseCheck {
+        found,
+        repaired: Some(repaired),
+        backup: Some(backup),
+        remaining: Some(remaining),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::habits;
+    use crate::storage;
+
+    #[test]
+    fn finds_and_repairs_app_level_damage() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        assert!(check(&conn).unwrap().is_healthy());
+
+        // Rows as a build without enforced foreign keys could leave them
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        conn.execute_batch(
+            "INSERT INTO habits (id, localUuid, calendarId, name, type, position, createdAt, updatedAt)
+                 VALUES ('h1', 'h1', 'gone', 'Read', 'positive', 0, 0, 0);
+             INSERT INTO completions (id, habitId, completedAt, clientUpdatedAt)
+                 VALUES ('c1', 'h1', 0, 0), ('c2', 'deleted', 0, 0);
+             INSERT INTO activeTimers (id, habitId, startTime, status, createdAt, updatedAt)
+                 VALUES ('t1', 'deleted', 0, 'running', 0, 0);
+             INSERT INTO activityHistory (id, userId, localUuid, date)
+                 VALUES ('a2', NULL, 'a2', '2024-03-01'), ('a3', NULL, 'a3', '2024-03-02'),
+                        ('a1', NULL, 'a1', '2024-03-01');",
+        )
+        .unwrap();
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+
+        let report = check(&conn).unwrap();
+        assert!(report.integrity_errors.is_empty());
+        assert_eq!(report.foreign_key_violations.len(), 3);
+        assert_eq!(report.orphaned_completions, vec!["c2"]);
+        assert_eq!(report.orphaned_timers, vec!["t1"]);
+        assert_eq!(report.habits_missing_calendar, vec!["h1"]);
+        assert_eq!(
+            report.duplicate_activity_days,
+            vec![DuplicateDay {
+                user_id: None,
+                date: "2024-03-01".into(),
+                ids: vec!["a2".into(), "a1".into()],
+            }]
+        );
+
+        let summary = repair(&mut conn, &report).unwrap();
+        assert_eq!(
+            (
+                summary.deleted_completions,
+                summary.deleted_timers,
+                summary.deleted_activity_rows,
+                summary.rehomed_habits
+            ),
+            (1, 1, 1, 1)
+        );
+        assert!(check(&conn).unwrap().is_healthy());
+        // The earliest row of the day is the one kept
+        let kept: String = conn
+            .query_row(
+                "SELECT id FROM activityHistory WHERE date = '2024-03-01'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(kept, "a2");
+        let habit = habits::get(&conn, "h1").unwrap();
+        assert_eq!(Some(habit.calendar_id), summary.recovery_calendar_id);
+    }
+}
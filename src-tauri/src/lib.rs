@@ -1,3 +1,33 @@
+pub mod auto_backup;
+pub mod backup;
+pub mod calendars;
+pub mod completion_csv;
+pub mod completions;
+pub mod credentials;
+pub mod days;
+pub mod encryption;
+pub mod error;
+pub mod gamification;
+pub mod habitica_import;
+pub mod habits;
+pub mod ics;
+pub mod integrity;
+pub mod loop_import;
+pub mod migrations;
+pub mod nudges;
+pub mod recurrence;
+pub mod reminders;
+pub mod settings;
+pub mod storage;
+pub mod streaks;
+pub mod sync;
+pub mod timer_recovery;
+pub mod timers;
+pub mod today;
+#[cfg(desktop)]
+pub mod tray;
+pub mod tray_icon;
+
 // Define the command within the library crate
 #[tauri::command]
 fn get_os() -> String {
@@ -6,29 +36,114 @@ fn get_os() -> String {
 
 // Import the Manager trait and OS plugin
 
-// use tauri::Manager;
+use tauri::{AppHandle, Manager, Runtime};
 use tauri_plugin_os;
 
+/// Starts everything that reads the database. Runs during setup, or from
+/// `unlock_database` once an encrypted database has been opened.
+pub(crate) fn start_services<R: Runtime>(
+    app: &AppHandle<R>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Interrupted sessions are frozen before the timer service starts ticking
+    timer_recovery::init(app)?;
+    timers::start_service(app.clone())?;
+    reminders::start_service(app.clone())?;
+    nudges::start_service(app.clone())?;
+    auto_backup::start_service(app.clone())?;
+    #[cfg(desktop)]
+    tray::init(app)?;
+    Ok(())
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
-        // .setup(|_app| {
-        //     #[cfg(debug_assertions)] // Only open devtools in debug builds
-        //     {
-        //         if let Some(window) = _app.get_webview_window("main") {
-        //             window.open_devtools();
-        //             println!("Devtools opened successfully");
-        //         } else {
-        //             println!("Warning: Could not find main window to open devtools");
-        //         }
-        //     }
-        //     Ok(())
-        // })
+        .setup(|app| {
+            app.manage(gamification::GamificationCache::default());
+            // Open the native database and apply pending migrations before any command can reach it;
+            // an encrypted one stays closed until the webview sends the passphrase
+            if storage::init(app.handle())? {
+                start_services(app.handle())?;
+            }
+
+            // #[cfg(debug_assertions)] // Only open devtools in debug builds
+            // {
+            //     if let Some(window) = app.get_webview_window("main") {
+            //         window.open_devtools();
+            //         println!("Devtools opened successfully");
+            //     } else {
+            //         println!("Warning: Could not find main window to open devtools");
+            //     }
+            // }
+            Ok(())
+        })
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_os::init())
+        .plugin(tauri_plugin_notification::init())
         .invoke_handler(tauri::generate_handler![
             // Now use the function directly as it's in the same scope
-            get_os
+            get_os,
+            backup::export_backup,
+            backup::import_backup,
+            auto_backup::list_backups,
+            auto_backup::create_backup,
+            auto_backup::restore_backup,
+            auto_backup::get_auto_backup_settings,
+            auto_backup::set_auto_backup_settings,
+            integrity::check_database,
+            encryption::get_encryption_status,
+            encryption::unlock_database,
+            encryption::enable_encryption,
+            encryption::change_passphrase,
+            encryption::disable_encryption,
+            credentials::store_auth_token,
+            credentials::get_auth_token,
+            credentials::clear_auth_token,
+            sync::sync_now,
+            calendars::list_calendars,
+            calendars::create_calendar,
+            calendars::update_calendar,
+            calendars::reorder_calendars,
+            calendars::set_calendar_enabled,
+            calendars::delete_calendar,
+            completion_csv::export_completions_csv,
+            completion_csv::preview_csv_import,
+            completion_csv::import_completions_csv,
+            ics::export_ics,
+            habits::list_habits,
+            habits::create_habit,
+            habits::update_habit,
+            habits::set_habit_enabled,
+            habits::reorder_habits,
+            habits::move_habit,
+            habits::delete_habit,
+            loop_import::import_loop_backup,
+            habitica_import::import_habitica_export,
+            completions::log_completion,
+            completions::undo_last_completion,
+            completions::delete_completions_for_day,
+            completions::list_completions,
+            completions::get_daily_totals,
+            streaks::get_habit_streaks,
+            today::get_today_checklist,
+            gamification::get_gamification_state,
+            timers::list_active_timers,
+            timers::start_timer,
+            timers::pause_timer,
+            timers::resume_timer,
+            timers::stop_timer,
+            timers::get_timer_settings,
+            timers::set_timer_settings,
+            timer_recovery::get_recovered_timers,
+            timer_recovery::resolve_recovered_timer,
+            reminders::list_reminders,
+            reminders::create_reminder,
+            reminders::update_reminder,
+            reminders::delete_reminder,
+            reminders::snooze_reminder,
+            reminders::complete_reminder,
+            nudges::get_nudge_settings,
+            nudges::set_nudge_settings
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,545 @@
+// Importer for Loop Habit Tracker backups.
+//
+// Loop exports its whole SQLite database (`Loop Habits Backup *.db`). Its
+// habits land in one "Loop Habit Tracker" calendar, its frequencies map onto
+// `Recurrence`, and its repetitions become completions at local noon of the
+// day they were checked. Anything Habistat cannot express exactly is still
+// imported and listed under `approximated`. Habits carry Loop's uuid as their
+// `localUuid`, so importing the same backup twice skips what already exists.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDate};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::Serialize;
+use tauri::State;
+
+use crate::backup;
+use crate::calendars::{self, NewCalendar};
+use crate::completions::{self, Completion};
+use crate::days;
+use crate::error::{Error, Result};
+use crate::habits::{self, HabitType, NewHabit};
+use crate::recurrence::{Recurrence, RecurrenceInput};
+use crate::reminders::{self, NewReminder};
+use crate::storage::{new_id, now_millis, Database};
+
+pub const CALENDAR_NAME: &str = "Loop Habit Tracker";
+const CALENDAR_COLOR: &str = "teal";
+
+// Loop's repetition values for yes/no habits
+const YES_MANUAL: i64 = 2;
+// Numerical habits store amounts in thousandths
+const NUMERIC_SCALE: f64 = 1000.0;
+// Loop's `target_type` for "at most" numerical targets
+const AT_MOST: i64 = 1;
+const EVERY_DAY: i64 = 127;
+
+/// One habit-level remark in an import summary.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportNote {
+    pub habit: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoopImportSummary {
+    pub calendar_id: String,
+    pub habits_created: usize,
+    pub completions_created: usize,
+    pub reminders_created: usize,
+    /// Repetitions that are not check-offs (explicit "no" or "skip" entries).
+    pub completions_skipped: usize,
+    pub skipped: Vec<ImportNote>,
+    pub approximated: Vec<ImportNote>,
+}
+
+/// A row of Loop's `Habits` table, normalised across Loop versions.
+struct LoopHabit {
+    id: i64,
+    uuid: Option<String>,
+    name: String,
+    description: Option<String>,
+    archived: bool,
+    numerical: bool,
+    freq_num: i64,
+    freq_den: i64,
+    target_type: i64,
+    target_value: f64,
+    unit: Option<String>,
+    reminder: Option<(i64, i64, i64)>,
+}
+
+/// Imports every habit of the Loop backup at `path` into `conn`.
+pub fn import(conn: &mut Connection, path: &Path) -> Result<LoopImportSummary> {
+    let source = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let loop_habits = read_habits(&source)?;
+
+    let tx = conn.transaction()?;
+    let calendar_id = loop_calendar(&tx)?;
+    // Uuids are unique across all habits, wherever the user moved them since;
+    // names only identify habits still in the Loop calendar
+    let existing: Vec<habits::Habit> = habits::list(&tx, None)?;
+    let known_uuids: HashSet<&str> = existing.iter().map(|h| h.local_uuid.as_str()).collect();
+    let known_names: HashSet<String> = existing
+        .iter()
+        .filter(|h| h.calendar_id == calendar_id)
+        .map(|h| h.name.to_lowercase())
+        .collect();
+
+    let mut summary = LoopImportSummary {
+        calendar_id: calendar_id.clone(),
+        ..LoopImportSummary::default()
+    };
+    let note = |habit: &LoopHabit, message: String| ImportNote {
+        habit: habit.name.clone(),
+        message,
+    };
+    for loop_habit in &loop_habits {
+        let already_imported = match &loop_habit.uuid {
+            Some(uuid) => known_uuids.contains(uuid.as_str()),
+            None => known_names.contains(&loop_habit.name.to_lowercase()),
+        };
+        if already_imported {
+            summary
+                .skipped
+                .push(note(loop_habit, "already imported".into()));
+            continue;
+        }
+        if loop_habit.name.trim().is_empty() {
+            summary
+                .skipped
+                .push(note(loop_habit, "habit has no name".into()));
+            continue;
+        }
+
+        let (recurrence, exact) = recurrence(loop_habit);
+        if !exact {
+            summary.approximated.push(note(
+                loop_habit,
+                format!(
+                    "{} times every {} days imported as {}",
+                    loop_habit.freq_num,
+                    loop_habit.freq_den,
+                    recurrence.describe()
+                ),
+            ));
+        }
+        let target_quantity = loop_habit.numerical.then(|| {
+            let per_day = loop_habit.target_value / loop_habit.freq_den.max(1) as f64;
+            if loop_habit.freq_den > 1 {
+                summary.approximated.push(note(
+                    loop_habit,
+                    format!(
+                        "target of {} per {} days imported as {per_day:.2} per day",
+                        loop_habit.target_value, loop_habit.freq_den
+                    ),
+                ));
+            }
+            per_day
+        });
+        if loop_habit.numerical && loop_habit.target_type == AT_MOST {
+            summary.approximated.push(note(
+                loop_habit,
+                "\"at most\" target imported as a daily target".into(),
+            ));
+        }
+
+        let habit = habits::create(
+            &tx,
+            NewHabit {
+                calendar_id: calendar_id.clone(),
+                name: loop_habit.name.clone(),
+                description: loop_habit.description.clone(),
+                habit_type: HabitType::Positive,
+                timer_enabled: false,
+                target_duration_seconds: None,
+                points_value: 0,
+                user_id: None,
+                position: None,
+                recurrence: Some(RecurrenceInput::Model(recurrence)),
+                target_quantity: target_quantity.filter(|t| *t > 0.0),
+                unit: loop_habit.unit.clone(),
+            },
+        )?;
+        summary.habits_created += 1;
+
+        let (created, skipped, first_day) = import_repetitions(&source, &tx, loop_habit, &habit)?;
+        summary.completions_created += created;
+        summary.completions_skipped += skipped;
+
+        // Keep Loop's identity and start the habit on its first check-off so
+        // interval recurrences line up with the imported history
+        let created_at = first_day.map_or(habit.created_at, |day| {
+            habit.created_at.min(days::local_day_bounds(day).0)
+        });
+        tx.execute(
+            "UPDATE habits SET localUuid = ?2, createdAt = ?3 WHERE id = ?1",
+            params![
+                habit.id,
+                loop_habit.uuid.as_deref().unwrap_or(&habit.local_uuid),
+                created_at
+            ],
+        )?;
+
+        if loop_habit.archived {
+            habits::set_enabled(&tx, &habit.id, false)?;
+        }
+        if let Some((hour, minute, weekdays)) = loop_habit.reminder {
+            if weekdays == EVERY_DAY {
+                reminders::create(
+                    &tx,
+                    NewReminder {
+                        habit_id: habit.id.clone(),
+                        time: format!("{hour:02}:{minute:02}"),
+                        weekdays: None,
+                    },
+                )?;
+                summary.reminders_created += 1;
+            } else {
+                summary.skipped.push(note(
+                    loop_habit,
+                    "reminder on selected weekdays only was not imported".into(),
+                ));
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(summary)
+}
+
+fn read_habits(source: &Connection) -> Result<Vec<LoopHabit>> {
+    let columns = backup::columns(source, "Habits")?;
+    if !columns.contains("freq_num") || backup::columns(source, "Repetitions")?.is_empty() {
+        return Err(Error::InvalidInput(
+            "file is not a Loop Habit Tracker backup".into(),
+        ));
+    }
+    // Older Loop versions lack the newer columns
+    let column = |name: &str, fallback: &str| {
+        if columns.contains(name) {
+            name.to_string()
+        } else {
+            fallback.to_string()
+        }
+    };
+    let sql = format!(
+        "SELECT id, {uuid}, name, {question}, description, archived, {kind},
+                freq_num, freq_den, {target_type}, {target_value}, {unit},
+                reminder_hour, reminder_min, {reminder_days}
+         FROM Habits ORDER BY position, id",
+        uuid = column("uuid", "NULL"),
+        question = column("question", "NULL"),
+        kind = column("type", "0"),
+        target_type = column("target_type", "0"),
+        target_value = column("target_value", "0"),
+        unit = column("unit", "NULL"),
+        reminder_days = column("reminder_days", "127"),
+    );
+    let mut stmt = source.prepare(&sql)?;
+    let rows = stmt.query_map([], |row| {
+        let text = |index: usize| -> rusqlite::Result<Option<String>> {
+            Ok(row
+                .get::<_, Option<String>>(index)?
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty()))
+        };
+        let reminder_hour: Option<i64> = row.get(12)?;
+        let reminder_min: Option<i64> = row.get(13)?;
+        Ok(LoopHabit {
+            id: row.get(0)?,
+            uuid: text(1)?,
+            name: text(2)?.unwrap_or_default(),
+            description: text(3)?.or(text(4)?),
+            archived: row.get::<_, Option<i64>>(5)?.unwrap_or(0) != 0,
+            numerical: row.get::<_, i64>(6)? == 1,
+            freq_num: row.get::<_, Option<i64>>(7)?.unwrap_or(1),
+            freq_den: row.get::<_, Option<i64>>(8)?.unwrap_or(1),
+            target_type: row.get(9)?,
+            target_value: row.get(10)?,
+            unit: text(11)?,
+            reminder: reminder_hour
+                .zip(reminder_min)
+                .map(|(hour, minute)| Ok::<_, rusqlite::Error>((hour, minute, row.get(14)?)))
+                .transpose()?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// The calendar imported habits go to, created on first use.
+fn loop_calendar(conn: &Connection) -> Result<String> {
+    let existing = conn
+        .query_row(
+            "SELECT id FROM calendars WHERE name = ?1 ORDER BY createdAt LIMIT 1",
+            [CALENDAR_NAME],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match existing {
+        Some(id) => Ok(id),
+        None => Ok(calendars::create(
+            conn,
+            NewCalendar {
+                name: CALENDAR_NAME.into(),
+                color_theme: CALENDAR_COLOR.into(),
+                user_id: None,
+                position: None,
+            },
+        )?
+        .id),
+    }
+}
+
+/// Maps Loop's "`freq_num` times in `freq_den` days" onto a [`Recurrence`],
+/// and whether the mapping is exact.
+fn recurrence(habit: &LoopHabit) -> (Recurrence, bool) {
+    // Numerical habits use the frequency as the target's period instead
+    if habit.numerical {
+        return (Recurrence::Daily, true);
+    }
+    let (num, den) = (habit.freq_num.max(1), habit.freq_den.max(1));
+    match (num, den) {
+        (num, den) if num >= den => (Recurrence::Daily, num == den),
+        (1, den) => (
+            Recurrence::EveryNDays {
+                interval: den as u32,
+            },
+            true,
+        ),
+        (num, 7) => (Recurrence::TimesPerWeek { count: num as u32 }, true),
+        // An N-day window is only roughly a calendar month
+        (num, 28..=31) => (
+            Recurrence::TimesPerMonth {
+                count: num.min(28) as u32,
+            },
+            false,
+        ),
+        (num, den) => {
+            let per_week = (num as f64 * 7.0 / den as f64).round().clamp(1.0, 7.0);
+            (
+                Recurrence::TimesPerWeek {
+                    count: per_week as u32,
+                },
+                false,
+            )
+        }
+    }
+}
+
+/// Copies the check-offs of one Loop habit. Returns how many were created and
+/// skipped, and the first day imported.
+fn import_repetitions(
+    source: &Connection,
+    conn: &Connection,
+    loop_habit: &LoopHabit,
+    habit: &habits::Habit,
+) -> Result<(usize, usize, Option<NaiveDate>)> {
+    let mut stmt = source
+        .prepare("SELECT timestamp, value FROM Repetitions WHERE habit = ?1 ORDER BY timestamp")?;
+    let rows = stmt.query_map([loop_habit.id], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, Option<i64>>(1)?))
+    })?;
+
+    let now = now_millis();
+    let (mut created, mut skipped, mut first_day) = (0, 0, None);
+    for row in rows {
+        let (timestamp, value) = row?;
+        // Very old backups have no value column; every row was a check-off
+        let value = value.unwrap_or(YES_MANUAL);
+        let quantity = if loop_habit.numerical {
+            value as f64 / NUMERIC_SCALE
+        } else if value == YES_MANUAL {
+            1.0
+        } else {
+            0.0
+        };
+        // Loop timestamps are UTC midnight of the local day
+        let day = DateTime::from_timestamp_millis(timestamp).map(|at| at.date_naive());
+        let Some(day) = day.filter(|_| quantity > 0.0) else {
+            skipped += 1;
+            continue;
+        };
+        completions::insert(
+            conn,
+            &Completion {
+                id: new_id(),
+                user_id: None,
+                habit_id: habit.id.clone(),
+                completed_at: days::local_day_bounds(day).0 + 12 * 60 * 60 * 1000,
+                client_updated_at: now,
+                quantity,
+                unit: habit.unit.clone(),
+                duration_seconds: None,
+            },
+        )?;
+        first_day.get_or_insert(day);
+        created += 1;
+    }
+    Ok((created, skipped, first_day))
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn import_loop_backup(db: State<'_, Database>, path: String) -> Result<LoopImportSummary> {
+    import(&mut db.conn(), Path::new(&path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage;
+
+    const LOOP_SCHEMA: &str = "
+        CREATE TABLE Habits (id integer primary key autoincrement, archived integer,
+            color integer, description text, freq_den integer, freq_num integer,
+            highlight integer, name text, position integer, reminder_hour integer,
+            reminder_min integer, reminder_days integer not null default 127,
+            type integer not null default 0, target_type integer not null default 0,
+            target_value real not null default 0, unit text not null default '',
+            question text, uuid text);
+        CREATE TABLE Repetitions (id integer primary key autoincrement,
+            habit integer not null references habits(id), timestamp integer not null,
+            value integer not null);";
+
+    fn loop_backup() -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("loop-{}.db", new_id()));
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(LOOP_SCHEMA).unwrap();
+        conn.execute_batch(
+            "INSERT INTO Habits (id, archived, freq_den, freq_num, name, position,
+                reminder_hour, reminder_min, question, uuid)
+             VALUES (1, 0, 7, 3, 'Gym', 0, 7, 30, 'Did you work out?', 'aaa'),
+                    (2, 1, 10, 3, 'Call mum', 1, NULL, NULL, NULL, 'bbb');
+             INSERT INTO Habits (id, archived, freq_den, freq_num, name, position, type,
+                target_value, unit, uuid)
+             VALUES (3, 0, 1, 1, 'Water', 2, 1, 8, 'glasses', 'ccc');
+             -- 2024-03-01 and 2024-03-02 at UTC midnight
+             INSERT INTO Repetitions (habit, timestamp, value)
+             VALUES (1, 1709251200000, 2), (1, 1709337600000, 3), (2, 1709251200000, 2),
+                    (3, 1709251200000, 6500), (3, 1709337600000, 0);",
+        )
+        .unwrap();
+        path
+    }
+
+    #[test]
+    fn imports_habits_and_repetitions_once() {
+        let path = loop_backup();
+        let db = storage::Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+
+        let summary = import(&mut conn, &path).unwrap();
+        assert_eq!(summary.habits_created, 3);
+        assert_eq!(summary.completions_created, 3);
+        assert_eq!(summary.completions_skipped, 2);
+        assert_eq!(summary.reminders_created, 1);
+        let approximated: Vec<&str> = summary
+            .approximated
+            .iter()
+            .map(|n| n.habit.as_str())
+            .collect();
+        assert_eq!(approximated, vec!["Call mum"]);
+
+        let imported = habits::list(&conn, Some(&summary.calendar_id)).unwrap();
+        let gym = &imported[0];
+        assert_eq!(gym.local_uuid, "aaa");
+        assert_eq!(gym.recurrence, Recurrence::TimesPerWeek { count: 3 });
+        assert_eq!(gym.description.as_deref(), Some("Did you work out?"));
+        assert!(!imported[1].is_enabled);
+        let water = &imported[2];
+        assert_eq!(water.target_quantity, Some(8.0));
+        let logged = completions::list_all(&conn, &water.id).unwrap();
+        assert_eq!(logged[0].quantity, 6.5);
+        assert_eq!(
+            days::local_date_of(logged[0].completed_at),
+            "2024-03-01".parse::<NaiveDate>().unwrap()
+        );
+
+        let again = import(&mut conn, &path).unwrap();
+        assert_eq!((again.habits_created, again.skipped.len()), (0, 3));
+        assert_eq!(again.calendar_id, summary.calendar_id);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn reimport_finds_moved_habits_by_uuid() {
+        let path = loop_backup();
+        let db = storage::Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        let summary = import(&mut conn, &path).unwrap();
+
+        // The user renames the Loop calendar and moves Gym elsewhere
+        let gym = habits::list(&conn, Some(&summary.calendar_id)).unwrap()[0].clone();
+        let patch = calendars::CalendarPatch {
+            name: Some("Fitness".into()),
+            ..calendars::CalendarPatch::default()
+        };
+        calendars::update(&conn, &summary.calendar_id, patch).unwrap();
+        let other = calendars::create(
+            &conn,
+            NewCalendar {
+                name: "Other".into(),
+                color_theme: "red".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap();
+        habits::move_to_calendar(&mut conn, &gym.id, &other.id, None).unwrap();
+
+        let again = import(&mut conn, &path).unwrap();
+        assert_eq!(again.habits_created, 0);
+        assert_eq!(habits::list(&conn, None).unwrap().len(), 3);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn day_windows_map_to_months_approximately() {
+        let habit = LoopHabit {
+            id: 1,
+            uuid: None,
+            name: "Haircut".into(),
+            description: None,
+            archived: false,
+            numerical: false,
+            freq_num: 2,
+            freq_den: 30,
+            target_type: 0,
+            target_value: 0.0,
+            unit: None,
+            reminder: None,
+        };
+        assert_eq!(
+            recurrence(&habit),
+            (Recurrence::TimesPerMonth { count: 2 }, false)
+        );
+        let weekly = LoopHabit {
+            freq_den: 7,
+            ..habit
+        };
+        assert_eq!(
+            recurrence(&weekly),
+            (Recurrence::TimesPerWeek { count: 2 }, true)
+        );
+    }
+
+    #[test]
+    fn rejects_other_databases() {
+        let path = std::env::temp_dir().join(format!("not-loop-{}.db", new_id()));
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch("CREATE TABLE notes (id integer)")
+            .unwrap();
+        let db = storage::Database::open_in_memory().unwrap();
+        assert!(matches!(
+            import(&mut db.conn(), &path),
+            Err(Error::InvalidInput(_))
+        ));
+        let _ = std::fs::remove_file(path);
+    }
+}
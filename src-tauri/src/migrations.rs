@@ -0,0 +1,157 @@
+// Versioned schema migrations for the native database.
+// Migrations are embedded at compile time and applied in order at startup.
+// Each one is recorded in `_migrations` (the same table production-init.sql creates).
+
+use std::collections::HashSet;
+
+use rusqlite::Connection;
+
+use crate::error::{Error, Result};
+
+struct Migration {
+    name: &'static str,
+    sql: &'static str,
+}
+
+macro_rules! migration {
+    ($name:literal) => {
+        Migration {
+            name: $name,
+            sql: include_str!(concat!("../migrations/", $name, ".sql")),
+        }
+    };
+}
+
+// Append only. Names are applied in this order and must never be renamed.
+const MIGRATIONS: &[Migration] = &[
+    migration!("0001_initial"),
+    migration!("0002_completion_day_index"),
+    migration!("0003_settings"),
+    migration!("0004_reminders"),
+    migration!("0005_habit_recurrence"),
+    migration!("0006_quantities"),
+    migration!("0007_completion_duration"),
+    migration!("0008_pending_deletions"),
+];
+
+const CREATE_MIGRATIONS_TABLE: &str = "CREATE TABLE IF NOT EXISTS _migrations (
+  name TEXT PRIMARY KEY,
+  applied_at INTEGER DEFAULT (strftime('%s', 'now'))
+);";
+
+/// Applies all pending migrations in a single transaction.
+///
+/// Returns the names of the migrations that were applied. Fails with
+/// [`Error::SchemaTooNew`] if the database was written by a newer app version.
+pub fn run(conn: &mut Connection) -> Result<Vec<&'static str>> {
+    let tx = conn.transaction()?;
+    tx.execute_batch(CREATE_MIGRATIONS_TABLE)?;
+
+    let applied = applied(&tx)?;
+
+    // A migration we do not know about means a newer build touched this file;
+    // running older code against it could silently corrupt data.
+    check_known(&applied)?;
+
+    let mut newly_applied = Vec::new();
+    for migration in MIGRATIONS {
+        if applied.iter().any(|name| name == migration.name) {
+            continue;
+        }
+        tx.execute_batch(migration.sql)?;
+        tx.execute(
+            "INSERT INTO _migrations (name) VALUES (?1)",
+            [migration.name],
+        )?;
+        newly_applied.push(migration.name);
+    }
+
+    tx.commit()?;
+    Ok(newly_applied)
+}
+
+/// Names of the migrations applied to this database, in order.
+pub fn applied(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM _migrations ORDER BY name")?;
+    let names = stmt.query_map([], |row| row.get(0))?;
+    Ok(names.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Fails with [`Error::SchemaTooNew`] if `names` includes a migration this
+/// build does not know, i.e. the data comes from a newer app version.
+pub fn check_known(names: &[String]) -> Result<()> {
+    let known: HashSet<&str> = MIGRATIONS.iter().map(|m| m.name).collect();
+    match names.iter().find(|name| !known.contains(name.as_str())) {
+        Some(unknown) => Err(Error::SchemaTooNew(unknown.clone())),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantities_backfill_existing_completions() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // Database as left by a build that knew migrations up to 0005
+        conn.execute_batch(CREATE_MIGRATIONS_TABLE).unwrap();
+        for migration in &MIGRATIONS[..5] {
+            conn.execute_batch(migration.sql).unwrap();
+            conn.execute(
+                "INSERT INTO _migrations (name) VALUES (?1)",
+                [migration.name],
+            )
+            .unwrap();
+        }
+        // The parent habit is irrelevant here
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        conn.execute(
+            "INSERT INTO completions (id, habitId, completedAt, clientUpdatedAt)
+             VALUES ('c1', 'h1', 0, 0)",
+            [],
+        )
+        .unwrap();
+
+        assert_eq!(
+            run(&mut conn).unwrap(),
+            vec![
+                "0006_quantities",
+                "0007_completion_duration",
+                "0008_pending_deletions"
+            ]
+        );
+        let quantity: f64 = conn
+            .query_row(
+                "SELECT quantity FROM completions WHERE id = 'c1'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(quantity, 1.0);
+    }
+
+    #[test]
+    fn second_run_applies_nothing() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(run(&mut conn).unwrap().len(), MIGRATIONS.len());
+        assert!(run(&mut conn).unwrap().is_empty());
+        assert_eq!(applied(&conn).unwrap().len(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn unknown_applied_migration_is_schema_too_new() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO _migrations (name) VALUES ('9999_from_the_future')",
+            [],
+        )
+        .unwrap();
+
+        match run(&mut conn) {
+            Err(Error::SchemaTooNew(name)) => assert_eq!(name, "9999_from_the_future"),
+            other => panic!("expected SchemaTooNew, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,390 @@
+// End-of-day "streak at risk" nudge.
+//
+// From the configured check time until midnight, habits whose current streak
+// breaks at local midnight (see `Streak::at_risk_today`) trigger a single
+// notification. Quiet hours hold the nudge back, calendars can opt out, and
+// every habit is nudged at most once per day; the habits already nudged today
+// are kept under the `streakNudgesSent` settings key.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{NaiveDate, NaiveTime, TimeZone};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::days::{Clock, SystemClock};
+use crate::error::Result;
+use crate::storage::Database;
+use crate::{calendars, days, habits, settings, streaks};
+
+pub const AT_RISK_EVENT: &str = "streak://at-risk";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+const SETTINGS_KEY: &str = "streakNudges";
+const SENT_KEY: &str = "streakNudgesSent";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NudgeSettings {
+    pub enabled: bool,
+    /// Earliest local time the nudge goes out, `HH:MM`.
+    #[serde(with = "days::hh_mm")]
+    pub check_time: NaiveTime,
+    pub quiet_hours: Option<QuietHours>,
+    /// Calendars whose habits are never nudged about.
+    pub disabled_calendar_ids: Vec<String>,
+}
+
+impl Default for NudgeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            check_time: NaiveTime::from_hms_opt(20, 0, 0).unwrap_or_default(),
+            quiet_hours: None,
+            disabled_calendar_ids: Vec::new(),
+        }
+    }
+}
+
+/// Local time range without nudges; `end` before `start` spans midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuietHours {
+    #[serde(with = "days::hh_mm")]
+    pub start: NaiveTime,
+    #[serde(with = "days::hh_mm")]
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+// Habits already nudged about on `date`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct SentNudges {
+    date: Option<NaiveDate>,
+    habit_ids: Vec<String>,
+}
+
+/// One habit in a nudge; payload entry of `streak://at-risk`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AtRiskHabit {
+    pub habit_id: String,
+    pub habit_name: String,
+    pub calendar_id: String,
+    pub current_streak: u32,
+}
+
+/// Enabled habits whose streak ends tonight in `tz`, skipping disabled and
+/// opted-out calendars.
+pub fn at_risk<Tz: TimeZone>(
+    conn: &Connection,
+    tz: &Tz,
+    today: NaiveDate,
+    disabled_calendar_ids: &[String],
+) -> Result<Vec<AtRiskHabit>> {
+    let calendars: HashMap<String, bool> = calendars::list(conn)?
+        .into_iter()
+        .map(|calendar| {
+            let nudged = calendar.is_enabled && !disabled_calendar_ids.contains(&calendar.id);
+            (calendar.id, nudged)
+        })
+        .collect();
+    let streaks: HashMap<String, streaks::Streak> = streaks::for_habits(conn, tz, None, today)?
+        .into_iter()
+        .map(|s| (s.habit_id, s.streak))
+        .collect();
+
+    Ok(habits::list(conn, None)?
+        .into_iter()
+        .filter(|habit| habit.is_enabled)
+        .filter(|habit| calendars.get(&habit.calendar_id).copied().unwrap_or(false))
+        .filter_map(|habit| {
+            let streak = streaks.get(&habit.id).filter(|s| s.at_risk_today)?;
+            Some(AtRiskHabit {
+                current_streak: streak.current_streak,
+                habit_id: habit.id,
+                habit_name: habit.name,
+                calendar_id: habit.calendar_id,
+            })
+        })
+        .collect())
+}
+
+/// Decides when the nudge goes out and for which habits.
+pub struct Nudger<C: Clock> {
+    clock: C,
+}
+
+impl<C: Clock> Nudger<C> {
+    pub fn new(clock: C) -> Self {
+        Self { clock }
+    }
+
+    /// Habits to nudge about right now, recorded as sent. Empty outside the
+    /// nudge window, during quiet hours, or when everything was already sent.
+    pub fn tick(&self, conn: &Connection) -> Result<Vec<AtRiskHabit>> {
+        let nudge_settings: NudgeSettings = settings::get(conn, SETTINGS_KEY)?;
+        let now = self.clock.now();
+        let time = now.time();
+        let quiet = nudge_settings
+            .quiet_hours
+            .is_some_and(|quiet| quiet.contains(time));
+        if !nudge_settings.enabled || time < nudge_settings.check_time || quiet {
+            return Ok(Vec::new());
+        }
+
+        let today = now.date_naive();
+        let mut sent: SentNudges = settings::get(conn, SENT_KEY)?;
+        if sent.date != Some(today) {
+            sent = SentNudges {
+                date: Some(today),
+                habit_ids: Vec::new(),
+            };
+        }
+
+        let due: Vec<AtRiskHabit> = at_risk(
+            conn,
+            &now.timezone(),
+            today,
+            &nudge_settings.disabled_calendar_ids,
+        )?
+        .into_iter()
+        .filter(|habit| !sent.habit_ids.contains(&habit.habit_id))
+        .collect();
+        if !due.is_empty() {
+            sent.habit_ids
+                .extend(due.iter().map(|habit| habit.habit_id.clone()));
+            settings::set(conn, SENT_KEY, &sent)?;
+        }
+        Ok(due)
+    }
+}
+
+/// Starts the background thread that sends the evening nudge.
+pub fn start_service<R: Runtime>(app: AppHandle<R>) -> std::io::Result<()> {
+    std::thread::Builder::new()
+        .name("habistat-nudges".into())
+        .spawn(move || {
+            let nudger = Nudger::new(SystemClock);
+            loop {
+                std::thread::sleep(POLL_INTERVAL);
+                let db = app.state::<Database>();
+                let due = nudger.tick(&db.conn());
+                match due {
+                    Ok(due) if !due.is_empty() => {
+                        notify(&app, &due);
+                        let _ = app.emit(AT_RISK_EVENT, due);
+                    }
+                    Ok(_) => {}
+                    Err(e) => eprintln!("Streak nudge check failed: {e}"),
+                }
+            }
+        })?;
+    Ok(())
+}
+
+fn notify<R: Runtime>(app: &AppHandle<R>, habits: &[AtRiskHabit]) {
+    let body = match habits {
+        [habit] => format!(
+            "Complete \"{}\" before midnight to keep your {}-day streak.",
+            habit.habit_name, habit.current_streak
+        ),
+        _ => {
+            let names: Vec<String> = habits
+                .iter()
+                .map(|habit| format!("{} ({} days)", habit.habit_name, habit.current_streak))
+                .collect();
+            format!("These streaks end at midnight: {}", names.join(", "))
+        }
+    };
+    let shown = app
+        .notification()
+        .builder()
+        .title("Streak at risk")
+        .body(body)
+        .show();
+    if let Err(e) = shown {
+        eprintln!("Failed to show streak nudge: {e}");
+    }
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn get_nudge_settings(db: State<'_, Database>) -> Result<NudgeSettings> {
+    settings::get(&db.conn(), SETTINGS_KEY)
+}
+
+#[tauri::command]
+pub fn set_nudge_settings(db: State<'_, Database>, nudge_settings: NudgeSettings) -> Result<()> {
+    settings::set(&db.conn(), SETTINGS_KEY, &nudge_settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    use chrono::{DateTime, FixedOffset, Local};
+
+    use crate::calendars::NewCalendar;
+    use crate::habits::Habit;
+    use crate::{completions, storage};
+
+    struct ManualClock<Tz: TimeZone>(Cell<DateTime<Tz>>);
+
+    impl<Tz: TimeZone> Clock for &ManualClock<Tz>
+    where
+        DateTime<Tz>: Copy,
+    {
+        type Tz = Tz;
+
+        fn now(&self) -> DateTime<Tz> {
+            self.0.get()
+        }
+    }
+
+    fn local(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2024, 6, day, hour, minute, 0)
+            .single()
+            .unwrap()
+    }
+
+    fn habit(conn: &Connection, calendar: &str, name: &str) -> Habit {
+        let calendar = match calendars::list(conn)
+            .unwrap()
+            .into_iter()
+            .find(|c| c.name == calendar)
+        {
+            Some(calendar) => calendar,
+            None => calendars::create(
+                conn,
+                NewCalendar {
+                    name: calendar.into(),
+                    color_theme: "green".into(),
+                    user_id: None,
+                    position: None,
+                },
+            )
+            .unwrap(),
+        };
+        habits::tests_support::new_habit(conn, &calendar.id, name)
+    }
+
+    fn complete<Tz: TimeZone>(conn: &Connection, habit: &Habit, at: DateTime<Tz>) {
+        completions::log(conn, &habit.id, Some(at.timestamp_millis()), None).unwrap();
+    }
+
+    fn names(habits: &[AtRiskHabit]) -> Vec<&str> {
+        habits.iter().map(|h| h.habit_name.as_str()).collect()
+    }
+
+    #[test]
+    fn nudges_only_streaks_ending_tonight_once() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let read = habit(&conn, "Mind", "Read");
+        let walk = habit(&conn, "Mind", "Walk");
+        habit(&conn, "Mind", "Never started");
+        complete(&conn, &read, local(10, 9, 0));
+        complete(&conn, &read, local(11, 9, 0));
+        complete(&conn, &walk, local(11, 9, 0));
+        complete(&conn, &walk, local(12, 8, 0));
+
+        let clock = ManualClock(Cell::new(local(12, 19, 59)));
+        let nudger = Nudger::new(&clock);
+        assert!(nudger.tick(&conn).unwrap().is_empty());
+
+        clock.0.set(local(12, 20, 0));
+        let due = nudger.tick(&conn).unwrap();
+        assert_eq!(names(&due), vec!["Read"]);
+        assert_eq!(due[0].current_streak, 2);
+
+        clock.0.set(local(12, 21, 0));
+        assert!(nudger.tick(&conn).unwrap().is_empty());
+
+        // A new day starts a fresh dedupe record
+        complete(&conn, &read, local(12, 22, 0));
+        clock.0.set(local(13, 20, 30));
+        assert_eq!(names(&nudger.tick(&conn).unwrap()), vec!["Read", "Walk"]);
+    }
+
+    #[test]
+    fn respects_quiet_hours_and_calendar_opt_out() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let read = habit(&conn, "Mind", "Read");
+        let lift = habit(&conn, "Body", "Lift");
+        complete(&conn, &read, local(11, 9, 0));
+        complete(&conn, &lift, local(11, 9, 0));
+
+        let nudge_settings = NudgeSettings {
+            check_time: NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            quiet_hours: Some(QuietHours {
+                start: NaiveTime::from_hms_opt(18, 30, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(21, 0, 0).unwrap(),
+            }),
+            disabled_calendar_ids: vec![lift.calendar_id.clone()],
+            ..NudgeSettings::default()
+        };
+        settings::set(&conn, SETTINGS_KEY, &nudge_settings).unwrap();
+
+        let clock = ManualClock(Cell::new(local(12, 19, 0)));
+        let nudger = Nudger::new(&clock);
+        assert!(nudger.tick(&conn).unwrap().is_empty());
+
+        clock.0.set(local(12, 21, 0));
+        assert_eq!(names(&nudger.tick(&conn).unwrap()), vec!["Read"]);
+    }
+
+    #[test]
+    fn counts_days_in_the_clock_timezone() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let read = habit(&conn, "Mind", "Read");
+        // Far enough east that 00:30 is still the previous day almost anywhere else
+        let kiribati = FixedOffset::east_opt(14 * 3600).unwrap();
+        let at = |day, hour, minute| {
+            kiribati
+                .with_ymd_and_hms(2024, 6, day, hour, minute, 0)
+                .single()
+                .unwrap()
+        };
+        complete(&conn, &read, at(11, 9, 0));
+        complete(&conn, &read, at(12, 0, 30));
+
+        let clock = ManualClock(Cell::new(at(12, 21, 0)));
+        let nudger = Nudger::new(&clock);
+        assert!(nudger.tick(&conn).unwrap().is_empty());
+
+        clock.0.set(at(13, 21, 0));
+        let due = nudger.tick(&conn).unwrap();
+        assert_eq!(names(&due), vec!["Read"]);
+        assert_eq!(due[0].current_streak, 2);
+    }
+
+    #[test]
+    fn quiet_hours_can_span_midnight() {
+        let quiet = QuietHours {
+            start: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+        };
+        assert!(quiet.contains(NaiveTime::from_hms_opt(23, 15, 0).unwrap()));
+        assert!(quiet.contains(NaiveTime::from_hms_opt(6, 59, 0).unwrap()));
+        assert!(!quiet.contains(NaiveTime::from_hms_opt(21, 59, 0).unwrap()));
+    }
+}
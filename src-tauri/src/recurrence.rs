@@ -0,0 +1,357 @@
+// How often a habit is meant to be done.
+//
+// A recurrence splits the calendar into periods, each needing a number of
+// distinct completion days: every day, every N days (counted from when the
+// habit was created), chosen weekdays, or X days per week/month. Streaks count
+// satisfied periods, so a 3x-per-week habit is not "broken" on its off days.
+//
+// Stored as JSON in `habits.recurrence`; `NULL` means daily, which keeps rows
+// created by older builds and by Convex sync valid. Besides the JSON model the
+// commands accept an RRULE subset (`FREQ=DAILY;INTERVAL=n`,
+// `FREQ=WEEKLY;BYDAY=MO,WE,FR`).
+
+use std::collections::BTreeSet;
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+const RRULE_DAYS: [&str; 7] = ["SU", "MO", "TU", "WE", "TH", "FR", "SA"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Recurrence {
+    #[default]
+    Daily,
+    /// Once in every window of `interval` days, starting on the creation day.
+    EveryNDays { interval: u32 },
+    /// On the given days, 0 = Sunday ... 6 = Saturday.
+    Weekdays { days: Vec<u8> },
+    /// On `count` distinct days of each Monday-to-Sunday week.
+    TimesPerWeek { count: u32 },
+    /// On `count` distinct days of each calendar month.
+    TimesPerMonth { count: u32 },
+}
+
+/// Recurrence as sent by the webview: the JSON model or an RRULE string.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum RecurrenceInput {
+    Rule(String),
+    Model(Recurrence),
+}
+
+impl RecurrenceInput {
+    pub fn into_recurrence(self) -> Result<Recurrence> {
+        let recurrence = match self {
+            RecurrenceInput::Rule(rule) => Recurrence::from_rrule(&rule)?,
+            RecurrenceInput::Model(recurrence) => recurrence,
+        };
+        recurrence.validate()?;
+        Ok(recurrence)
+    }
+}
+
+/// Inclusive date range that needs `required` distinct completion days.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Period {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub required: u32,
+}
+
+impl Period {
+    fn single(date: NaiveDate) -> Self {
+        Period {
+            start: date,
+            end: date,
+            required: 1,
+        }
+    }
+
+    /// Distinct completion days inside the period, ignoring days after `today`.
+    pub fn done(&self, days: &BTreeSet<NaiveDate>, today: NaiveDate) -> u32 {
+        if self.start > today {
+            return 0;
+        }
+        days.range(self.start..=self.end.min(today)).count() as u32
+    }
+
+    pub fn is_satisfied(&self, days: &BTreeSet<NaiveDate>, today: NaiveDate) -> bool {
+        self.done(days, today) >= self.required
+    }
+}
+
+impl Recurrence {
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |message: &str| Err(Error::InvalidInput(message.into()));
+        match self {
+            Recurrence::Daily => Ok(()),
+            Recurrence::EveryNDays { interval } if *interval == 0 => {
+                invalid("recurrence interval must be at least 1 day")
+            }
+            Recurrence::Weekdays { days } if days.is_empty() => {
+                invalid("recurrence needs at least one weekday")
+            }
+            Recurrence::Weekdays { days } if days.iter().any(|&day| day > 6) => {
+                invalid("recurrence weekdays must be 0 (Sunday) to 6 (Saturday)")
+            }
+            Recurrence::TimesPerWeek { count } if !(1..=7).contains(count) => {
+                invalid("times per week must be between 1 and 7")
+            }
+            // Every month has at least 28 days
+            Recurrence::TimesPerMonth { count } if !(1..=28).contains(count) => {
+                invalid("times per month must be between 1 and 28")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Parses the supported RRULE subset: `FREQ=DAILY` with an optional
+    /// `INTERVAL`, and `FREQ=WEEKLY` with `BYDAY`. An `RRULE:` prefix is allowed.
+    pub fn from_rrule(rule: &str) -> Result<Self> {
+        let unsupported = || Error::InvalidInput(format!("unsupported RRULE `{rule}`"));
+        let body = rule.trim();
+        let body = body.strip_prefix("RRULE:").unwrap_or(body);
+
+        let (mut freq, mut interval, mut by_day) = (None, 1, None);
+        for part in body.split(';').filter(|part| !part.is_empty()) {
+            let (key, value) = part.split_once('=').ok_or_else(unsupported)?;
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => freq = Some(value.to_ascii_uppercase()),
+                "INTERVAL" => {
+                    interval = value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .ok_or_else(unsupported)?
+                }
+                "BYDAY" => {
+                    let days = value
+                        .split(',')
+                        .map(|day| {
+                            RRULE_DAYS
+                                .iter()
+                                .position(|d| d.eq_ignore_ascii_case(day.trim()))
+                                .map(|index| index as u8)
+                        })
+                        .collect::<Option<BTreeSet<u8>>>()
+                        .ok_or_else(unsupported)?;
+                    by_day = Some(days.into_iter().collect::<Vec<_>>());
+                }
+                _ => return Err(unsupported()),
+            }
+        }
+
+        let recurrence = match (freq.as_deref(), interval, by_day) {
+            (Some("DAILY"), 1, None) => Recurrence::Daily,
+            (Some("DAILY"), interval, None) => Recurrence::EveryNDays { interval },
+            (Some("WEEKLY"), 1, Some(days)) => Recurrence::Weekdays { days },
+            _ => return Err(unsupported()),
+        };
+        recurrence.validate()?;
+        Ok(recurrence)
+    }
+
+    /// Short English description, e.g. "3 times per week".
+    pub fn describe(&self) -> String {
+        match self {
+            Recurrence::Daily => "daily".into(),
+            Recurrence::EveryNDays { interval } => format!("every {interval} days"),
+            Recurrence::Weekdays { days } => format!("{} weekdays", days.len()),
+            Recurrence::TimesPerWeek { count } => format!("{count} times per week"),
+            Recurrence::TimesPerMonth { count } => format!("{count} times per month"),
+        }
+    }
+
+    /// The RRULE equivalent, if the recurrence can be expressed as one.
+    pub fn to_rrule(&self) -> Option<String> {
+        match self {
+            Recurrence::Daily => Some("FREQ=DAILY".into()),
+            Recurrence::EveryNDays { interval } => Some(format!("FREQ=DAILY;INTERVAL={interval}")),
+            Recurrence::Weekdays { days } => {
+                let days: Vec<&str> = days.iter().map(|&d| RRULE_DAYS[d as usize % 7]).collect();
+                Some(format!("FREQ=WEEKLY;BYDAY={}", days.join(",")))
+            }
+            Recurrence::TimesPerWeek { .. } | Recurrence::TimesPerMonth { .. } => None,
+        }
+    }
+
+    /// The period `date` falls in, `None` on days without one (weekdays off).
+    /// `anchor` is the habit's first day, which every-N-days windows count from.
+    pub fn period_at(&self, date: NaiveDate, anchor: NaiveDate) -> Option<Period> {
+        match self {
+            Recurrence::Daily => Some(Period::single(date)),
+            Recurrence::EveryNDays { interval } => {
+                let interval = i64::from((*interval).max(1));
+                let offset = (date - anchor).num_days().rem_euclid(interval);
+                let start = date - Duration::days(offset);
+                Some(Period {
+                    start,
+                    end: start + Duration::days(interval - 1),
+                    required: 1,
+                })
+            }
+            Recurrence::Weekdays { days } => {
+                let day = date.weekday().num_days_from_sunday() as u8;
+                days.contains(&day).then(|| Period::single(date))
+            }
+            Recurrence::TimesPerWeek { count } => {
+                let week = date.week(Weekday::Mon);
+                Some(Period {
+                    start: week.first_day(),
+                    end: week.last_day(),
+                    required: *count,
+                })
+            }
+            Recurrence::TimesPerMonth { count } => {
+                let start = date.with_day(1)?;
+                let next = start.checked_add_months(chrono::Months::new(1))?;
+                Some(Period {
+                    start,
+                    end: next - Duration::days(1),
+                    required: *count,
+                })
+            }
+        }
+    }
+
+    /// The latest period that ends before `date`.
+    pub fn period_before(&self, date: NaiveDate, anchor: NaiveDate) -> Option<Period> {
+        // Weekday schedules may skip up to six days
+        (1..=7).find_map(|back| self.period_at(date - Duration::days(back), anchor))
+    }
+
+    /// Whether the habit still needs doing today: today is in a period that is
+    /// not yet satisfied and has no completion today.
+    pub fn is_due(&self, today: NaiveDate, anchor: NaiveDate, days: &BTreeSet<NaiveDate>) -> bool {
+        match self.period_at(today, anchor) {
+            Some(period) => !days.contains(&today) && !period.is_satisfied(days, today),
+            None => false,
+        }
+    }
+}
+
+impl ToSql for Recurrence {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match self {
+            Recurrence::Daily => Ok(ToSqlOutput::from(rusqlite::types::Null)),
+            other => serde_json::to_string(other)
+                .map(ToSqlOutput::from)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e))),
+        }
+    }
+}
+
+impl FromSql for Recurrence {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Null => Ok(Recurrence::Daily),
+            value => {
+                serde_json::from_str(value.as_str()?).map_err(|e| FromSqlError::Other(Box::new(e)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_rrule_subset() {
+        assert_eq!(
+            Recurrence::from_rrule("FREQ=DAILY").unwrap(),
+            Recurrence::Daily
+        );
+        assert_eq!(
+            Recurrence::from_rrule("RRULE:FREQ=DAILY;INTERVAL=3").unwrap(),
+            Recurrence::EveryNDays { interval: 3 }
+        );
+        let weekly = Recurrence::from_rrule("FREQ=WEEKLY;BYDAY=FR,MO,WE").unwrap();
+        assert_eq!(
+            weekly,
+            Recurrence::Weekdays {
+                days: vec![1, 3, 5]
+            }
+        );
+        assert_eq!(weekly.to_rrule().unwrap(), "FREQ=WEEKLY;BYDAY=MO,WE,FR");
+
+        assert!(Recurrence::from_rrule("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO").is_err());
+        assert!(Recurrence::from_rrule("FREQ=MONTHLY").is_err());
+        assert!(Recurrence::from_rrule("FREQ=DAILY;COUNT=5").is_err());
+    }
+
+    #[test]
+    fn accepts_model_or_rule_input() {
+        let input: RecurrenceInput =
+            serde_json::from_str(r#"{"kind": "timesPerWeek", "count": 3}"#).unwrap();
+        assert_eq!(
+            input.into_recurrence().unwrap(),
+            Recurrence::TimesPerWeek { count: 3 }
+        );
+        let input: RecurrenceInput = serde_json::from_str(r#""FREQ=DAILY;INTERVAL=2""#).unwrap();
+        assert_eq!(
+            input.into_recurrence().unwrap(),
+            Recurrence::EveryNDays { interval: 2 }
+        );
+        let input: RecurrenceInput =
+            serde_json::from_str(r#"{"kind": "timesPerMonth", "count": 30}"#).unwrap();
+        assert!(input.into_recurrence().is_err());
+    }
+
+    #[test]
+    fn periods() {
+        let anchor = date("2024-03-01");
+        let every_three = Recurrence::EveryNDays { interval: 3 };
+        let period = every_three.period_at(date("2024-03-05"), anchor).unwrap();
+        assert_eq!(
+            (period.start, period.end),
+            (date("2024-03-04"), date("2024-03-06"))
+        );
+        let before = every_three.period_before(period.start, anchor).unwrap();
+        assert_eq!(before.start, date("2024-03-01"));
+
+        // 2024-03-20 is a Wednesday
+        let weekly = Recurrence::TimesPerWeek { count: 3 };
+        let period = weekly.period_at(date("2024-03-20"), anchor).unwrap();
+        assert_eq!(
+            (period.start, period.end),
+            (date("2024-03-18"), date("2024-03-24"))
+        );
+
+        let monthly = Recurrence::TimesPerMonth { count: 2 };
+        let period = monthly.period_at(date("2024-02-10"), anchor).unwrap();
+        assert_eq!(
+            (period.start, period.end),
+            (date("2024-02-01"), date("2024-02-29"))
+        );
+
+        let mon_fri = Recurrence::Weekdays { days: vec![1, 5] };
+        assert_eq!(mon_fri.period_at(date("2024-03-20"), anchor), None);
+        let before = mon_fri.period_before(date("2024-03-20"), anchor).unwrap();
+        assert_eq!(before.start, date("2024-03-18"));
+    }
+
+    #[test]
+    fn due_only_while_the_period_needs_more() {
+        let anchor = date("2024-03-01");
+        let weekly = Recurrence::TimesPerWeek { count: 2 };
+        let mut days = BTreeSet::from([date("2024-03-18")]);
+        assert!(weekly.is_due(date("2024-03-20"), anchor, &days));
+        days.insert(date("2024-03-20"));
+        assert!(!weekly.is_due(date("2024-03-20"), anchor, &days));
+        assert!(!weekly.is_due(date("2024-03-21"), anchor, &days));
+        assert!(weekly.is_due(date("2024-03-25"), anchor, &days));
+    }
+}
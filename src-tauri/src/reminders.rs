@@ -0,0 +1,594 @@
+// Per-habit reminders and the scheduler thread that fires them.
+//
+// A reminder fires once on each of its weekdays at its local time, unless the
+// habit is not due that day: already completed, or not needed by its
+// recurrence (e.g. a 3x-per-week habit that already met this week's count).
+// Desktop notifications from tauri-plugin-notification carry no action
+// buttons, so every firing is also emitted as `reminder://fired`; the webview
+// offers "snooze" and "mark done" from there through `snooze_reminder` and
+// `complete_reminder`.
+//
+// The scheduler reads the time from a `days::Clock` so tests can move it by hand.
+
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDate, NaiveTime, TimeZone, Timelike};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::completions::{self, Completion};
+use crate::days::{self, Clock, SystemClock};
+use crate::error::{Error, Result};
+use crate::habits;
+use crate::storage::{new_id, now_millis, Database};
+use crate::streaks;
+
+pub const FIRED_EVENT: &str = "reminder://fired";
+
+pub const DEFAULT_SNOOZE_MINUTES: i64 = 10;
+
+/// A reminder whose time passed longer ago than this (e.g. while the app was
+/// closed) is skipped for the day rather than fired late.
+pub const MAX_LATENESS_MILLIS: i64 = 60 * 60 * 1000;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+const ALL_WEEKDAYS: i64 = 0b111_1111;
+
+const COLUMNS: &str =
+    "id, habitId, timeOfDay, weekdays, isEnabled, snoozedUntil, lastFiredAt, createdAt, updatedAt";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reminder {
+    pub id: String,
+    pub habit_id: String,
+    /// Local time of day, serialized as `HH:MM`.
+    #[serde(serialize_with = "days::hh_mm::serialize")]
+    pub time: NaiveTime,
+    /// Days the reminder fires on, 0 = Sunday ... 6 = Saturday.
+    pub weekdays: Vec<u8>,
+    pub is_enabled: bool,
+    pub snoozed_until: Option<i64>,
+    pub last_fired_at: Option<i64>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl Reminder {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        let minutes: u32 = row.get(2)?;
+        let mask: i64 = row.get(3)?;
+        Ok(Self {
+            id: row.get(0)?,
+            habit_id: row.get(1)?,
+            time: NaiveTime::from_num_seconds_from_midnight_opt(minutes * 60, 0)
+                .unwrap_or_default(),
+            weekdays: (0..7).filter(|day| mask & (1 << day) != 0).collect(),
+            is_enabled: row.get(4)?,
+            snoozed_until: row.get(5)?,
+            last_fired_at: row.get(6)?,
+            created_at: row.get(7)?,
+            updated_at: row.get(8)?,
+        })
+    }
+
+    fn fires_on(&self, date: NaiveDate) -> bool {
+        let day = date.weekday().num_days_from_sunday() as u8;
+        self.weekdays.contains(&day)
+    }
+}
+
+/// Input for [`create`]. `weekdays` defaults to every day.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewReminder {
+    pub habit_id: String,
+    /// `HH:MM`, local time.
+    pub time: String,
+    pub weekdays: Option<Vec<u8>>,
+}
+
+/// Fields [`update`] may change; `None` leaves the column untouched.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReminderPatch {
+    pub time: Option<String>,
+    pub weekdays: Option<Vec<u8>>,
+    pub is_enabled: Option<bool>,
+}
+
+/// A reminder the scheduler just fired; payload of `reminder://fired`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FiredReminder {
+    pub reminder: Reminder,
+    pub habit_name: String,
+    /// When the reminder was due: its local time today, or the end of a snooze.
+    pub scheduled_for: i64,
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime> {
+    days::hh_mm::parse(time).ok_or_else(|| {
+        Error::InvalidInput(format!("invalid reminder time `{time}`, expected HH:MM"))
+    })
+}
+
+fn weekday_mask(weekdays: &[u8]) -> Result<i64> {
+    if weekdays.is_empty() {
+        return Err(Error::InvalidInput(
+            "a reminder needs at least one weekday".into(),
+        ));
+    }
+    weekdays.iter().try_fold(0, |mask, &day| {
+        if day > 6 {
+            return Err(Error::InvalidInput(format!(
+                "invalid weekday {day}, expected 0 (Sunday) to 6 (Saturday)"
+            )));
+        }
+        Ok(mask | 1 << day)
+    })
+}
+
+fn minutes_of(time: NaiveTime) -> i64 {
+    i64::from(time.num_seconds_from_midnight() / 60)
+}
+
+/// Reminders ordered by time of day, optionally limited to one habit.
+pub fn list(conn: &Connection, habit_id: Option<&str>) -> Result<Vec<Reminder>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {COLUMNS} FROM reminders
+         WHERE ?1 IS NULL OR habitId = ?1
+         ORDER BY timeOfDay, createdAt"
+    ))?;
+    let rows = stmt.query_map([habit_id], Reminder::from_row)?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+pub fn get(conn: &Connection, id: &str) -> Result<Reminder> {
+    conn.query_row(
+        &format!("SELECT {COLUMNS} FROM reminders WHERE id = ?1"),
+        [id],
+        Reminder::from_row,
+    )
+    .optional()?
+    .ok_or_else(|| Error::not_found("reminder", id))
+}
+
+pub fn create(conn: &Connection, input: NewReminder) -> Result<Reminder> {
+    habits::get(conn, &input.habit_id)?;
+    let time = parse_time(&input.time)?;
+    let weekdays = match &input.weekdays {
+        Some(weekdays) => weekday_mask(weekdays)?,
+        None => ALL_WEEKDAYS,
+    };
+
+    let id = new_id();
+    conn.execute(
+        "INSERT INTO reminders (id, habitId, timeOfDay, weekdays, isEnabled, createdAt, updatedAt)
+         VALUES (?1, ?2, ?3, ?4, 1, ?5, ?5)",
+        params![id, input.habit_id, minutes_of(time), weekdays, now_millis()],
+    )?;
+    get(conn, &id)
+}
+
+pub fn update(conn: &Connection, id: &str, patch: ReminderPatch) -> Result<Reminder> {
+    let mut reminder = get(conn, id)?;
+    if let Some(time) = patch.time {
+        reminder.time = parse_time(&time)?;
+    }
+    if let Some(weekdays) = patch.weekdays {
+        weekday_mask(&weekdays)?;
+        reminder.weekdays = weekdays;
+    }
+    if let Some(enabled) = patch.is_enabled {
+        reminder.is_enabled = enabled;
+    }
+    reminder.updated_at = now_millis();
+
+    conn.execute(
+        "UPDATE reminders SET timeOfDay = ?2, weekdays = ?3, isEnabled = ?4, updatedAt = ?5
+         WHERE id = ?1",
+        params![
+            id,
+            minutes_of(reminder.time),
+            weekday_mask(&reminder.weekdays)?,
+            reminder.is_enabled,
+            reminder.updated_at
+        ],
+    )?;
+    get(conn, id)
+}
+
+pub fn delete(conn: &Connection, id: &str) -> Result<()> {
+    if conn.execute("DELETE FROM reminders WHERE id = ?1", [id])? == 0 {
+        return Err(Error::not_found("reminder", id));
+    }
+    Ok(())
+}
+
+/// Fires the reminder again at `until`, even if it already fired today.
+pub fn snooze(conn: &Connection, id: &str, until: i64) -> Result<Reminder> {
+    get(conn, id)?;
+    conn.execute(
+        "UPDATE reminders SET snoozedUntil = ?2, updatedAt = ?3 WHERE id = ?1",
+        params![id, until, now_millis()],
+    )?;
+    get(conn, id)
+}
+
+/// Logs a completion for the reminder's habit and drops any pending snooze.
+pub fn complete(conn: &mut Connection, id: &str, user_id: Option<&str>) -> Result<Completion> {
+    let tx = conn.transaction()?;
+    let reminder = get(&tx, id)?;
+    let completion = completions::log(&tx, &reminder.habit_id, None, user_id)?;
+    tx.execute(
+        "UPDATE reminders SET snoozedUntil = NULL, updatedAt = ?2 WHERE id = ?1",
+        params![id, completion.client_updated_at],
+    )?;
+    tx.commit()?;
+    Ok(completion)
+}
+
+/// Decides which reminders are due at `now` and records them as fired.
+pub struct Scheduler<C: Clock> {
+    clock: C,
+}
+
+impl<C: Clock> Scheduler<C> {
+    pub fn new(clock: C) -> Self {
+        Self { clock }
+    }
+
+    /// Returns the reminders due since the last tick, marking them fired so
+    /// each one fires once per day (plus once per snooze).
+    ///
+    /// Reminders of disabled habits or calendars never fire. A habit that is
+    /// not due today is skipped and its pending snooze dropped.
+    pub fn tick(&self, conn: &mut Connection) -> Result<Vec<FiredReminder>> {
+        let now = self.clock.now();
+        let now_ms = now.timestamp_millis();
+        let today = now.date_naive();
+        let tz = now.timezone();
+
+        let tx = conn.transaction()?;
+        let candidates: Vec<(Reminder, String)> = {
+            let mut stmt = tx.prepare(&format!(
+                "SELECT {COLUMNS}, habitName FROM (
+                    SELECT r.*, h.name AS habitName FROM reminders r
+                    JOIN habits h ON h.id = r.habitId
+                    JOIN calendars c ON c.id = h.calendarId
+                    WHERE r.isEnabled = 1 AND h.isEnabled = 1 AND c.isEnabled = 1
+                 )
+                 ORDER BY timeOfDay, createdAt"
+            ))?;
+            let rows = stmt.query_map([], |row| Ok((Reminder::from_row(row)?, row.get(9)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        let mut fired = Vec::new();
+        for (reminder, habit_name) in candidates {
+            let due_at = match reminder.snoozed_until {
+                Some(until) => (until <= now_ms).then_some(until),
+                None if reminder.fires_on(today) => {
+                    let at = scheduled_at(&tz, today, reminder.time);
+                    let missed = reminder.last_fired_at.is_none_or(|fired| fired < at);
+                    (at <= now_ms && now_ms - at <= MAX_LATENESS_MILLIS && missed).then_some(at)
+                }
+                None => None,
+            };
+            let Some(scheduled_for) = due_at else {
+                continue;
+            };
+
+            // Done today, or the habit's recurrence does not need it today
+            let habit = habits::get(&tx, &reminder.habit_id)?;
+            let done = streaks::completion_days(&tx, &tz, Some(&habit.id))?
+                .remove(&habit.id)
+                .unwrap_or_default();
+            let anchor = days::date_of(&tz, habit.created_at);
+            if !habit.recurrence.is_due(today, anchor, &done) {
+                if reminder.snoozed_until.is_some() {
+                    tx.execute(
+                        "UPDATE reminders SET snoozedUntil = NULL WHERE id = ?1",
+                        [&reminder.id],
+                    )?;
+                }
+                continue;
+            }
+
+            tx.execute(
+                "UPDATE reminders SET lastFiredAt = ?2, snoozedUntil = NULL WHERE id = ?1",
+                params![reminder.id, now_ms],
+            )?;
+            fired.push(FiredReminder {
+                reminder: Reminder {
+                    last_fired_at: Some(now_ms),
+                    snoozed_until: None,
+                    ..reminder
+                },
+                habit_name,
+                scheduled_for,
+            });
+        }
+        tx.commit()?;
+        Ok(fired)
+    }
+}
+
+// A time skipped by a DST transition fires at the first valid minute after it
+fn scheduled_at<Tz: TimeZone>(tz: &Tz, date: NaiveDate, time: NaiveTime) -> i64 {
+    let mut local = date.and_time(time);
+    for _ in 0..24 * 60 {
+        if let Some(at) = tz.from_local_datetime(&local).earliest() {
+            return at.timestamp_millis();
+        }
+        local += chrono::Duration::minutes(1);
+    }
+    local.and_utc().timestamp_millis()
+}
+
+/// Starts the background thread that shows due reminders as notifications.
+pub fn start_service<R: Runtime>(app: AppHandle<R>) -> std::io::Result<()> {
+    std::thread::Builder::new()
+        .name("habistat-reminders".into())
+        .spawn(move || {
+            let scheduler = Scheduler::new(SystemClock);
+            loop {
+                std::thread::sleep(POLL_INTERVAL);
+                let db = app.state::<Database>();
+                let fired = scheduler.tick(&mut db.conn());
+                match fired {
+                    Ok(fired) => {
+                        for reminder in fired {
+                            notify(&app, &reminder);
+                            let _ = app.emit(FIRED_EVENT, reminder);
+                        }
+                    }
+                    Err(e) => eprintln!("Reminder check failed: {e}"),
+                }
+            }
+        })?;
+    Ok(())
+}
+
+fn notify<R: Runtime>(app: &AppHandle<R>, fired: &FiredReminder) {
+    let shown = app
+        .notification()
+        .builder()
+        .title(&fired.habit_name)
+        .body("Not done yet today. Open Habistat to mark it done or snooze.")
+        .show();
+    if let Err(e) = shown {
+        eprintln!("Failed to show reminder notification: {e}");
+    }
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn list_reminders(db: State<'_, Database>, habit_id: Option<String>) -> Result<Vec<Reminder>> {
+    list(&db.conn(), habit_id.as_deref())
+}
+
+#[tauri::command]
+pub fn create_reminder(db: State<'_, Database>, input: NewReminder) -> Result<Reminder> {
+    create(&db.conn(), input)
+}
+
+#[tauri::command]
+pub fn update_reminder(
+    db: State<'_, Database>,
+    id: String,
+    patch: ReminderPatch,
+) -> Result<Reminder> {
+    update(&db.conn(), &id, patch)
+}
+
+#[tauri::command]
+pub fn delete_reminder(db: State<'_, Database>, id: String) -> Result<()> {
+    delete(&db.conn(), &id)
+}
+
+#[tauri::command]
+pub fn snooze_reminder(
+    db: State<'_, Database>,
+    id: String,
+    minutes: Option<i64>,
+) -> Result<Reminder> {
+    let minutes = minutes.unwrap_or(DEFAULT_SNOOZE_MINUTES);
+    if minutes <= 0 {
+        return Err(Error::InvalidInput(
+            "snooze minutes must be positive".into(),
+        ));
+    }
+    snooze(&db.conn(), &id, now_millis() + minutes * 60 * 1000)
+}
+
+#[tauri::command]
+pub fn complete_reminder(
+    db: State<'_, Database>,
+    id: String,
+    user_id: Option<String>,
+) -> Result<Completion> {
+    complete(&mut db.conn(), &id, user_id.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    use chrono::{DateTime, Utc};
+
+    use crate::calendars::{self, NewCalendar};
+    use crate::storage;
+
+    struct ManualClock(Cell<DateTime<Utc>>);
+
+    impl ManualClock {
+        fn at(time: &str) -> Self {
+            Self(Cell::new(time.parse().unwrap()))
+        }
+
+        fn set(&self, time: &str) {
+            self.0.set(time.parse().unwrap());
+        }
+    }
+
+    impl Clock for &ManualClock {
+        type Tz = Utc;
+
+        fn now(&self) -> DateTime<Utc> {
+            self.0.get()
+        }
+    }
+
+    fn millis(time: &str) -> i64 {
+        time.parse::<DateTime<Utc>>().unwrap().timestamp_millis()
+    }
+
+    fn setup(conn: &Connection, time: &str, weekdays: Option<Vec<u8>>) -> Reminder {
+        let calendar = calendars::create(
+            conn,
+            NewCalendar {
+                name: "Health".into(),
+                color_theme: "green".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap();
+        let habit = habits::tests_support::new_habit(conn, &calendar.id, "Stretch");
+        create(
+            conn,
+            NewReminder {
+                habit_id: habit.id,
+                time: time.into(),
+                weekdays,
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn fires_once_at_the_scheduled_time() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        let reminder = setup(&conn, "08:30", None);
+        let clock = ManualClock::at("2024-03-20T08:29:00Z");
+        let scheduler = Scheduler::new(&clock);
+
+        assert!(scheduler.tick(&mut conn).unwrap().is_empty());
+
+        clock.set("2024-03-20T08:30:10Z");
+        let fired = scheduler.tick(&mut conn).unwrap();
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].reminder.id, reminder.id);
+        assert_eq!(fired[0].habit_name, "Stretch");
+        assert_eq!(fired[0].scheduled_for, millis("2024-03-20T08:30:00Z"));
+
+        clock.set("2024-03-20T08:45:00Z");
+        assert!(scheduler.tick(&mut conn).unwrap().is_empty());
+
+        // Fires again the next day
+        clock.set("2024-03-21T08:31:00Z");
+        assert_eq!(scheduler.tick(&mut conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn respects_weekdays_and_lateness() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        // Mondays only; 2024-03-20 is a Wednesday
+        setup(&conn, "09:00", Some(vec![1]));
+        let clock = ManualClock::at("2024-03-20T09:00:00Z");
+        let scheduler = Scheduler::new(&clock);
+        assert!(scheduler.tick(&mut conn).unwrap().is_empty());
+
+        // App was closed through Monday morning
+        clock.set("2024-03-25T10:30:00Z");
+        assert!(scheduler.tick(&mut conn).unwrap().is_empty());
+
+        clock.set("2024-04-01T09:59:00Z");
+        assert_eq!(scheduler.tick(&mut conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn skips_habits_completed_today() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        let reminder = setup(&conn, "20:00", None);
+        completions::log(
+            &conn,
+            &reminder.habit_id,
+            Some(millis("2024-03-20T07:00:00Z")),
+            None,
+        )
+        .unwrap();
+        let clock = ManualClock::at("2024-03-20T20:00:00Z");
+        let scheduler = Scheduler::new(&clock);
+
+        assert!(scheduler.tick(&mut conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn snoozed_reminder_fires_again() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        let reminder = setup(&conn, "08:00", None);
+        let clock = ManualClock::at("2024-03-20T08:00:00Z");
+        let scheduler = Scheduler::new(&clock);
+        assert_eq!(scheduler.tick(&mut conn).unwrap().len(), 1);
+
+        snooze(&conn, &reminder.id, millis("2024-03-20T08:10:00Z")).unwrap();
+        clock.set("2024-03-20T08:09:00Z");
+        assert!(scheduler.tick(&mut conn).unwrap().is_empty());
+
+        clock.set("2024-03-20T08:10:00Z");
+        let fired = scheduler.tick(&mut conn).unwrap();
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].scheduled_for, millis("2024-03-20T08:10:00Z"));
+        assert_eq!(get(&conn, &reminder.id).unwrap().snoozed_until, None);
+
+        clock.set("2024-03-20T08:20:00Z");
+        assert!(scheduler.tick(&mut conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn completing_logs_and_cancels_snooze() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        let reminder = setup(&conn, "08:00", None);
+        snooze(&conn, &reminder.id, millis("2024-03-20T08:10:00Z")).unwrap();
+
+        let completion = complete(&mut conn, &reminder.id, None).unwrap();
+        assert_eq!(completion.habit_id, reminder.habit_id);
+        assert_eq!(get(&conn, &reminder.id).unwrap().snoozed_until, None);
+    }
+
+    #[test]
+    fn validates_input() {
+        let db = storage::Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let reminder = setup(&conn, "07:05", Some(vec![0, 6]));
+        assert_eq!(reminder.weekdays, vec![0, 6]);
+        assert_eq!(
+            serde_json::to_value(&reminder).unwrap()["time"],
+            serde_json::json!("07:05")
+        );
+
+        let bad_time = ReminderPatch {
+            time: Some("7pm".into()),
+            ..ReminderPatch::default()
+        };
+        assert!(update(&conn, &reminder.id, bad_time).is_err());
+        let bad_day = ReminderPatch {
+            weekdays: Some(vec![7]),
+            ..ReminderPatch::default()
+        };
+        assert!(update(&conn, &reminder.id, bad_day).is_err());
+    }
+}
@@ -0,0 +1,33 @@
+// Key/value settings stored in the native `settings` table.
+// Each feature owns a key and a serde struct for its value; missing keys fall
+// back to the struct's `Default`.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::storage::now_millis;
+
+/// Reads the value stored under `key`, or `T::default()` if it was never set.
+pub fn get<T: DeserializeOwned + Default>(conn: &Connection, key: &str) -> Result<T> {
+    let value: Option<String> = conn
+        .query_row("SELECT value FROM settings WHERE key = ?1", [key], |row| {
+            row.get(0)
+        })
+        .optional()?;
+    match value {
+        Some(json) => Ok(serde_json::from_str(&json)?),
+        None => Ok(T::default()),
+    }
+}
+
+/// Stores `value` under `key`, replacing any previous value.
+pub fn set<T: Serialize>(conn: &Connection, key: &str, value: &T) -> Result<()> {
+    conn.execute(
+        "INSERT INTO settings (key, value, updatedAt) VALUES (?1, ?2, ?3)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value, updatedAt = excluded.updatedAt",
+        params![key, serde_json::to_string(value)?, now_millis()],
+    )?;
+    Ok(())
+}
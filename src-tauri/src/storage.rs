@@ -0,0 +1,312 @@
+// Native SQLite storage for the desktop/mobile builds.
+// The webview keeps its own sql.js copy for the browser build; inside Tauri the
+// database file lives in the app data dir so clearing WebView storage no longer
+// wipes the user's habits.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use rusqlite::hooks::Action;
+use rusqlite::Connection;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::encryption::{self, DatabaseKey};
+use crate::error::{Error, Result};
+use crate::migrations;
+
+/// File name of the native database inside the app data dir.
+pub const DB_FILE_NAME: &str = "habistat.db";
+
+/// Owns the SQLite connection. Registered as Tauri managed state in `run()`.
+pub struct Database {
+    conn: Mutex<Connection>,
+    path: Option<PathBuf>,
+    changes: Arc<Changes>,
+    /// SQLCipher key of the open file, `None` when it is plaintext.
+    key: Mutex<Option<DatabaseKey>>,
+}
+
+impl Database {
+    /// Opens (or creates) the database at `path` and applies pending migrations.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with_key(path, None)
+    }
+
+    /// Like [`open`](Self::open) for a file encrypted with `key` (see `encryption`).
+    pub fn open_with_key(path: impl AsRef<Path>, key: Option<DatabaseKey>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)?;
+        encryption::apply_key(&conn, key.as_ref())?;
+        Self::init(conn, Some(path.to_path_buf()), key)
+    }
+
+    /// Opens a throwaway in-memory database with the full schema.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?, None, None)
+    }
+
+    fn init(mut conn: Connection, path: Option<PathBuf>, key: Option<DatabaseKey>) -> Result<Self> {
+        configure(&conn)?;
+        migrations::run(&mut conn)?;
+
+        // Bumped on every row change, whoever makes it (commands, timer engine, sync)
+        let changes = Arc::new(Changes::default());
+        watch(&conn, &changes);
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            path,
+            changes,
+            key: Mutex::new(key),
+        })
+    }
+
+    /// Location of the database file, `None` for in-memory databases.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Counter that changes whenever a row is inserted, updated or deleted.
+    /// Background views (e.g. the tray) compare it to decide when to refresh.
+    pub fn generation(&self) -> u64 {
+        self.changes.rows.load(Ordering::Relaxed)
+    }
+
+    /// Counter that changes whenever a `habits` row changes or a `completions`
+    /// row is updated or deleted, but not when completions are inserted.
+    /// Caches that fold in new completions as they arrive (the points ledger)
+    /// only rebuild when it moves.
+    pub fn rewrites(&self) -> u64 {
+        self.changes.rewrites.load(Ordering::Relaxed)
+    }
+
+    /// Bumps [`generation`](Self::generation) and [`rewrites`](Self::rewrites)
+    /// for changes the update hook does not see, such as restoring a snapshot
+    /// over the whole file.
+    pub fn mark_changed(&self) {
+        self.changes.rows.fetch_add(1, Ordering::Relaxed);
+        self.changes.rewrites.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Locks the connection for the duration of the returned guard.
+    pub fn conn(&self) -> MutexGuard<'_, Connection> {
+        // A panic while holding the lock cannot leave SQLite itself in a bad state,
+        // so recover the guard instead of poisoning every later command.
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Key the file is encrypted with, `None` when it is plaintext.
+    pub fn key(&self) -> Option<DatabaseKey> {
+        self.key.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Re-encrypts the file under `key`, or decrypts it with `None`. Commands
+    /// wait on the connection lock meanwhile; switching between plaintext and
+    /// encrypted closes and reopens the connection around a full rewrite.
+    pub fn set_key(&self, key: Option<DatabaseKey>) -> Result<()> {
+        let path = self
+            .path
+            .clone()
+            .ok_or_else(|| Error::InvalidInput("in-memory databases cannot be encrypted".into()))?;
+        let mut conn = self.conn();
+        let mut current = self.key.lock().unwrap_or_else(|e| e.into_inner());
+        if let (Some(_), Some(new)) = (current.as_ref(), key.as_ref()) {
+            encryption::rekey(&conn, new)?;
+        } else {
+            // Copy from the live connection, so it is only given up once a
+            // copy that opens with the new key is waiting to replace the file
+            let copy = encryption::export_copy(&conn, &path, key.as_ref())?;
+            let old = std::mem::replace(&mut *conn, Connection::open_in_memory()?);
+            if let Err((old, e)) = old.close() {
+                *conn = old;
+                let _ = std::fs::remove_file(&copy);
+                return Err(e.into());
+            }
+            let moved = std::fs::rename(&copy, &path);
+            // Whichever file the rename left in place has already been opened
+            // with its key, so one of the two fits it
+            let keys = if moved.is_ok() {
+                [key.as_ref(), current.as_ref()]
+            } else {
+                [current.as_ref(), key.as_ref()]
+            };
+            let (reopened, opened_with) = reopen(&path, &self.changes, keys)?;
+            let opened_with = opened_with.cloned();
+            *conn = reopened;
+            *current = opened_with;
+            moved?;
+        }
+        *current = key;
+        self.mark_changed();
+        Ok(())
+    }
+}
+
+// Opens the file at `path` with the first of `keys` that fits it, returning
+// that key alongside the connection
+fn reopen<'k>(
+    path: &Path,
+    changes: &Arc<Changes>,
+    keys: [Option<&'k DatabaseKey>; 2],
+) -> Result<(Connection, Option<&'k DatabaseKey>)> {
+    let mut last_error = None;
+    for key in keys {
+        let opened = Connection::open(path)
+            .map_err(Error::from)
+            .and_then(|conn| {
+                encryption::apply_key(&conn, key)?;
+                configure(&conn)?;
+                Ok(conn)
+            });
+        match opened {
+            Ok(conn) => {
+                watch(&conn, changes);
+                return Ok((conn, key));
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or(Error::WrongPassphrase))
+}
+
+// Row change counters, shared with the update hook of whichever connection is open
+#[derive(Default)]
+struct Changes {
+    rows: AtomicU64,
+    rewrites: AtomicU64,
+}
+
+// Bumps `changes` whenever a row changes on `conn`
+fn watch(conn: &Connection, changes: &Arc<Changes>) {
+    let changes = Arc::clone(changes);
+    conn.update_hook(Some(move |action: Action, _: &str, table: &str, _: i64| {
+        changes.rows.fetch_add(1, Ordering::Relaxed);
+        let rewrite = match table {
+            "habits" => true,
+            "completions" => !matches!(action, Action::SQLITE_INSERT),
+            _ => false,
+        };
+        if rewrite {
+            changes.rewrites.fetch_add(1, Ordering::Relaxed);
+        }
+    }));
+}
+
+// Per-connection settings; these are not persisted in the database file
+fn configure(conn: &Connection) -> Result<()> {
+    conn.pragma_update(None, "foreign_keys", "ON")?;
+    conn.pragma_update(None, "busy_timeout", 5000)?;
+    // journal_mode cannot change inside a transaction, so it is set here rather than in a migration
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    Ok(())
+}
+
+/// Current time as a Unix timestamp in milliseconds, the unit used by every table.
+pub fn now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Fresh identifier for `id`/`localUuid` columns, matching `crypto.randomUUID()` in the webview.
+pub fn new_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Namespace for [`derived_id`]; never change it or re-imports stop matching.
+const DERIVED_ID_NAMESPACE: uuid::Uuid = uuid::uuid!("5b0f3c1e-8a52-4c1d-9e0b-6f2a7d4e9c31");
+
+/// Stable identifier for data brought in from elsewhere: the same `name`
+/// always yields the same UUID, so importers can recognise earlier imports.
+pub fn derived_id(name: &str) -> String {
+    uuid::Uuid::new_v5(&DERIVED_ID_NAMESPACE, name.as_bytes()).to_string()
+}
+
+/// Replaces `path` with what `write` puts in `<path>.partial`. The partial file
+/// is moved into place once `write` succeeds and removed if anything fails, so
+/// neither a truncated `path` nor a stray partial file is left behind.
+pub fn write_atomically<T>(path: &Path, write: impl FnOnce(&Path) -> Result<T>) -> Result<T> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+
+    let written = write(&partial).and_then(|value| {
+        fs::rename(&partial, path)?;
+        Ok(value)
+    });
+    if written.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    written
+}
+
+/// Resolves the database path inside the platform app data dir.
+pub fn db_path<R: Runtime>(app: &AppHandle<R>) -> tauri::Result<PathBuf> {
+    Ok(app.path().app_data_dir()?.join(DB_FILE_NAME))
+}
+
+/// Opens the app database and registers it as managed state. Returns `false`
+/// for an encrypted database, which waits for `unlock_database` instead.
+pub fn init<R: Runtime>(
+    app: &AppHandle<R>,
+) -> std::result::Result<bool, Box<dyn std::error::Error>> {
+    let path = db_path(app)?;
+    if encryption::is_encrypted(&path)? {
+        return Ok(false);
+    }
+    app.manage(Database::open(path)?);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendars::{self, NewCalendar};
+    use crate::encryption::KeyDerivation;
+
+    fn key(passphrase: &str) -> DatabaseKey {
+        KeyDerivation {
+            memory_kib: 64,
+            iterations: 1,
+            ..KeyDerivation::generate()
+        }
+        .derive(passphrase)
+        .unwrap()
+    }
+
+    #[test]
+    fn reopen_falls_back_to_the_key_that_fits() {
+        let dir = std::env::temp_dir().join(format!("habistat-storage-{}", new_id()));
+        let path = dir.join(DB_FILE_NAME);
+        let (right, wrong) = (key("correct horse"), key("wrong horse"));
+        let db = Database::open(&path).unwrap();
+        db.set_key(Some(right.clone())).unwrap();
+        drop(db);
+
+        let changes = Arc::new(Changes::default());
+        let (conn, opened_with) = reopen(&path, &changes, [Some(&wrong), Some(&right)]).unwrap();
+        assert_eq!(opened_with, Some(&right));
+        // Reopened connections keep reporting changes
+        calendars::create(
+            &conn,
+            NewCalendar {
+                name: "Sobriety".into(),
+                color_theme: "blue".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap();
+        assert!(changes.rows.load(Ordering::Relaxed) > 0);
+        drop(conn);
+
+        assert!(reopen(&path, &changes, [Some(&wrong), None]).is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
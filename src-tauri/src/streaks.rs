@@ -0,0 +1,386 @@
+// Streak calculation for habits.
+// Works on local calendar days: a positive habit's streak is consecutive
+// satisfied periods of its recurrence (days, for daily habits), a negative
+// habit's streak is consecutive days without a completion (counted from when
+// the habit was created or last slipped).
+
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::{Duration, NaiveDate, TimeZone};
+use rusqlite::Connection;
+use serde::Serialize;
+use tauri::State;
+
+use crate::days;
+use crate::error::Result;
+use crate::habits::{self, Habit, HabitType};
+use crate::recurrence::Recurrence;
+use crate::storage::Database;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Streak {
+    /// Consecutive satisfied periods: days for daily habits, weeks for
+    /// "X times per week", scheduled days for weekday habits, and so on.
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    /// First day of the current streak, `None` when there is no streak.
+    pub streak_start_date: Option<NaiveDate>,
+    /// The current streak breaks at local midnight unless the habit is done today,
+    /// i.e. today is the last chance to satisfy the current period.
+    /// Always `false` for negative habits, which only break by slipping.
+    pub at_risk_today: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HabitStreak {
+    pub habit_id: String,
+    #[serde(flatten)]
+    pub streak: Streak,
+}
+
+/// Computes the streak of a habit from the local days it was completed on.
+///
+/// `tracking_start` is the first day the habit existed; negative habits count
+/// clean days from there and every-N-days recurrences align their windows to it.
+pub fn compute(
+    habit_type: HabitType,
+    recurrence: &Recurrence,
+    completion_days: &BTreeSet<NaiveDate>,
+    tracking_start: NaiveDate,
+    today: NaiveDate,
+) -> Streak {
+    match habit_type {
+        HabitType::Positive => positive_streak(recurrence, completion_days, tracking_start, today),
+        HabitType::Negative => negative_streak(completion_days, tracking_start, today),
+    }
+}
+
+fn positive_streak(
+    recurrence: &Recurrence,
+    days: &BTreeSet<NaiveDate>,
+    anchor: NaiveDate,
+    today: NaiveDate,
+) -> Streak {
+    let mut streak = Streak::default();
+    // Future-dated rows are ignored
+    let Some(&first_day) = days.range(..=today).next() else {
+        return streak;
+    };
+
+    // The period containing today is still open: it extends the streak once
+    // satisfied but does not break it yet
+    let mut cursor = match recurrence.period_at(today, anchor) {
+        Some(period) if period.is_satisfied(days, today) => Some(period),
+        Some(period) => {
+            let days_left = (period.end - today).num_days() + 1;
+            let missing = period.required - period.done(days, today);
+            streak.at_risk_today = !days.contains(&today) && days_left <= i64::from(missing);
+            recurrence.period_before(period.start, anchor)
+        }
+        None => recurrence.period_before(today, anchor),
+    };
+    while let Some(period) = cursor.filter(|p| p.is_satisfied(days, today)) {
+        streak.current_streak += 1;
+        streak.streak_start_date = Some(period.start);
+        cursor = recurrence.period_before(period.start, anchor);
+    }
+    streak.at_risk_today &= streak.current_streak > 0;
+
+    // Longest run of satisfied periods, walking back to the first completion
+    let mut run = 0;
+    let mut cursor = recurrence
+        .period_at(today, anchor)
+        .or_else(|| recurrence.period_before(today, anchor));
+    while let Some(period) = cursor.filter(|p| p.end >= first_day) {
+        if period.is_satisfied(days, today) {
+            run += 1;
+            streak.longest_streak = streak.longest_streak.max(run);
+        } else if period.end < today {
+            run = 0;
+        }
+        cursor = recurrence.period_before(period.start, anchor);
+    }
+    streak
+}
+
+fn negative_streak(
+    days: &BTreeSet<NaiveDate>,
+    tracking_start: NaiveDate,
+    today: NaiveDate,
+) -> Streak {
+    let mut streak = Streak::default();
+    let first_day = days
+        .first()
+        .map_or(tracking_start, |&first| first.min(tracking_start));
+    if first_day > today {
+        return streak;
+    }
+
+    // Each slip ends the clean run that started after the previous one
+    let mut run_start = first_day;
+    for &slip in days.range(first_day..=today) {
+        let run = (slip - run_start).num_days().max(0) as u32;
+        streak.longest_streak = streak.longest_streak.max(run);
+        run_start = slip + Duration::days(1);
+    }
+
+    // Today counts as clean until a slip is logged
+    if run_start <= today {
+        streak.current_streak = ((today - run_start).num_days() + 1) as u32;
+        streak.streak_start_date = Some(run_start);
+    }
+    streak.longest_streak = streak.longest_streak.max(streak.current_streak);
+    streak
+}
+
+/// Streaks for the given habits (all habits when `habit_ids` is `None`) as of
+/// `today`, with completions counted on their day in `tz`.
+pub fn for_habits<Tz: TimeZone>(
+    conn: &Connection,
+    tz: &Tz,
+    habit_ids: Option<&[String]>,
+    today: NaiveDate,
+) -> Result<Vec<HabitStreak>> {
+    let habits: Vec<Habit> = habits::list(conn, None)?
+        .into_iter()
+        .filter(|h| habit_ids.is_none_or(|ids| ids.contains(&h.id)))
+        .collect();
+
+    let days_by_habit = match habit_ids {
+        None => completion_days(conn, tz, None)?,
+        Some(_) => {
+            let mut days_by_habit = HashMap::new();
+            for habit in &habits {
+                days_by_habit.extend(completion_days(conn, tz, Some(&habit.id))?);
+            }
+            days_by_habit
+        }
+    };
+
+    let empty = BTreeSet::new();
+    Ok(habits
+        .into_iter()
+        .map(|habit| {
+            let days = days_by_habit.get(&habit.id).unwrap_or(&empty);
+            let streak = compute(
+                habit.habit_type,
+                &habit.recurrence,
+                days,
+                days::date_of(tz, habit.created_at),
+                today,
+            );
+            HabitStreak {
+                habit_id: habit.id,
+                streak,
+            }
+        })
+        .collect())
+}
+
+/// Local days (in `tz`) with at least one completion, per habit; all habits
+/// when `habit_id` is `None`.
+pub fn completion_days<Tz: TimeZone>(
+    conn: &Connection,
+    tz: &Tz,
+    habit_id: Option<&str>,
+) -> Result<HashMap<String, BTreeSet<NaiveDate>>> {
+    let mut days_by_habit: HashMap<String, BTreeSet<NaiveDate>> = HashMap::new();
+    let mut stmt = conn
+        .prepare("SELECT habitId, completedAt FROM completions WHERE ?1 IS NULL OR habitId = ?1")?;
+    let mut rows = stmt.query([habit_id])?;
+    while let Some(row) = rows.next()? {
+        let habit_id: String = row.get(0)?;
+        let completed_at: i64 = row.get(1)?;
+        days_by_habit
+            .entry(habit_id)
+            .or_default()
+            .insert(days::date_of(tz, completed_at));
+    }
+    Ok(days_by_habit)
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn get_habit_streaks(
+    db: State<'_, Database>,
+    habit_ids: Option<Vec<String>>,
+) -> Result<Vec<HabitStreak>> {
+    for_habits(
+        &db.conn(),
+        &chrono::Local,
+        habit_ids.as_deref(),
+        days::today(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn days(dates: &[&str]) -> BTreeSet<NaiveDate> {
+        dates.iter().map(|d| date(d)).collect()
+    }
+
+    #[test]
+    fn weekly_target_survives_off_days() {
+        let recurrence = Recurrence::TimesPerWeek { count: 2 };
+        // Weeks of Mar 4 and Mar 11 met, the week of Mar 18 has one so far
+        let done = days(&[
+            "2024-03-05",
+            "2024-03-07",
+            "2024-03-11",
+            "2024-03-15",
+            "2024-03-18",
+        ]);
+        let anchor = date("2024-03-01");
+
+        let streak = compute(
+            HabitType::Positive,
+            &recurrence,
+            &done,
+            anchor,
+            date("2024-03-20"),
+        );
+        assert_eq!(streak.current_streak, 2);
+        assert_eq!(streak.streak_start_date, Some(date("2024-03-04")));
+        assert!(!streak.at_risk_today);
+
+        // Sunday with one completion missing: today is the last chance
+        let streak = compute(
+            HabitType::Positive,
+            &recurrence,
+            &done,
+            anchor,
+            date("2024-03-24"),
+        );
+        assert_eq!(streak.current_streak, 2);
+        assert!(streak.at_risk_today);
+
+        // The week ended one short
+        let streak = compute(
+            HabitType::Positive,
+            &recurrence,
+            &done,
+            anchor,
+            date("2024-03-25"),
+        );
+        assert_eq!(streak.current_streak, 0);
+        assert_eq!(streak.longest_streak, 2);
+    }
+
+    #[test]
+    fn weekday_schedule_skips_unscheduled_days() {
+        // Mondays and Fridays
+        let recurrence = Recurrence::Weekdays { days: vec![1, 5] };
+        let done = days(&["2024-03-11", "2024-03-15", "2024-03-18"]);
+        let anchor = date("2024-03-01");
+
+        // Wednesday: nothing scheduled, streak carries over from Monday
+        let streak = compute(
+            HabitType::Positive,
+            &recurrence,
+            &done,
+            anchor,
+            date("2024-03-20"),
+        );
+        assert_eq!((streak.current_streak, streak.at_risk_today), (3, false));
+
+        // Friday without a completion yet
+        let streak = compute(
+            HabitType::Positive,
+            &recurrence,
+            &done,
+            anchor,
+            date("2024-03-22"),
+        );
+        assert_eq!((streak.current_streak, streak.at_risk_today), (3, true));
+    }
+
+    #[test]
+    fn missed_day_breaks_daily_streak() {
+        let done = days(&["2024-03-01", "2024-03-02", "2024-03-03", "2024-03-05"]);
+        let anchor = date("2024-03-01");
+
+        let streak = compute(
+            HabitType::Positive,
+            &Recurrence::Daily,
+            &done,
+            anchor,
+            date("2024-03-05"),
+        );
+        assert_eq!(streak.current_streak, 1);
+        assert_eq!(streak.longest_streak, 3);
+        assert_eq!(streak.streak_start_date, Some(date("2024-03-05")));
+
+        // Not done yet today: yesterday's streak stands but is at risk
+        let streak = compute(
+            HabitType::Positive,
+            &Recurrence::Daily,
+            &done,
+            anchor,
+            date("2024-03-06"),
+        );
+        assert_eq!((streak.current_streak, streak.at_risk_today), (1, true));
+
+        let streak = compute(
+            HabitType::Positive,
+            &Recurrence::Daily,
+            &done,
+            anchor,
+            date("2024-03-07"),
+        );
+        assert_eq!(streak.current_streak, 0);
+        assert_eq!(streak.streak_start_date, None);
+    }
+
+    #[test]
+    fn negative_habit_counts_days_since_last_slip() {
+        let created = date("2024-03-01");
+        let today = date("2024-03-20");
+
+        // Never slipped: clean since creation, today included
+        let streak = compute(
+            HabitType::Negative,
+            &Recurrence::Daily,
+            &BTreeSet::new(),
+            created,
+            today,
+        );
+        assert_eq!(streak.current_streak, 20);
+        assert_eq!(streak.streak_start_date, Some(created));
+        assert!(!streak.at_risk_today);
+
+        // Clean Mar 1-9, slipped Mar 10, clean again from Mar 11
+        let slips = days(&["2024-03-10"]);
+        let streak = compute(
+            HabitType::Negative,
+            &Recurrence::Daily,
+            &slips,
+            created,
+            today,
+        );
+        assert_eq!(streak.current_streak, 10);
+        assert_eq!(streak.longest_streak, 10);
+        assert_eq!(streak.streak_start_date, Some(date("2024-03-11")));
+
+        // A slip today ends the streak
+        let slips = days(&["2024-03-10", "2024-03-20"]);
+        let streak = compute(
+            HabitType::Negative,
+            &Recurrence::Daily,
+            &slips,
+            created,
+            today,
+        );
+        assert_eq!(streak.current_streak, 0);
+        assert_eq!(streak.streak_start_date, None);
+        assert_eq!(streak.longest_streak, 9);
+    }
+}
@@ -0,0 +1,1702 @@
+// Two-way sync of the native database with Convex.
+//
+// Talks to the Convex HTTP API (`/api/query`, `/api/mutation`) with the
+// Clerk token kept by `credentials`, calling the same public functions as the
+// webview's `sync-service.ts`. Tables sync in dependency order: calendars,
+// habits, completions, activityHistory. For each, rows changed locally since
+// its `syncMetadata.lastSyncTimestamp` are pushed, then the server's rows are
+// pulled. The timestamp only advances when both directions succeed, so a
+// failed table is simply retried next time.
+//
+// Calendars, habits and activity are matched with the server by `localUuid`,
+// not `id`: importers give what they bring in a stable `localUuid`, so the
+// same import on two devices lands on one server row. Habits refer to their
+// calendar's `localUuid` on the server, and server completions to the Convex
+// id of their habit, so both are translated in each direction.
+//
+// Conflicts are Last-Write-Wins as the Convex schema documents: the row with
+// the newer `updatedAt` (calendars, habits) or `clientUpdatedAt`
+// (completions) wins, on the server through its upsert mutations and locally
+// here. Rows without an owner (made before signing in) are pushed and adopted
+// by the signed-in user.
+//
+// The server keeps no tombstones, so calendars, habits and completions
+// deleted here are recorded in `pendingDeletions` (see migration 0008) and
+// deleted on the server before the table is pulled; until then, pulled
+// copies of them are ignored. Deletions of rows that are back (e.g. restored
+// from a backup) are dropped instead. The server deletes completions only by
+// habit and day, so each is deleted over the millisecond it was completed at.
+// Activity is not deleted through sync.
+// The database lock is never held across a network call.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+use zeroize::Zeroizing;
+
+use crate::credentials;
+use crate::days;
+use crate::error::{Error, Result};
+use crate::storage::{now_millis, Database};
+
+pub const SYNCED_EVENT: &str = "sync://completed";
+
+const PAGE_SIZE: usize = 100;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+// One sync at a time, however it was started
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Minimal client for Convex's HTTP function API.
+pub struct ConvexClient {
+    url: String,
+    token: Zeroizing<String>,
+    agent: ureq::Agent,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+enum FunctionResult {
+    Success {
+        value: Value,
+    },
+    Error {
+        #[serde(rename = "errorMessage")]
+        error_message: String,
+    },
+}
+
+impl ConvexClient {
+    /// `url` is the deployment URL, e.g. `https://happy-otter-123.convex.cloud`.
+    pub fn new(url: &str, token: Zeroizing<String>) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            token,
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+        }
+    }
+
+    pub fn query<T: DeserializeOwned>(&self, path: &str, args: Value) -> Result<T> {
+        self.call("query", path, args)
+    }
+
+    pub fn mutation<T: DeserializeOwned>(&self, path: &str, args: Value) -> Result<T> {
+        self.call("mutation", path, args)
+    }
+
+    fn call<T: DeserializeOwned>(&self, kind: &str, path: &str, args: Value) -> Result<T> {
+        let response = self
+            .agent
+            .post(&format!("{}/api/{kind}", self.url))
+            .set("Authorization", &format!("Bearer {}", self.token.as_str()))
+            .send_json(json!({ "path": path, "args": args, "format": "json" }));
+        let result: FunctionResult = match response {
+            Ok(response) => response.into_json()?,
+            Err(ureq::Error::Status(401 | 403, _)) => {
+                return Err(Error::Sync("Convex rejected the sign-in token".into()));
+            }
+            // Function errors come back as JSON with an error status
+            Err(ureq::Error::Status(status, response)) => response
+                .into_json()
+                .map_err(|_| Error::Sync(format!("`{path}` failed with HTTP {status}")))?,
+            Err(e) => return Err(Error::Sync(e.to_string())),
+        };
+        match result {
+            FunctionResult::Success { value } => Ok(serde_json::from_value(value)?),
+            FunctionResult::Error { error_message } => {
+                Err(Error::Sync(format!("`{path}`: {error_message}")))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncTable {
+    Calendars,
+    Habits,
+    Completions,
+    ActivityHistory,
+}
+
+impl SyncTable {
+    /// Dependency order: habits need their calendar, completions their habit.
+    pub const ALL: [SyncTable; 4] = [
+        SyncTable::Calendars,
+        SyncTable::Habits,
+        SyncTable::Completions,
+        SyncTable::ActivityHistory,
+    ];
+
+    /// Table name, also the `syncMetadata` row id the webview uses.
+    pub fn name(self) -> &'static str {
+        match self {
+            SyncTable::Calendars => "calendars",
+            SyncTable::Habits => "habits",
+            SyncTable::Completions => "completions",
+            SyncTable::ActivityHistory => "activityHistory",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableReport {
+    pub table: String,
+    pub pushed: usize,
+    /// Local deletions applied on the server.
+    pub deleted: usize,
+    /// Rows created or updated locally from the server.
+    pub pulled: usize,
+    /// Rows left out because what they reference is missing on the other side.
+    pub skipped: usize,
+    /// Set when the table failed; its sync timestamp did not advance.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    pub started_at: i64,
+    pub tables: Vec<TableReport>,
+}
+
+impl SyncReport {
+    pub fn succeeded(&self) -> bool {
+        self.tables.iter().all(|table| table.error.is_none())
+    }
+}
+
+/// When `table` last synced successfully; 0 if never.
+pub fn last_sync(conn: &Connection, table: SyncTable) -> Result<i64> {
+    Ok(conn
+        .query_row(
+            "SELECT lastSyncTimestamp FROM syncMetadata WHERE id = ?1",
+            [table.name()],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or(0))
+}
+
+fn set_last_sync(conn: &Connection, table: SyncTable, timestamp: i64) -> Result<()> {
+    conn.execute(
+        "INSERT INTO syncMetadata (id, lastSyncTimestamp) VALUES (?1, ?2)
+         ON CONFLICT(id) DO UPDATE SET lastSyncTimestamp = excluded.lastSyncTimestamp",
+        params![table.name(), timestamp],
+    )?;
+    Ok(())
+}
+
+/// Syncs every table for `user_id`. Tables fail independently; see
+/// [`TableReport::error`].
+pub fn sync(db: &Database, client: &ConvexClient, user_id: &str) -> SyncReport {
+    let started_at = now_millis();
+    let mut tables = Vec::new();
+    for table in SyncTable::ALL {
+        let result = sync_table(db, client, user_id, table);
+        tables.push(match result {
+            Ok(report) => TableReport {
+                table: table.name().into(),
+                ..report
+            },
+            Err(e) => TableReport {
+                table: table.name().into(),
+                error: Some(e.to_string()),
+                ..TableReport::default()
+            },
+        });
+    }
+    SyncReport { started_at, tables }
+}
+
+fn sync_table(
+    db: &Database,
+    client: &ConvexClient,
+    user_id: &str,
+    table: SyncTable,
+) -> Result<TableReport> {
+    let started = now_millis();
+    let since = last_sync(&db.conn(), table)?;
+    let report = match table {
+        SyncTable::Calendars => sync_calendars(db, client, user_id, since),
+        SyncTable::Habits => sync_habits(db, client, user_id, since),
+        SyncTable::Completions => sync_completions(db, client, user_id, since),
+        SyncTable::ActivityHistory => sync_activity(db, client, user_id, since),
+    }?;
+    // Changes made while this table synced are newer than `started`
+    set_last_sync(&db.conn(), table, started)?;
+    Ok(report)
+}
+
+// Convex numbers are float64, so integer columns arrive as f64
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RemoteCalendar {
+    local_uuid: String,
+    name: String,
+    color_theme: String,
+    position: f64,
+    is_enabled: bool,
+    created_at: f64,
+    updated_at: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RemoteHabit {
+    #[serde(rename = "_id")]
+    convex_id: String,
+    local_uuid: String,
+    calendar_id: String,
+    name: String,
+    description: Option<String>,
+    #[serde(rename = "type")]
+    habit_type: String,
+    timer_enabled: bool,
+    target_duration_seconds: Option<f64>,
+    points_value: Option<f64>,
+    position: f64,
+    is_enabled: bool,
+    created_at: f64,
+    updated_at: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RemoteCompletion {
+    local_uuid: String,
+    /// Convex id of the habit.
+    habit_id: String,
+    completed_at: f64,
+    /// Not returned by current deployments, which resolve on `completedAt`.
+    client_updated_at: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RemoteActivity {
+    local_uuid: String,
+    date: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Page<T> {
+    #[serde(alias = "completions", alias = "activityHistory")]
+    page: Vec<T>,
+    next_cursor: Option<String>,
+    is_done: bool,
+}
+
+// Convex rejects `null` for optional arguments, so absent values are omitted
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CalendarArgs {
+    local_uuid: String,
+    name: String,
+    color_theme: String,
+    position: i64,
+    is_enabled: bool,
+    created_at: i64,
+    updated_at: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HabitArgs {
+    local_uuid: String,
+    calendar_id: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(rename = "type")]
+    habit_type: String,
+    timer_enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_duration_seconds: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    points_value: Option<i64>,
+    position: i64,
+    is_enabled: bool,
+    created_at: i64,
+    updated_at: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CompletionArgs {
+    local_uuid: String,
+    habit_id: String,
+    completed_at: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ActivityArgs {
+    local_uuid: String,
+    date: String,
+}
+
+/// Follows the cursor of a paginated `*Since` query to the end.
+fn pull_since<T: DeserializeOwned>(
+    client: &ConvexClient,
+    path: &str,
+    since: i64,
+) -> Result<Vec<T>> {
+    let mut rows = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut args = json!({ "timestamp": since, "limit": PAGE_SIZE });
+        if let Some(cursor) = &cursor {
+            args["cursor"] = json!(cursor);
+        }
+        let page: Page<T> = client.query(path, args)?;
+        rows.extend(page.page);
+        match page.next_cursor {
+            Some(next) if !page.is_done => cursor = Some(next),
+            _ => return Ok(rows),
+        }
+    }
+}
+
+// Rows to push: the user's changes since the last sync, plus every row
+// without an owner yet
+const OWNED_OR_NEW: &str = "(userId IS NULL OR (userId = ?1 AND updatedAt > ?2))";
+
+/// Deletes on the server, through mutation `path`, the rows of `table` the
+/// user deleted here. Each is forgotten once the server no longer has it;
+/// rows that are back in `table` are forgotten without being deleted.
+fn push_deletions(
+    db: &Database,
+    client: &ConvexClient,
+    user_id: &str,
+    table: SyncTable,
+    path: &str,
+) -> Result<usize> {
+    let local_uuids: Vec<String> = {
+        let conn = db.conn();
+        conn.execute(
+            &format!(
+                "DELETE FROM pendingDeletions WHERE tableName = ?1
+                 AND localUuid IN (SELECT localUuid FROM {})",
+                table.name()
+            ),
+            [table.name()],
+        )?;
+        let mut stmt = conn.prepare(
+            "SELECT localUuid FROM pendingDeletions WHERE tableName = ?1 AND userId = ?2
+             ORDER BY deletedAt",
+        )?;
+        let rows = stmt.query_map(params![table.name(), user_id], |row| row.get(0))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    for local_uuid in &local_uuids {
+        match client.mutation::<Value>(path, json!({ "localUuid": local_uuid })) {
+            Ok(_) => {}
+            // `habits:deleteHabit` throws for a habit the server never had
+            Err(Error::Sync(message)) if message.contains("not found") => {}
+            Err(e) => return Err(e),
+        }
+        db.conn().execute(
+            "DELETE FROM pendingDeletions WHERE tableName = ?1 AND localUuid = ?2",
+            params![table.name(), local_uuid],
+        )?;
+    }
+    Ok(local_uuids.len())
+}
+
+/// Deletes on the server the completions the user deleted here, like
+/// [`push_deletions`]. `convex_ids` maps habit `localUuid`s to Convex ids;
+/// completions of habits the server no longer has went with their habit.
+fn push_completion_deletions(
+    db: &Database,
+    client: &ConvexClient,
+    user_id: &str,
+    convex_ids: &HashMap<String, String>,
+) -> Result<usize> {
+    let pending: Vec<(String, String, i64)> = {
+        let conn = db.conn();
+        conn.execute(
+            "DELETE FROM pendingDeletions WHERE tableName = 'completions'
+             AND localUuid IN (SELECT id FROM completions)",
+            [],
+        )?;
+        let mut stmt = conn.prepare(
+            "SELECT localUuid, habitLocalUuid, completedAt FROM pendingDeletions
+             WHERE tableName = 'completions' AND userId = ?1
+             ORDER BY deletedAt",
+        )?;
+        let rows = stmt.query_map([user_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    let mut deleted = 0;
+    for (local_uuid, habit_local_uuid, completed_at) in &pending {
+        if let Some(habit_id) = convex_ids.get(habit_local_uuid) {
+            client.mutation::<Value>(
+                "completions:deleteLatestCompletionForDay",
+                json!({
+                    "habitId": habit_id,
+                    "dayStartMs": completed_at,
+                    "dayEndMs": completed_at + 1,
+                }),
+            )?;
+            deleted += 1;
+        }
+        db.conn().execute(
+            "DELETE FROM pendingDeletions WHERE tableName = 'completions' AND localUuid = ?1",
+            [local_uuid],
+        )?;
+    }
+    Ok(deleted)
+}
+
+// Deleted here while the table synced; the pulled copy must not revive it
+fn pending_deletion(conn: &Connection, table: SyncTable, local_uuid: &str) -> Result<bool> {
+    Ok(conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pendingDeletions WHERE tableName = ?1 AND localUuid = ?2)",
+        params![table.name(), local_uuid],
+        |row| row.get(0),
+    )?)
+}
+
+fn sync_calendars(
+    db: &Database,
+    client: &ConvexClient,
+    user_id: &str,
+    since: i64,
+) -> Result<TableReport> {
+    let local: Vec<(String, CalendarArgs)> = {
+        let conn = db.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, localUuid, name, colorTheme, position, isEnabled, createdAt, updatedAt
+             FROM calendars WHERE {OWNED_OR_NEW} ORDER BY position"
+        ))?;
+        let rows = stmt.query_map(params![user_id, since], |row| {
+            Ok((
+                row.get(0)?,
+                CalendarArgs {
+                    local_uuid: row.get(1)?,
+                    name: row.get(2)?,
+                    color_theme: row.get(3)?,
+                    position: row.get(4)?,
+                    is_enabled: row.get(5)?,
+                    created_at: row.get(6)?,
+                    updated_at: row.get(7)?,
+                },
+            ))
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    let mut report = TableReport {
+        deleted: push_deletions(
+            db,
+            client,
+            user_id,
+            SyncTable::Calendars,
+            "calendars:deleteCalendar",
+        )?,
+        ..TableReport::default()
+    };
+    for (id, args) in local {
+        client.mutation::<Value>("calendars:createCalendar", serde_json::to_value(args)?)?;
+        adopt(&db.conn(), "calendars", &id, user_id)?;
+        report.pushed += 1;
+    }
+
+    let remote: Vec<RemoteCalendar> = client.query("calendars:getUserCalendars", json!({}))?;
+    let mut conn = db.conn();
+    let tx = conn.transaction()?;
+    for calendar in remote {
+        if pending_deletion(&tx, SyncTable::Calendars, &calendar.local_uuid)? {
+            continue;
+        }
+        report.pulled += tx.execute(
+            "INSERT INTO calendars
+                 (id, userId, localUuid, name, colorTheme, position, isEnabled, createdAt, updatedAt)
+             VALUES (?1, ?2, ?1, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT(localUuid) DO UPDATE SET
+                 userId = excluded.userId, name = excluded.name,
+                 colorTheme = excluded.colorTheme, position = excluded.position,
+                 isEnabled = excluded.isEnabled, updatedAt = excluded.updatedAt
+             WHERE excluded.updatedAt > calendars.updatedAt",
+            params![
+                calendar.local_uuid,
+                user_id,
+                calendar.name,
+                calendar.color_theme,
+                calendar.position as i64,
+                calendar.is_enabled,
+                calendar.created_at as i64,
+                calendar.updated_at as i64,
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(report)
+}
+
+fn sync_habits(
+    db: &Database,
+    client: &ConvexClient,
+    user_id: &str,
+    since: i64,
+) -> Result<TableReport> {
+    let local: Vec<(String, HabitArgs)> = {
+        let conn = db.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, localUuid,
+                    (SELECT localUuid FROM calendars WHERE calendars.id = habits.calendarId),
+                    name, description, type, timerEnabled, targetDurationSeconds, pointsValue,
+                    position, isEnabled, createdAt, updatedAt
+             FROM habits WHERE {OWNED_OR_NEW} ORDER BY position"
+        ))?;
+        let rows = stmt.query_map(params![user_id, since], |row| {
+            Ok((
+                row.get(0)?,
+                HabitArgs {
+                    local_uuid: row.get(1)?,
+                    calendar_id: row.get(2)?,
+                    name: row.get(3)?,
+                    description: row.get(4)?,
+                    habit_type: row.get(5)?,
+                    timer_enabled: row.get(6)?,
+                    target_duration_seconds: row.get(7)?,
+                    points_value: row.get(8)?,
+                    position: row.get(9)?,
+                    is_enabled: row.get(10)?,
+                    created_at: row.get(11)?,
+                    updated_at: row.get(12)?,
+                },
+            ))
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    let mut report = TableReport {
+        deleted: push_deletions(db, client, user_id, SyncTable::Habits, "habits:deleteHabit")?,
+        ..TableReport::default()
+    };
+    for (id, args) in local {
+        client.mutation::<Value>("habits:createHabit", serde_json::to_value(args)?)?;
+        adopt(&db.conn(), "habits", &id, user_id)?;
+        report.pushed += 1;
+    }
+
+    let remote: Vec<RemoteHabit> = client.query("habits:getUserHabits", json!({}))?;
+    let mut conn = db.conn();
+    let tx = conn.transaction()?;
+    for habit in remote {
+        if pending_deletion(&tx, SyncTable::Habits, &habit.local_uuid)? {
+            continue;
+        }
+        // The server names the calendar by its `localUuid`
+        let calendar_id: Option<String> = tx
+            .query_row(
+                "SELECT id FROM calendars WHERE localUuid = ?1",
+                [&habit.calendar_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(calendar_id) = calendar_id else {
+            report.skipped += 1;
+            continue;
+        };
+        // Local-only columns (recurrence, quantities) keep their values
+        report.pulled += tx.execute(
+            "INSERT INTO habits
+                 (id, userId, localUuid, calendarId, name, description, type, timerEnabled,
+                  targetDurationSeconds, pointsValue, position, isEnabled, createdAt, updatedAt)
+             VALUES (?1, ?2, ?1, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+             ON CONFLICT(localUuid) DO UPDATE SET
+                 userId = excluded.userId, calendarId = excluded.calendarId,
+                 name = excluded.name, description = excluded.description,
+                 type = excluded.type, timerEnabled = excluded.timerEnabled,
+                 targetDurationSeconds = excluded.targetDurationSeconds,
+                 pointsValue = excluded.pointsValue, position = excluded.position,
+                 isEnabled = excluded.isEnabled, updatedAt = excluded.updatedAt
+             WHERE excluded.updatedAt > habits.updatedAt",
+            params![
+                habit.local_uuid,
+                user_id,
+                calendar_id,
+                habit.name,
+                habit.description,
+                habit.habit_type,
+                habit.timer_enabled,
+                habit.target_duration_seconds.map(|seconds| seconds as i64),
+                habit.points_value.map(|points| points as i64),
+                habit.position as i64,
+                habit.is_enabled,
+                habit.created_at as i64,
+                habit.updated_at as i64,
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(report)
+}
+
+fn sync_completions(
+    db: &Database,
+    client: &ConvexClient,
+    user_id: &str,
+    since: i64,
+) -> Result<TableReport> {
+    // Habit localUuid <-> Convex habit id, both ways
+    let habits: Vec<RemoteHabit> = client.query("habits:getUserHabits", json!({}))?;
+    let convex_ids: HashMap<String, String> = habits
+        .into_iter()
+        .map(|habit| (habit.local_uuid, habit.convex_id))
+        .collect();
+    let local_uuids: HashMap<&str, &str> = convex_ids
+        .iter()
+        .map(|(local, convex)| (convex.as_str(), local.as_str()))
+        .collect();
+
+    let mut report = TableReport {
+        deleted: push_completion_deletions(db, client, user_id, &convex_ids)?,
+        ..TableReport::default()
+    };
+    let local: Vec<CompletionArgs> = {
+        let conn = db.conn();
+        // `habitId` carries the habit's localUuid until it is translated below
+        let mut stmt = conn.prepare(
+            "SELECT id, (SELECT localUuid FROM habits WHERE habits.id = completions.habitId),
+                    completedAt
+             FROM completions
+             WHERE userId IS NULL OR (userId = ?1 AND clientUpdatedAt > ?2)
+             ORDER BY completedAt",
+        )?;
+        let rows = stmt.query_map(params![user_id, since], |row| {
+            Ok(CompletionArgs {
+                local_uuid: row.get(0)?,
+                habit_id: row.get(1)?,
+                completed_at: row.get(2)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    let mut batch = Vec::new();
+    for mut completion in local {
+        // The habit itself failed to push; the completion waits for it
+        let Some(convex_id) = convex_ids.get(&completion.habit_id) else {
+            report.skipped += 1;
+            continue;
+        };
+        completion.habit_id = convex_id.clone();
+        batch.push(completion);
+    }
+    for chunk in batch.chunks(PAGE_SIZE) {
+        client.mutation::<Value>(
+            "completions:batchUpsertCompletions",
+            json!({ "completions": chunk }),
+        )?;
+        let conn = db.conn();
+        for completion in chunk {
+            adopt(&conn, "completions", &completion.local_uuid, user_id)?;
+        }
+        report.pushed += chunk.len();
+    }
+
+    let remote: Vec<RemoteCompletion> =
+        pull_since(client, "completions:getCompletionsSince", since)?;
+    let mut conn = db.conn();
+    let tx = conn.transaction()?;
+    for completion in remote {
+        if pending_deletion(&tx, SyncTable::Completions, &completion.local_uuid)? {
+            continue;
+        }
+        let habit_id: Option<String> = match local_uuids.get(completion.habit_id.as_str()) {
+            Some(local_uuid) => tx
+                .query_row(
+                    "SELECT id FROM habits WHERE localUuid = ?1",
+                    [local_uuid],
+                    |row| row.get(0),
+                )
+                .optional()?,
+            None => None,
+        };
+        let Some(habit_id) = habit_id else {
+            report.skipped += 1;
+            continue;
+        };
+        let stamp = completion
+            .client_updated_at
+            .unwrap_or(completion.completed_at) as i64;
+        report.pulled += tx.execute(
+            "INSERT INTO completions (id, userId, habitId, completedAt, clientUpdatedAt)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(id) DO UPDATE SET
+                 habitId = excluded.habitId, completedAt = excluded.completedAt,
+                 clientUpdatedAt = excluded.clientUpdatedAt
+             WHERE excluded.clientUpdatedAt > completions.clientUpdatedAt",
+            params![
+                completion.local_uuid,
+                user_id,
+                habit_id,
+                completion.completed_at as i64,
+                stamp,
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(report)
+}
+
+fn sync_activity(
+    db: &Database,
+    client: &ConvexClient,
+    user_id: &str,
+    since: i64,
+) -> Result<TableReport> {
+    // Rows carry no timestamp; days from the last sync's day on may have changed
+    let since_date = if since == 0 {
+        String::new()
+    } else {
+        days::local_date_of(since).to_string()
+    };
+    let local: Vec<ActivityArgs> = {
+        let conn = db.conn();
+        let mut stmt = conn.prepare(
+            "SELECT localUuid, date FROM activityHistory
+             WHERE (userId = ?1 AND date >= ?2)
+                OR (userId IS NULL AND date NOT IN
+                    (SELECT date FROM activityHistory WHERE userId = ?1))
+             ORDER BY date",
+        )?;
+        let rows = stmt.query_map(params![user_id, since_date], |row| {
+            Ok(ActivityArgs {
+                local_uuid: row.get(0)?,
+                date: row.get(1)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    let mut report = TableReport::default();
+    for chunk in local.chunks(PAGE_SIZE) {
+        client.mutation::<Value>(
+            "activityHistory:batchUpsertActivityHistory",
+            json!({ "entries": chunk }),
+        )?;
+        report.pushed += chunk.len();
+    }
+    {
+        // Anonymous days the user already has are folded into theirs
+        let conn = db.conn();
+        conn.execute(
+            "UPDATE OR IGNORE activityHistory SET userId = ?1 WHERE userId IS NULL",
+            [user_id],
+        )?;
+        conn.execute(
+            "DELETE FROM activityHistory WHERE userId IS NULL AND date IN
+                 (SELECT date FROM activityHistory WHERE userId = ?1)",
+            [user_id],
+        )?;
+    }
+
+    let remote: Vec<RemoteActivity> =
+        pull_since(client, "activityHistory:getActivityHistorySince", since)?;
+    let mut conn = db.conn();
+    let tx = conn.transaction()?;
+    for activity in remote {
+        // One row per day; the unique indexes make this idempotent
+        report.pulled += tx.execute(
+            "INSERT OR IGNORE INTO activityHistory (id, userId, localUuid, date)
+             VALUES (?1, ?2, ?1, ?3)",
+            params![activity.local_uuid, user_id, activity.date],
+        )?;
+    }
+    tx.commit()?;
+    Ok(report)
+}
+
+// Gives a row made before signing in to the user once the server has it
+fn adopt(conn: &Connection, table: &str, id: &str, user_id: &str) -> Result<()> {
+    conn.execute(
+        &format!("UPDATE {table} SET userId = ?2 WHERE id = ?1 AND userId IS NULL"),
+        params![id, user_id],
+    )?;
+    Ok(())
+}
+
+// --- Tauri commands ---
+
+/// Starts a sync in the background with the stored sign-in token. The
+/// [`SyncReport`] arrives as `SYNCED_EVENT`.
+#[tauri::command]
+pub fn sync_now<R: Runtime>(app: AppHandle<R>, convex_url: String, user_id: String) -> Result<()> {
+    if app.try_state::<Database>().is_none() {
+        return Err(Error::InvalidInput(
+            "unlock the database before syncing".into(),
+        ));
+    }
+    let token = credentials::read_token(&credentials::fallback(&app)?)?
+        .ok_or_else(|| Error::InvalidInput("sign in before syncing".into()))?;
+    let running =
+        Running::start().ok_or_else(|| Error::InvalidInput("a sync is already running".into()))?;
+    let client = ConvexClient::new(&convex_url, Zeroizing::new(token));
+    // A failed spawn drops the closure, and `running` with it
+    std::thread::Builder::new()
+        .name("habistat-sync".into())
+        .spawn(move || {
+            // Managed state is never removed once the database is unlocked
+            let report = sync(&app.state::<Database>(), &client, &user_id);
+            drop(running);
+            let _ = app.emit(SYNCED_EVENT, &report);
+        })?;
+    Ok(())
+}
+
+// Holds `RUNNING` for one sync and releases it when dropped, even by a panic
+struct Running;
+
+impl Running {
+    fn start() -> Option<Self> {
+        (!RUNNING.swap(true, Ordering::SeqCst)).then_some(Running)
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+
+    use crate::backup::{self, ImportMode};
+    use crate::calendars::{self, NewCalendar};
+    use crate::completions;
+    use crate::habits::{self, NewHabit};
+
+    const TOKEN: &str = "test-token";
+    // Small pages so the cursor loop is exercised
+    const MOCK_PAGE: usize = 2;
+
+    /// In-memory stand-in for the deployment, mirroring the upsert rules of
+    /// `src/convex/*.ts`.
+    #[derive(Default)]
+    struct MockConvex {
+        calendars: Vec<Value>,
+        habits: Vec<Value>,
+        completions: Vec<Value>,
+        activity: Vec<Value>,
+        next_id: usize,
+        /// Function that fails with a server error.
+        failing: Option<&'static str>,
+        /// Every function called, in order.
+        calls: Vec<String>,
+    }
+
+    fn ok(value: Value) -> (u16, Value) {
+        (
+            200,
+            json!({ "status": "success", "value": value, "logLines": [] }),
+        )
+    }
+
+    fn fail(message: &str) -> (u16, Value) {
+        (
+            400,
+            json!({ "status": "error", "errorMessage": message, "logLines": [] }),
+        )
+    }
+
+    // Like Convex validators: no unknown fields and no nulls
+    fn valid(args: &Value, allowed: &[&str]) -> bool {
+        args.as_object().is_some_and(|fields| {
+            fields
+                .iter()
+                .all(|(name, value)| allowed.contains(&name.as_str()) && !value.is_null())
+        })
+    }
+
+    fn page(rows: Vec<Value>, key: &str, args: &Value) -> Value {
+        let start: usize = args["cursor"].as_str().map_or(0, |c| c.parse().unwrap());
+        let end = (start + MOCK_PAGE).min(rows.len());
+        json!({
+            key: rows[start..end],
+            "nextCursor": end.to_string(),
+            "isDone": end == rows.len(),
+        })
+    }
+
+    impl MockConvex {
+        fn id(&mut self, prefix: &str) -> String {
+            self.next_id += 1;
+            format!("{prefix}{}", self.next_id)
+        }
+
+        fn upsert_lww(rows: &mut Vec<Value>, args: &Value, id: String) {
+            match rows
+                .iter_mut()
+                .find(|row| row["localUuid"] == args["localUuid"])
+            {
+                Some(row) => {
+                    if args["updatedAt"].as_f64() > row["updatedAt"].as_f64() {
+                        for (name, value) in args.as_object().unwrap() {
+                            row[name] = value.clone();
+                        }
+                    }
+                }
+                None => {
+                    let mut row = args.clone();
+                    row["_id"] = json!(id);
+                    rows.push(row);
+                }
+            }
+        }
+
+        fn handle(&mut self, path: &str, args: &Value) -> (u16, Value) {
+            self.calls.push(path.into());
+            if self.failing == Some(path) {
+                return fail("Server Error");
+            }
+            match path {
+                "calendars:getUserCalendars" => ok(json!(self.calendars)),
+                "calendars:createCalendar" => {
+                    let fields = [
+                        "localUuid",
+                        "name",
+                        "colorTheme",
+                        "position",
+                        "isEnabled",
+                        "createdAt",
+                        "updatedAt",
+                    ];
+                    if !valid(args, &fields) {
+                        return fail("ArgumentValidationError");
+                    }
+                    let id = self.id("c");
+                    Self::upsert_lww(&mut self.calendars, args, id);
+                    ok(json!(null))
+                }
+                "calendars:deleteCalendar" => {
+                    let before = self.calendars.len();
+                    self.calendars
+                        .retain(|row| row["localUuid"] != args["localUuid"]);
+                    ok(if self.calendars.len() < before {
+                        json!("deleted")
+                    } else {
+                        json!(null)
+                    })
+                }
+                "habits:getUserHabits" => ok(json!(self.habits)),
+                "habits:deleteHabit" => {
+                    let Some(index) = self
+                        .habits
+                        .iter()
+                        .position(|row| row["localUuid"] == args["localUuid"])
+                    else {
+                        return fail("Uncaught Error: Habit not found");
+                    };
+                    let habit = self.habits.remove(index);
+                    self.completions
+                        .retain(|row| row["habitId"] != habit["_id"]);
+                    ok(json!(null))
+                }
+                "habits:createHabit" => {
+                    let fields = [
+                        "localUuid",
+                        "calendarId",
+                        "name",
+                        "description",
+                        "type",
+                        "timerEnabled",
+                        "targetDurationSeconds",
+                        "pointsValue",
+                        "position",
+                        "isEnabled",
+                        "createdAt",
+                        "updatedAt",
+                    ];
+                    if !valid(args, &fields) {
+                        return fail("ArgumentValidationError");
+                    }
+                    let id = self.id("h");
+                    Self::upsert_lww(&mut self.habits, args, id);
+                    ok(json!(null))
+                }
+                "completions:getCompletionsSince" => {
+                    let since = args["timestamp"].as_f64().unwrap();
+                    let rows = self
+                        .completions
+                        .iter()
+                        .filter(|row| row["completedAt"].as_f64().unwrap() > since)
+                        .cloned()
+                        .collect();
+                    ok(page(rows, "completions", args))
+                }
+                "completions:batchUpsertCompletions" => {
+                    for completion in args["completions"].as_array().unwrap() {
+                        if !valid(completion, &["localUuid", "habitId", "completedAt"]) {
+                            return fail("ArgumentValidationError");
+                        }
+                        let existing = self
+                            .completions
+                            .iter_mut()
+                            .find(|row| row["localUuid"] == completion["localUuid"]);
+                        match existing {
+                            Some(row) => *row = completion.clone(),
+                            None => self.completions.push(completion.clone()),
+                        }
+                    }
+                    ok(json!([]))
+                }
+                "completions:deleteLatestCompletionForDay" => {
+                    if !valid(args, &["habitId", "dayStartMs", "dayEndMs"]) {
+                        return fail("ArgumentValidationError");
+                    }
+                    let (start, end) = (args["dayStartMs"].as_f64(), args["dayEndMs"].as_f64());
+                    let latest = self
+                        .completions
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, row)| {
+                            row["habitId"] == args["habitId"]
+                                && row["completedAt"].as_f64() >= start
+                                && row["completedAt"].as_f64() < end
+                        })
+                        .max_by(|(_, a), (_, b)| {
+                            a["completedAt"]
+                                .as_f64()
+                                .partial_cmp(&b["completedAt"].as_f64())
+                                .unwrap()
+                        })
+                        .map(|(index, _)| index);
+                    match latest {
+                        Some(index) => {
+                            let row = self.completions.remove(index);
+                            ok(json!({ "deleted": true, "localUuid": row["localUuid"] }))
+                        }
+                        None => ok(json!({ "deleted": false })),
+                    }
+                }
+                "activityHistory:getActivityHistorySince" => {
+                    ok(page(self.activity.clone(), "activityHistory", args))
+                }
+                "activityHistory:batchUpsertActivityHistory" => {
+                    for entry in args["entries"].as_array().unwrap() {
+                        match self
+                            .activity
+                            .iter_mut()
+                            .find(|row| row["date"] == entry["date"])
+                        {
+                            Some(row) => row["localUuid"] = entry["localUuid"].clone(),
+                            None => self.activity.push(entry.clone()),
+                        }
+                    }
+                    ok(json!({ "processed": 0, "results": [] }))
+                }
+                _ => fail(&format!("Could not find public function for '{path}'")),
+            }
+        }
+    }
+
+    fn respond(stream: TcpStream, server: &Mutex<MockConvex>) {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let (mut length, mut authorization) = (0, String::new());
+        loop {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            let Some((name, value)) = line.trim_end().split_once(':') else {
+                break;
+            };
+            match name.to_ascii_lowercase().as_str() {
+                "content-length" => length = value.trim().parse().unwrap(),
+                "authorization" => authorization = value.trim().to_string(),
+                _ => {}
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        let request: Value = serde_json::from_slice(&body).unwrap();
+
+        let (status, reply) = if authorization != format!("Bearer {TOKEN}") {
+            (401, json!({ "code": "Unauthenticated" }))
+        } else {
+            let path = request["path"].as_str().unwrap();
+            server.lock().unwrap().handle(path, &request["args"])
+        };
+        let reply = reply.to_string();
+        let mut stream = reader.into_inner();
+        write!(
+            stream,
+            "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{reply}",
+            reply.len()
+        )
+        .unwrap();
+    }
+
+    /// Serves `server` on a free local port and returns its URL.
+    fn serve(server: Arc<Mutex<MockConvex>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                respond(stream.unwrap(), &server);
+            }
+        });
+        url
+    }
+
+    fn calendar_names(conn: &Connection) -> Vec<String> {
+        let mut names: Vec<String> = calendars::list(conn)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn remote_calendar(uuid: &str, name: &str, updated_at: i64) -> Value {
+        json!({
+            "_id": format!("remote-{uuid}"), "localUuid": uuid, "name": name,
+            "colorTheme": "blue", "position": 0, "isEnabled": true,
+            "createdAt": 1_000, "updatedAt": updated_at,
+        })
+    }
+
+    fn remote_habit(convex_id: &str, uuid: &str, calendar_id: &str, name: &str) -> Value {
+        json!({
+            "_id": convex_id, "localUuid": uuid, "calendarId": calendar_id,
+            "name": name, "type": "positive", "timerEnabled": false,
+            "position": 0.0, "isEnabled": true, "createdAt": 1_000.0, "updatedAt": 1_000.0,
+        })
+    }
+
+    fn remote_completions(convex_habit_id: &str, count: i64) -> Vec<Value> {
+        (0..count)
+            .map(|n| {
+                json!({
+                    "_id": format!("done-{n}"), "localUuid": format!("done-remote-{n}"),
+                    "habitId": convex_habit_id, "completedAt": 5_000 + n,
+                })
+            })
+            .collect()
+    }
+
+    fn new_calendar(conn: &Connection, name: &str) -> calendars::Calendar {
+        calendars::create(
+            conn,
+            NewCalendar {
+                name: name.into(),
+                color_theme: "green".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap()
+    }
+
+    fn new_habit(conn: &Connection, calendar_id: &str, name: &str) -> habits::Habit {
+        habits::create(
+            conn,
+            NewHabit {
+                timer_enabled: true,
+                ..NewHabit::named(calendar_id, name)
+            },
+        )
+        .unwrap()
+    }
+
+    /// A mock deployment holding `setup`'s rows, and a client for it.
+    fn connect(setup: impl FnOnce(&mut MockConvex)) -> (Arc<Mutex<MockConvex>>, ConvexClient) {
+        let mut server = MockConvex::default();
+        setup(&mut server);
+        let server = Arc::new(Mutex::new(server));
+        let url = serve(Arc::clone(&server));
+        (
+            server,
+            ConvexClient::new(&url, Zeroizing::new(TOKEN.into())),
+        )
+    }
+
+    fn table(report: &SyncReport, table: SyncTable) -> &TableReport {
+        report
+            .tables
+            .iter()
+            .find(|t| t.table == table.name())
+            .unwrap()
+    }
+
+    #[test]
+    fn syncs_both_ways_with_last_write_wins() {
+        let (server, client) = connect(|server| {
+            server.calendars = vec![
+                remote_calendar("cal-shared", "Server wins", 2_000),
+                remote_calendar("cal-mine", "Local wins", 2_000),
+            ];
+            server.habits = vec![remote_habit(
+                "h-remote",
+                "habit-remote",
+                "cal-shared",
+                "Stretch",
+            )];
+            server.completions = remote_completions("h-remote", 3);
+            server.activity = vec![json!({ "localUuid": "day-remote", "date": "2024-03-02" })];
+        });
+
+        let db = Database::open_in_memory().unwrap();
+        let habit_id = {
+            let conn = db.conn();
+            conn.execute_batch(
+                "INSERT INTO calendars (id, localUuid, name, colorTheme, position, createdAt, updatedAt)
+                     VALUES ('cal-shared', 'cal-shared', 'Stale', 'blue', 0, 1000, 1500),
+                            ('cal-mine', 'cal-mine', 'Edited here', 'blue', 1, 1000, 3000);
+                 INSERT INTO activityHistory (id, userId, localUuid, date)
+                     VALUES ('day-local', NULL, 'day-local', '2024-03-01');",
+            )
+            .unwrap();
+            let calendar = new_calendar(&conn, "New here");
+            let habit = new_habit(&conn, &calendar.id, "Meditate");
+            completions::log_timed(&conn, &habit.id, 6_000, 600, None).unwrap();
+            habit.id
+        };
+
+        let report = sync(&db, &client, "user_1");
+        assert!(report.succeeded(), "{report:?}");
+        let conn = db.conn();
+        assert_eq!(
+            calendar_names(&conn),
+            vec!["Edited here", "New here", "Server wins"]
+        );
+        let owners: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM calendars WHERE userId IS NOT 'user_1'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(owners, 0);
+        assert_eq!(habits::get(&conn, "habit-remote").unwrap().name, "Stretch");
+        assert_eq!(
+            completions::list_all(&conn, "habit-remote").unwrap().len(),
+            3
+        );
+        let days: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM activityHistory WHERE userId = 'user_1'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(days, 2);
+        for table in SyncTable::ALL {
+            assert!(last_sync(&conn, table).unwrap() >= report.started_at);
+        }
+        drop(conn);
+
+        {
+            let server = server.lock().unwrap();
+            let mine = server
+                .calendars
+                .iter()
+                .find(|c| c["localUuid"] == "cal-mine")
+                .unwrap();
+            assert_eq!(mine["name"], "Edited here");
+            let shared = server
+                .calendars
+                .iter()
+                .find(|c| c["localUuid"] == "cal-shared")
+                .unwrap();
+            assert_eq!(shared["name"], "Server wins");
+            assert_eq!(server.calendars.len(), 3);
+            // Pushed completions reference the Convex id of their habit
+            let habit = server
+                .habits
+                .iter()
+                .find(|h| h["localUuid"] == json!(habit_id))
+                .unwrap();
+            let pushed = server
+                .completions
+                .iter()
+                .find(|c| c["completedAt"] == 6_000)
+                .unwrap();
+            assert_eq!(pushed["habitId"], habit["_id"]);
+            assert_eq!(server.activity.len(), 2);
+        }
+
+        // Nothing changed since, so nothing is pushed again
+        let report = sync(&db, &client, "user_1");
+        assert!(report.succeeded(), "{report:?}");
+        assert!(
+            report.tables.iter().all(|table| table.pushed == 0),
+            "{report:?}"
+        );
+    }
+
+    #[test]
+    fn rejected_token_fails_every_table() {
+        let (_, client) = connect(|_| {});
+        let db = Database::open_in_memory().unwrap();
+        new_calendar(&db.conn(), "Mind");
+
+        let report = sync(
+            &db,
+            &ConvexClient::new(&client.url, Zeroizing::new("expired".into())),
+            "user_1",
+        );
+        assert!(report.tables.iter().all(|table| table.error.is_some()));
+        for table in SyncTable::ALL {
+            assert_eq!(last_sync(&db.conn(), table).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn skips_habits_whose_calendar_is_missing() {
+        let (_, client) = connect(|server| {
+            server.habits = vec![
+                remote_habit("h-1", "habit-orphan", "cal-gone", "Orphan"),
+                remote_habit("h-2", "habit-kept", "cal-here", "Kept"),
+            ];
+            server.calendars = vec![remote_calendar("cal-here", "Here", 1_000)];
+        });
+        let db = Database::open_in_memory().unwrap();
+
+        let report = sync(&db, &client, "user_1");
+        assert!(report.succeeded(), "{report:?}");
+        let habits = table(&report, SyncTable::Habits);
+        assert_eq!((habits.pulled, habits.skipped), (1, 1));
+        let conn = db.conn();
+        assert!(habits::get(&conn, "habit-kept").is_ok());
+        assert!(matches!(
+            habits::get(&conn, "habit-orphan"),
+            Err(Error::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn skips_completions_of_unmapped_habits() {
+        let (server, client) = connect(|server| {
+            server.completions = remote_completions("h-unknown", 1);
+        });
+        let db = Database::open_in_memory().unwrap();
+        let habit = {
+            let conn = db.conn();
+            let calendar = new_calendar(&conn, "Mind");
+            new_habit(&conn, &calendar.id, "Read")
+        };
+        completions::log(&db.conn(), &habit.id, Some(6_000), None).unwrap();
+        // The habit never reaches the server, so its completion has no Convex id
+        server.lock().unwrap().failing = Some("habits:createHabit");
+
+        let report = sync(&db, &client, "user_1");
+        let completions = table(&report, SyncTable::Completions);
+        assert!(completions.error.is_none(), "{report:?}");
+        // One local completion without a Convex habit, one remote without a local one
+        assert_eq!((completions.pushed, completions.skipped), (0, 2));
+        let count: i64 = db
+            .conn()
+            .query_row("SELECT count(*) FROM completions", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(server.lock().unwrap().completions.len(), 1);
+    }
+
+    #[test]
+    fn failed_table_keeps_its_timestamp() {
+        let (server, client) = connect(|server| {
+            server.failing = Some("habits:getUserHabits");
+        });
+        let db = Database::open_in_memory().unwrap();
+        {
+            let conn = db.conn();
+            let calendar = new_calendar(&conn, "Mind");
+            new_habit(&conn, &calendar.id, "Read");
+        }
+
+        let report = sync(&db, &client, "user_1");
+        assert!(!report.succeeded());
+        assert!(table(&report, SyncTable::Calendars).error.is_none());
+        assert!(table(&report, SyncTable::Habits).error.is_some());
+        assert!(last_sync(&db.conn(), SyncTable::Calendars).unwrap() >= report.started_at);
+        assert_eq!(last_sync(&db.conn(), SyncTable::Habits).unwrap(), 0);
+
+        // The habit was pushed before the pull failed; the retry is harmless
+        server.lock().unwrap().failing = None;
+        let report = sync(&db, &client, "user_1");
+        assert!(report.succeeded(), "{report:?}");
+        assert!(last_sync(&db.conn(), SyncTable::Habits).unwrap() >= report.started_at);
+        assert_eq!(server.lock().unwrap().habits.len(), 1);
+    }
+
+    #[test]
+    fn pagination_stops_when_done() {
+        let (server, client) = connect(|server| {
+            server.calendars = vec![remote_calendar("cal-1", "Mind", 1_000)];
+            server.habits = vec![remote_habit("h-1", "habit-1", "cal-1", "Read")];
+            server.completions = remote_completions("h-1", 5);
+        });
+        let db = Database::open_in_memory().unwrap();
+
+        let report = sync(&db, &client, "user_1");
+        assert!(report.succeeded(), "{report:?}");
+        assert_eq!(table(&report, SyncTable::Completions).pulled, 5);
+        // Pages of 2, 2 and 1; the last says it is done despite its cursor
+        let server = server.lock().unwrap();
+        let pages = server
+            .calls
+            .iter()
+            .filter(|path| *path == "completions:getCompletionsSince")
+            .count();
+        assert_eq!(pages, 3);
+    }
+
+    #[test]
+    fn deleted_rows_stay_deleted() {
+        let (server, client) = connect(|_| {});
+        let db = Database::open_in_memory().unwrap();
+        let (mind, read, body) = {
+            let conn = db.conn();
+            let mind = new_calendar(&conn, "Mind");
+            let read = new_habit(&conn, &mind.id, "Read");
+            new_habit(&conn, &mind.id, "Write");
+            let body = new_calendar(&conn, "Body");
+            new_habit(&conn, &body.id, "Lift");
+            (mind, read, body)
+        };
+        completions::log(&db.conn(), &read.id, Some(6_000), None).unwrap();
+        assert!(sync(&db, &client, "user_1").succeeded());
+        assert_eq!(server.lock().unwrap().habits.len(), 3);
+
+        habits::delete(&mut db.conn(), &read.id).unwrap();
+        // Its other habit goes with the calendar
+        calendars::delete(&db.conn(), &body.id).unwrap();
+        let report = sync(&db, &client, "user_1");
+        assert!(report.succeeded(), "{report:?}");
+        assert_eq!(table(&report, SyncTable::Calendars).deleted, 1);
+        assert_eq!(table(&report, SyncTable::Habits).deleted, 2);
+
+        let conn = db.conn();
+        assert_eq!(calendar_names(&conn), vec!["Mind"]);
+        let names: Vec<String> = habits::list(&conn, None)
+            .unwrap()
+            .into_iter()
+            .map(|h| h.name)
+            .collect();
+        assert_eq!(names, vec!["Write"]);
+        let pending: i64 = conn
+            .query_row("SELECT count(*) FROM pendingDeletions", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(pending, 0);
+        drop(conn);
+        {
+            let server = server.lock().unwrap();
+            assert_eq!(server.calendars.len(), 1);
+            assert_eq!(server.habits.len(), 1);
+            assert!(server.completions.is_empty());
+        }
+
+        // A full pull does not bring them back either
+        db.conn().execute("DELETE FROM syncMetadata", []).unwrap();
+        assert!(sync(&db, &client, "user_1").succeeded());
+        assert_eq!(habits::list(&db.conn(), Some(&mind.id)).unwrap().len(), 1);
+        assert_eq!(calendar_names(&db.conn()), vec!["Mind"]);
+    }
+
+    #[test]
+    fn undone_completions_are_deleted_on_the_server() {
+        let (server, client) = connect(|_| {});
+        let db = Database::open_in_memory().unwrap();
+        let read = {
+            let conn = db.conn();
+            let mind = new_calendar(&conn, "Mind");
+            new_habit(&conn, &mind.id, "Read")
+        };
+        let day = "2024-03-02".parse().unwrap();
+        let (start, _) = days::local_day_bounds(day);
+        for completed_at in [start + 6_000, start + 7_000] {
+            completions::log(&db.conn(), &read.id, Some(completed_at), None).unwrap();
+        }
+        assert!(sync(&db, &client, "user_1").succeeded());
+        assert_eq!(server.lock().unwrap().completions.len(), 2);
+
+        let undone = completions::undo_last(&db.conn(), &read.id, day)
+            .unwrap()
+            .unwrap();
+        let report = sync(&db, &client, "user_1");
+        assert!(report.succeeded(), "{report:?}");
+        assert_eq!(table(&report, SyncTable::Completions).deleted, 1);
+        {
+            let server = server.lock().unwrap();
+            assert_eq!(server.completions.len(), 1);
+            assert_eq!(server.completions[0]["completedAt"], json!(start + 6_000));
+            assert!(!server
+                .completions
+                .iter()
+                .any(|row| row["localUuid"] == json!(undone.id)));
+        }
+        assert!(!pending_deletion(&db.conn(), SyncTable::Completions, &undone.id).unwrap());
+
+        // A full pull does not bring it back either
+        db.conn().execute("DELETE FROM syncMetadata", []).unwrap();
+        assert!(sync(&db, &client, "user_1").succeeded());
+        assert_eq!(
+            completions::list_all(&db.conn(), &read.id).unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn failed_deletion_is_retried() {
+        let (server, client) = connect(|server| {
+            server.calendars = vec![remote_calendar("cal-1", "Mind", 1_000)];
+            server.habits = vec![remote_habit("h-1", "habit-1", "cal-1", "Read")];
+            server.failing = Some("habits:deleteHabit");
+        });
+        let db = Database::open_in_memory().unwrap();
+        assert!(sync(&db, &client, "user_1").succeeded());
+        habits::delete(&mut db.conn(), "habit-1").unwrap();
+
+        let report = sync(&db, &client, "user_1");
+        assert!(table(&report, SyncTable::Habits).error.is_some());
+        assert!(pending_deletion(&db.conn(), SyncTable::Habits, "habit-1").unwrap());
+        assert!(habits::get(&db.conn(), "habit-1").is_err());
+
+        server.lock().unwrap().failing = None;
+        let report = sync(&db, &client, "user_1");
+        assert!(report.succeeded(), "{report:?}");
+        assert!(!pending_deletion(&db.conn(), SyncTable::Habits, "habit-1").unwrap());
+        assert!(server.lock().unwrap().habits.is_empty());
+    }
+
+    #[test]
+    fn replace_import_does_not_delete_restored_rows() {
+        let (server, client) = connect(|_| {});
+        let db = Database::open_in_memory().unwrap();
+        {
+            let conn = db.conn();
+            let calendar = new_calendar(&conn, "Mind");
+            let habit = new_habit(&conn, &calendar.id, "Read");
+            completions::log(&conn, &habit.id, Some(6_000), None).unwrap();
+        }
+        assert!(sync(&db, &client, "user_1").succeeded());
+        let exported = backup::export(&db.conn()).unwrap();
+
+        backup::import(&mut db.conn(), &exported, ImportMode::Replace).unwrap();
+        let pending: i64 = db
+            .conn()
+            .query_row("SELECT count(*) FROM pendingDeletions", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(pending, 0);
+
+        let report = sync(&db, &client, "user_1");
+        assert!(report.succeeded(), "{report:?}");
+        assert!(report.tables.iter().all(|table| table.deleted == 0));
+        let server = server.lock().unwrap();
+        assert_eq!(server.calendars.len(), 1);
+        assert_eq!(server.habits.len(), 1);
+        assert_eq!(server.completions.len(), 1);
+    }
+
+    #[test]
+    fn deletions_of_restored_rows_are_dropped() {
+        let (server, client) = connect(|_| {});
+        let db = Database::open_in_memory().unwrap();
+        let calendar = new_calendar(&db.conn(), "Mind");
+        assert!(sync(&db, &client, "user_1").succeeded());
+
+        // Deleted, then put back by something other than a backup import
+        calendars::delete(&db.conn(), &calendar.id).unwrap();
+        db.conn()
+            .execute(
+                "INSERT INTO calendars
+                     (id, userId, localUuid, name, colorTheme, position, createdAt, updatedAt)
+                 VALUES (?1, 'user_1', ?2, 'Mind', 'green', 0, 0, 0)",
+                [&calendar.id, &calendar.local_uuid],
+            )
+            .unwrap();
+        assert!(pending_deletion(&db.conn(), SyncTable::Calendars, &calendar.local_uuid).unwrap());
+
+        let report = sync(&db, &client, "user_1");
+        assert!(report.succeeded(), "{report:?}");
+        assert_eq!(table(&report, SyncTable::Calendars).deleted, 0);
+        assert!(!pending_deletion(&db.conn(), SyncTable::Calendars, &calendar.local_uuid).unwrap());
+        assert_eq!(server.lock().unwrap().calendars.len(), 1);
+    }
+
+    // A device that imported the same calendar, habit and completion as any
+    // other: fresh local ids, but the importer's stable localUuids
+    fn imported_device() -> Database {
+        let db = Database::open_in_memory().unwrap();
+        {
+            let conn = db.conn();
+            let calendar = new_calendar(&conn, "Habitica");
+            let habit = new_habit(&conn, &calendar.id, "Floss");
+            conn.execute(
+                "UPDATE calendars SET localUuid = 'import-cal' WHERE id = ?1",
+                [&calendar.id],
+            )
+            .unwrap();
+            conn.execute(
+                "UPDATE habits SET localUuid = 'import-habit' WHERE id = ?1",
+                [&habit.id],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO completions (id, habitId, completedAt, clientUpdatedAt)
+                 VALUES ('import-done', ?1, 6000, 6000)",
+                [&habit.id],
+            )
+            .unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn imported_rows_sync_by_local_uuid() {
+        let (server, client) = connect(|_| {});
+        let (first, second) = (imported_device(), imported_device());
+        assert!(sync(&first, &client, "user_1").succeeded());
+        let report = sync(&second, &client, "user_1");
+        assert!(report.succeeded(), "{report:?}");
+
+        {
+            let server = server.lock().unwrap();
+            assert_eq!(server.calendars.len(), 1);
+            assert_eq!(server.habits.len(), 1);
+            assert_eq!(server.habits[0]["localUuid"], "import-habit");
+            assert_eq!(server.habits[0]["calendarId"], "import-cal");
+            assert_eq!(server.completions.len(), 1);
+            assert_eq!(server.completions[0]["habitId"], server.habits[0]["_id"]);
+        }
+        // Pulling the other device's copies updates the local rows in place
+        let conn = second.conn();
+        assert_eq!(calendar_names(&conn), vec!["Habitica"]);
+        let imported = habits::list(&conn, None).unwrap();
+        assert_eq!(imported.len(), 1);
+        assert_eq!(
+            completions::list_all(&conn, &imported[0].id).unwrap().len(),
+            1
+        );
+
+        // Deleting the imported habit deletes it on the server too
+        drop(conn);
+        habits::delete(&mut second.conn(), &imported[0].id).unwrap();
+        let report = sync(&second, &client, "user_1");
+        assert!(report.succeeded(), "{report:?}");
+        assert_eq!(table(&report, SyncTable::Habits).deleted, 1);
+        assert!(server.lock().unwrap().habits.is_empty());
+    }
+}
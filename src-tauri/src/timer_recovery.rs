@@ -0,0 +1,304 @@
+// Recovery of timers that were running when the app last exited.
+//
+// A `running` row found at startup means the process went away mid-session
+// (crash, kill, or quit). Such timers are frozen as paused, announced via
+// `timer://recovered` and kept here until the user resumes, discards or logs
+// them through `resolve_recovered_timer`.
+
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+use crate::completions::{self, Completion};
+use crate::error::{Error, Result};
+use crate::storage::{now_millis, Database};
+use crate::timers::{self, ActiveTimer, TimerStatus};
+
+pub const RECOVERED_EVENT: &str = "timer://recovered";
+
+/// Longest session a recovered timer may be logged as. A timer left running
+/// for days would otherwise turn into a multi-day "session".
+pub const MAX_RECOVERED_SESSION_SECONDS: i64 = 4 * 60 * 60;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveredTimer {
+    pub timer: ActiveTimer,
+    /// Time since `startTime` minus paused durations, up to the moment of recovery.
+    pub elapsed_seconds: i64,
+    /// What logging the timer would record, `elapsedSeconds` capped at
+    /// [`MAX_RECOVERED_SESSION_SECONDS`].
+    pub loggable_seconds: i64,
+    pub exceeds_cap: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RecoveryAction {
+    Resume,
+    Discard,
+    LogCompletion,
+}
+
+/// Outcome of [`resolve`]; `timer` is set when resumed, `completion` when logged.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryResult {
+    pub timer: Option<ActiveTimer>,
+    pub completion: Option<Completion>,
+}
+
+/// Recovered timers awaiting a decision, registered as managed state in `run()`.
+#[derive(Default)]
+pub struct TimerRecovery(Mutex<Vec<RecoveredTimer>>);
+
+impl TimerRecovery {
+    fn pending(&self) -> std::sync::MutexGuard<'_, Vec<RecoveredTimer>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Freezes every `running` timer as paused at `now` and reports it.
+///
+/// Pausing keeps the background engine from ticking or auto-completing a
+/// session nobody is attending to while the user decides.
+pub fn detect(conn: &mut Connection, now: i64) -> Result<Vec<RecoveredTimer>> {
+    let tx = conn.transaction()?;
+    let mut recovered = Vec::new();
+    for mut timer in timers::list(&tx)? {
+        if timer.status != TimerStatus::Running {
+            continue;
+        }
+        tx.execute(
+            "UPDATE activeTimers SET status = ?2, pausedTime = ?3, updatedAt = ?3 WHERE id = ?1",
+            params![timer.id, TimerStatus::Paused, now],
+        )?;
+        timer.status = TimerStatus::Paused;
+        timer.paused_time = Some(now);
+        timer.updated_at = now;
+
+        let elapsed_seconds = timer.elapsed_seconds(now);
+        recovered.push(RecoveredTimer {
+            timer,
+            elapsed_seconds,
+            loggable_seconds: elapsed_seconds.min(MAX_RECOVERED_SESSION_SECONDS),
+            exceeds_cap: elapsed_seconds > MAX_RECOVERED_SESSION_SECONDS,
+        });
+    }
+    tx.commit()?;
+    Ok(recovered)
+}
+
+/// Applies the user's decision for a recovered timer at `now`.
+pub fn resolve(
+    conn: &mut Connection,
+    recovered: &RecoveredTimer,
+    action: RecoveryAction,
+    now: i64,
+) -> Result<RecoveryResult> {
+    let timer = &recovered.timer;
+    match action {
+        RecoveryAction::Resume => {
+            // The downtime counts as paused, so the session picks up at its
+            // capped length instead of auto-completing with days on the clock
+            let paused_seconds = ((now - timer.start_time) / 1000 - recovered.loggable_seconds)
+                .max(timer.total_paused_duration_seconds);
+            let resumed = conn.execute(
+                "UPDATE activeTimers SET status = ?2, pausedTime = NULL,
+                    totalPausedDurationSeconds = ?3, updatedAt = ?4
+                 WHERE id = ?1",
+                params![timer.id, TimerStatus::Running, paused_seconds, now],
+            )?;
+            if resumed == 0 {
+                return Err(Error::not_found("timer", &timer.id));
+            }
+            Ok(RecoveryResult {
+                timer: timers::for_habit(conn, &timer.habit_id)?,
+                completion: None,
+            })
+        }
+        RecoveryAction::Discard => {
+            conn.execute("DELETE FROM activeTimers WHERE id = ?1", [&timer.id])?;
+            Ok(RecoveryResult::default())
+        }
+        RecoveryAction::LogCompletion => {
+            // Date the completion at the end of the (capped) session, not at recovery time
+            let session_end = timer.start_time
+                + (timer.total_paused_duration_seconds + recovered.loggable_seconds) * 1000;
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM activeTimers WHERE id = ?1", [&timer.id])?;
+            let completion = completions::log_timed(
+                &tx,
+                &timer.habit_id,
+                session_end,
+                recovered.loggable_seconds,
+                timer.user_id.as_deref(),
+            )?;
+            tx.commit()?;
+            Ok(RecoveryResult {
+                timer: None,
+                completion: Some(completion),
+            })
+        }
+    }
+}
+
+/// Detects interrupted timers at startup, stores them and notifies the webview.
+///
+/// Must run before the timer service starts ticking.
+pub fn init<R: Runtime>(app: &AppHandle<R>) -> Result<()> {
+    let recovered = detect(&mut app.state::<Database>().conn(), now_millis())?;
+    if !recovered.is_empty() {
+        // The webview may not be listening yet; it can also poll `get_recovered_timers`
+        let _ = app.emit(RECOVERED_EVENT, &recovered);
+    }
+    app.manage(TimerRecovery(Mutex::new(recovered)));
+    Ok(())
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn get_recovered_timers(recovery: State<'_, TimerRecovery>) -> Vec<RecoveredTimer> {
+    recovery.pending().clone()
+}
+
+#[tauri::command]
+pub fn resolve_recovered_timer(
+    db: State<'_, Database>,
+    recovery: State<'_, TimerRecovery>,
+    timer_id: String,
+    action: RecoveryAction,
+) -> Result<RecoveryResult> {
+    let mut pending = recovery.pending();
+    let index = pending
+        .iter()
+        .position(|r| r.timer.id == timer_id)
+        .ok_or_else(|| Error::not_found("recovered timer", &timer_id))?;
+    let result = resolve(&mut db.conn(), &pending[index], action, now_millis())?;
+    pending.remove(index);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendars::{self, NewCalendar};
+    use crate::habits::{self, NewHabit};
+
+    const SECOND: i64 = 1000;
+    const DAY: i64 = 24 * 60 * 60 * SECOND;
+
+    // A habit with a running timer started at `start_time`
+    fn interrupted(conn: &Connection, start_time: i64) -> String {
+        let calendar = calendars::create(
+            conn,
+            NewCalendar {
+                name: "Focus".into(),
+                color_theme: "indigo".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap();
+        let habit = habits::create(
+            conn,
+            NewHabit {
+                timer_enabled: true,
+                target_duration_seconds: Some(MAX_RECOVERED_SESSION_SECONDS + 3600),
+                ..NewHabit::named(&calendar.id, "Deep work")
+            },
+        )
+        .unwrap();
+        timers::start(conn, &habit.id, None).unwrap();
+        conn.execute(
+            "UPDATE activeTimers SET startTime = ?2 WHERE habitId = ?1",
+            params![habit.id, start_time],
+        )
+        .unwrap();
+        habit.id
+    }
+
+    #[test]
+    fn detect_freezes_running_timers_and_caps_them() {
+        let db = Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        let now = now_millis();
+        let habit_id = interrupted(&conn, now - 3 * DAY);
+
+        let recovered = detect(&mut conn, now).unwrap();
+        assert_eq!(recovered.len(), 1);
+        let recovered = &recovered[0];
+        assert_eq!(recovered.elapsed_seconds, 3 * DAY / SECOND);
+        assert_eq!(recovered.loggable_seconds, MAX_RECOVERED_SESSION_SECONDS);
+        assert!(recovered.exceeds_cap);
+        let timer = timers::for_habit(&conn, &habit_id).unwrap().unwrap();
+        assert_eq!(timer.status, TimerStatus::Paused);
+        assert_eq!(timer.paused_time, Some(now));
+
+        // Already paused timers are left alone
+        assert!(detect(&mut conn, now + SECOND).unwrap().is_empty());
+    }
+
+    #[test]
+    fn resume_continues_from_the_capped_session() {
+        let db = Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        let now = now_millis();
+        let habit_id = interrupted(&conn, now - 3 * DAY);
+        let recovered = detect(&mut conn, now).unwrap().remove(0);
+
+        let resumed_at = now + 30 * SECOND;
+        let timer = resolve(&mut conn, &recovered, RecoveryAction::Resume, resumed_at)
+            .unwrap()
+            .timer
+            .unwrap();
+        assert_eq!(timer.status, TimerStatus::Running);
+        assert_eq!(
+            timer.elapsed_seconds(resumed_at),
+            MAX_RECOVERED_SESSION_SECONDS
+        );
+
+        // Stopping an hour later logs the capped session plus that hour
+        let logged = timer.elapsed_seconds(resumed_at + 3600 * SECOND);
+        assert_eq!(logged, MAX_RECOVERED_SESSION_SECONDS + 3600);
+        let mut engine = timers::TimerEngine::default();
+        let outcome = engine.tick(&mut conn, resumed_at + 60 * SECOND).unwrap();
+        assert!(outcome.reached.is_empty());
+        assert_eq!(timers::list(&conn).unwrap()[0].habit_id, habit_id);
+    }
+
+    #[test]
+    fn log_and_discard_remove_the_timer() {
+        let db = Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        let now = now_millis();
+        let start_time = now - 2 * DAY;
+        let habit_id = interrupted(&conn, start_time);
+        let recovered = detect(&mut conn, now).unwrap().remove(0);
+
+        let completion = resolve(&mut conn, &recovered, RecoveryAction::LogCompletion, now)
+            .unwrap()
+            .completion
+            .unwrap();
+        assert_eq!(
+            completion.duration_seconds,
+            Some(MAX_RECOVERED_SESSION_SECONDS)
+        );
+        assert_eq!(
+            completion.completed_at,
+            start_time + MAX_RECOVERED_SESSION_SECONDS * SECOND
+        );
+        assert!(timers::for_habit(&conn, &habit_id).unwrap().is_none());
+
+        let other = interrupted(&conn, now - 60 * SECOND);
+        let recovered = detect(&mut conn, now).unwrap().remove(0);
+        assert!(!recovered.exceeds_cap);
+        let result = resolve(&mut conn, &recovered, RecoveryAction::Discard, now).unwrap();
+        assert!(result.timer.is_none() && result.completion.is_none());
+        assert!(timers::for_habit(&conn, &other).unwrap().is_none());
+        assert!(completions::list_all(&conn, &other).unwrap().is_empty());
+    }
+}
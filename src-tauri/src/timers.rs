@@ -0,0 +1,590 @@
+// Habit timers backed by the `activeTimers` table.
+//
+// Commands start/pause/resume/stop timers; a background thread started from
+// `run()` ticks once per second, emits `timer://tick` for running timers and
+// `timer://target-reached` when a habit's `targetDurationSeconds` is hit, so
+// timers keep counting while the window is hidden.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+use crate::completions::{self, Completion};
+use crate::error::{Error, Result};
+use crate::habits;
+use crate::settings;
+use crate::storage::{new_id, now_millis, Database};
+
+pub const TICK_EVENT: &str = "timer://tick";
+pub const TARGET_REACHED_EVENT: &str = "timer://target-reached";
+
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+const SETTINGS_KEY: &str = "timers";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimerStatus {
+    Running,
+    Paused,
+}
+
+impl TimerStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TimerStatus::Running => "running",
+            TimerStatus::Paused => "paused",
+        }
+    }
+}
+
+impl ToSql for TimerStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+impl FromSql for TimerStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "running" => Ok(TimerStatus::Running),
+            "paused" => Ok(TimerStatus::Paused),
+            other => Err(FromSqlError::Other(
+                format!("unknown timer status `{other}`").into(),
+            )),
+        }
+    }
+}
+
+const COLUMNS: &str = "id, userId, habitId, startTime, pausedTime, totalPausedDurationSeconds, \
+     status, createdAt, updatedAt";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveTimer {
+    pub id: String,
+    pub user_id: Option<String>,
+    pub habit_id: String,
+    pub start_time: i64,
+    pub paused_time: Option<i64>,
+    pub total_paused_duration_seconds: i64,
+    pub status: TimerStatus,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl ActiveTimer {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            habit_id: row.get(2)?,
+            start_time: row.get(3)?,
+            paused_time: row.get(4)?,
+            total_paused_duration_seconds: row.get(5)?,
+            status: row.get(6)?,
+            created_at: row.get(7)?,
+            updated_at: row.get(8)?,
+        })
+    }
+
+    /// Seconds the timer has been running as of `now`, excluding paused spans.
+    pub fn elapsed_seconds(&self, now: i64) -> i64 {
+        let end = match (self.status, self.paused_time) {
+            (TimerStatus::Paused, Some(paused_at)) => paused_at,
+            _ => now,
+        };
+        ((end - self.start_time) / 1000 - self.total_paused_duration_seconds).max(0)
+    }
+}
+
+/// Persisted under the `timers` settings key.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TimerSettings {
+    /// Stop the timer and log a completion as soon as the target duration is reached.
+    pub auto_complete_on_target: bool,
+}
+
+/// Payload of [`TICK_EVENT`], one entry per running timer.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimerTick {
+    pub timer_id: String,
+    pub habit_id: String,
+    pub elapsed_seconds: i64,
+    pub target_duration_seconds: Option<i64>,
+}
+
+/// Payload of [`TARGET_REACHED_EVENT`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetReached {
+    pub timer_id: String,
+    pub habit_id: String,
+    pub elapsed_seconds: i64,
+    /// Set when the timer was auto-completed (see [`TimerSettings`]).
+    pub completion: Option<Completion>,
+}
+
+/// Result of [`stop`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoppedTimer {
+    pub elapsed_seconds: i64,
+    pub completion: Option<Completion>,
+}
+
+pub fn list(conn: &Connection) -> Result<Vec<ActiveTimer>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {COLUMNS} FROM activeTimers ORDER BY startTime"
+    ))?;
+    let rows = stmt.query_map([], ActiveTimer::from_row)?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// The timer of a habit, if one is active. There is at most one per habit.
+pub fn for_habit(conn: &Connection, habit_id: &str) -> Result<Option<ActiveTimer>> {
+    Ok(conn
+        .query_row(
+            &format!(
+                "SELECT {COLUMNS} FROM activeTimers WHERE habitId = ?1
+                 ORDER BY startTime DESC LIMIT 1"
+            ),
+            [habit_id],
+            ActiveTimer::from_row,
+        )
+        .optional()?)
+}
+
+fn require_for_habit(conn: &Connection, habit_id: &str) -> Result<ActiveTimer> {
+    for_habit(conn, habit_id)?.ok_or_else(|| Error::not_found("timer for habit", habit_id))
+}
+
+/// Starts a timer for the habit; a paused timer is resumed, a running one returned as is.
+pub fn start(conn: &Connection, habit_id: &str, user_id: Option<&str>) -> Result<ActiveTimer> {
+    let habit = habits::get(conn, habit_id)?;
+    if !habit.timer_enabled {
+        return Err(Error::InvalidInput(format!(
+            "timer is not enabled for habit `{habit_id}`"
+        )));
+    }
+
+    match for_habit(conn, habit_id)? {
+        Some(timer) if timer.status == TimerStatus::Paused => resume(conn, habit_id),
+        Some(timer) => Ok(timer),
+        None => {
+            let now = now_millis();
+            let timer = ActiveTimer {
+                id: new_id(),
+                user_id: user_id.map(str::to_string),
+                habit_id: habit_id.to_string(),
+                start_time: now,
+                paused_time: None,
+                total_paused_duration_seconds: 0,
+                status: TimerStatus::Running,
+                created_at: now,
+                updated_at: now,
+            };
+            conn.execute(
+                &format!(
+                    "INSERT INTO activeTimers ({COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"
+                ),
+                params![
+                    timer.id,
+                    timer.user_id,
+                    timer.habit_id,
+                    timer.start_time,
+                    timer.paused_time,
+                    timer.total_paused_duration_seconds,
+                    timer.status,
+                    timer.created_at,
+                    timer.updated_at
+                ],
+            )?;
+            Ok(timer)
+        }
+    }
+}
+
+pub fn pause(conn: &Connection, habit_id: &str) -> Result<ActiveTimer> {
+    let mut timer = require_for_habit(conn, habit_id)?;
+    if timer.status == TimerStatus::Paused {
+        return Ok(timer);
+    }
+    let now = now_millis();
+    timer.status = TimerStatus::Paused;
+    timer.paused_time = Some(now);
+    timer.updated_at = now;
+    conn.execute(
+        "UPDATE activeTimers SET status = ?2, pausedTime = ?3, updatedAt = ?3 WHERE id = ?1",
+        params![timer.id, timer.status, now],
+    )?;
+    Ok(timer)
+}
+
+pub fn resume(conn: &Connection, habit_id: &str) -> Result<ActiveTimer> {
+    let mut timer = require_for_habit(conn, habit_id)?;
+    if timer.status == TimerStatus::Running {
+        return Ok(timer);
+    }
+    let now = now_millis();
+    let paused_for = timer
+        .paused_time
+        .map_or(0, |paused_at| (now - paused_at) / 1000);
+    timer.total_paused_duration_seconds += paused_for.max(0);
+    timer.status = TimerStatus::Running;
+    timer.paused_time = None;
+    timer.updated_at = now;
+    conn.execute(
+        "UPDATE activeTimers SET status = ?2, pausedTime = NULL,
+            totalPausedDurationSeconds = ?3, updatedAt = ?4
+         WHERE id = ?1",
+        params![
+            timer.id,
+            timer.status,
+            timer.total_paused_duration_seconds,
+            now
+        ],
+    )?;
+    Ok(timer)
+}
+
+/// Stops the habit's timer, optionally logging a completion for the session.
+pub fn stop(conn: &mut Connection, habit_id: &str, log_completion: bool) -> Result<StoppedTimer> {
+    let tx = conn.transaction()?;
+    let timer = require_for_habit(&tx, habit_id)?;
+    let now = now_millis();
+    let completion = finish(&tx, &timer, now, log_completion)?;
+    tx.commit()?;
+    Ok(StoppedTimer {
+        elapsed_seconds: timer.elapsed_seconds(now),
+        completion,
+    })
+}
+
+// Removes the timer row and logs the completion in the caller's transaction
+fn finish(
+    conn: &Connection,
+    timer: &ActiveTimer,
+    now: i64,
+    log_completion: bool,
+) -> Result<Option<Completion>> {
+    conn.execute("DELETE FROM activeTimers WHERE id = ?1", [&timer.id])?;
+    if !log_completion {
+        return Ok(None);
+    }
+    completions::log_timed(
+        conn,
+        &timer.habit_id,
+        now,
+        timer.elapsed_seconds(now),
+        timer.user_id.as_deref(),
+    )
+    .map(Some)
+}
+
+/// Drives running timers; owned by the background thread.
+#[derive(Debug, Default)]
+pub struct TimerEngine {
+    // Timers whose target was already announced, so the event fires once
+    notified: HashSet<String>,
+}
+
+/// Events produced by one [`TimerEngine::tick`].
+#[derive(Debug, Default)]
+pub struct TickOutcome {
+    pub ticks: Vec<TimerTick>,
+    pub reached: Vec<TargetReached>,
+}
+
+impl TimerEngine {
+    pub fn tick(&mut self, conn: &mut Connection, now: i64) -> Result<TickOutcome> {
+        let mut outcome = TickOutcome::default();
+        let running: Vec<ActiveTimer> = list(conn)?
+            .into_iter()
+            .filter(|t| t.status == TimerStatus::Running)
+            .collect();
+        self.notified
+            .retain(|id| running.iter().any(|timer| &timer.id == id));
+        if running.is_empty() {
+            return Ok(outcome);
+        }
+
+        let timer_settings: TimerSettings = settings::get(conn, SETTINGS_KEY)?;
+        let tx = conn.transaction()?;
+        for timer in running {
+            let elapsed = timer.elapsed_seconds(now);
+            let target = habits::get(&tx, &timer.habit_id)
+                .ok()
+                .and_then(|habit| habit.target_duration_seconds);
+
+            if let Some(target) = target {
+                if elapsed >= target && !self.notified.contains(&timer.id) {
+                    // Auto-completion stops the timer and logs the session in one go
+                    let completion = if timer_settings.auto_complete_on_target {
+                        finish(&tx, &timer, now, true)?
+                    } else {
+                        None
+                    };
+                    self.notified.insert(timer.id.clone());
+                    let stopped = completion.is_some();
+                    outcome.reached.push(TargetReached {
+                        timer_id: timer.id.clone(),
+                        habit_id: timer.habit_id.clone(),
+                        elapsed_seconds: elapsed,
+                        completion,
+                    });
+                    if stopped {
+                        continue;
+                    }
+                }
+            }
+
+            outcome.ticks.push(TimerTick {
+                timer_id: timer.id,
+                habit_id: timer.habit_id,
+                elapsed_seconds: elapsed,
+                target_duration_seconds: target,
+            });
+        }
+        tx.commit()?;
+        Ok(outcome)
+    }
+}
+
+/// Spawns the background thread that ticks running timers.
+pub fn start_service<R: Runtime>(app: AppHandle<R>) -> std::io::Result<()> {
+    std::thread::Builder::new()
+        .name("habistat-timers".into())
+        .spawn(move || {
+            let mut engine = TimerEngine::default();
+            loop {
+                std::thread::sleep(TICK_INTERVAL);
+                let db = app.state::<Database>();
+                let outcome = engine.tick(&mut db.conn(), now_millis());
+                match outcome {
+                    Ok(outcome) => {
+                        if !outcome.ticks.is_empty() {
+                            let _ = app.emit(TICK_EVENT, outcome.ticks);
+                        }
+                        for reached in outcome.reached {
+                            let _ = app.emit(TARGET_REACHED_EVENT, reached);
+                        }
+                    }
+                    Err(e) => eprintln!("Timer tick failed: {e}"),
+                }
+            }
+        })?;
+    Ok(())
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn list_active_timers(db: State<'_, Database>) -> Result<Vec<ActiveTimer>> {
+    list(&db.conn())
+}
+
+#[tauri::command]
+pub fn start_timer(
+    db: State<'_, Database>,
+    habit_id: String,
+    user_id: Option<String>,
+) -> Result<ActiveTimer> {
+    start(&db.conn(), &habit_id, user_id.as_deref())
+}
+
+#[tauri::command]
+pub fn pause_timer(db: State<'_, Database>, habit_id: String) -> Result<ActiveTimer> {
+    pause(&db.conn(), &habit_id)
+}
+
+#[tauri::command]
+pub fn resume_timer(db: State<'_, Database>, habit_id: String) -> Result<ActiveTimer> {
+    resume(&db.conn(), &habit_id)
+}
+
+#[tauri::command]
+pub fn stop_timer(
+    db: State<'_, Database>,
+    habit_id: String,
+    log_completion: bool,
+) -> Result<StoppedTimer> {
+    stop(&mut db.conn(), &habit_id, log_completion)
+}
+
+#[tauri::command]
+pub fn get_timer_settings(db: State<'_, Database>) -> Result<TimerSettings> {
+    settings::get(&db.conn(), SETTINGS_KEY)
+}
+
+#[tauri::command]
+pub fn set_timer_settings(db: State<'_, Database>, timer_settings: TimerSettings) -> Result<()> {
+    settings::set(&db.conn(), SETTINGS_KEY, &timer_settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendars::{self, NewCalendar};
+    use crate::habits::NewHabit;
+
+    const SECOND: i64 = 1000;
+
+    fn timed_habit(conn: &Connection, target_duration_seconds: Option<i64>) -> String {
+        let calendar = calendars::create(
+            conn,
+            NewCalendar {
+                name: "Focus".into(),
+                color_theme: "indigo".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap();
+        habits::create(
+            conn,
+            NewHabit {
+                timer_enabled: true,
+                target_duration_seconds,
+                ..NewHabit::named(&calendar.id, "Deep work")
+            },
+        )
+        .unwrap()
+        .id
+    }
+
+    // Moves the timer's start back so it has been running since `start_time`
+    fn started_at(conn: &Connection, habit_id: &str, start_time: i64) {
+        conn.execute(
+            "UPDATE activeTimers SET startTime = ?2 WHERE habitId = ?1",
+            params![habit_id, start_time],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn pause_and_resume_exclude_paused_time() {
+        let db = Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let habit_id = timed_habit(&conn, None);
+        let now = now_millis();
+        start(&conn, &habit_id, None).unwrap();
+        started_at(&conn, &habit_id, now - 600 * SECOND);
+
+        let paused = pause(&conn, &habit_id).unwrap();
+        assert_eq!(paused.status, TimerStatus::Paused);
+        // Paused timers stop counting, whatever the clock says
+        assert_eq!(paused.elapsed_seconds(now + 3600 * SECOND), 600);
+        assert_eq!(
+            pause(&conn, &habit_id).unwrap().paused_time,
+            paused.paused_time
+        );
+
+        // Pretend the pause began five minutes ago
+        conn.execute(
+            "UPDATE activeTimers SET pausedTime = ?2 WHERE habitId = ?1",
+            params![habit_id, now - 300 * SECOND],
+        )
+        .unwrap();
+        let resumed = resume(&conn, &habit_id).unwrap();
+        assert_eq!(resumed.status, TimerStatus::Running);
+        assert_eq!(resumed.paused_time, None);
+        assert_eq!(resumed.total_paused_duration_seconds, 300);
+        assert_eq!(resumed.elapsed_seconds(now), 300);
+        // A second start is a no-op on a running timer
+        assert_eq!(start(&conn, &habit_id, None).unwrap().id, resumed.id);
+    }
+
+    #[test]
+    fn stop_with_and_without_completion() {
+        let db = Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        let habit_id = timed_habit(&conn, None);
+
+        start(&conn, &habit_id, None).unwrap();
+        let stopped = stop(&mut conn, &habit_id, false).unwrap();
+        assert!(stopped.completion.is_none());
+        assert!(for_habit(&conn, &habit_id).unwrap().is_none());
+        assert!(completions::list_all(&conn, &habit_id).unwrap().is_empty());
+
+        start(&conn, &habit_id, None).unwrap();
+        started_at(&conn, &habit_id, now_millis() - 90 * SECOND);
+        let stopped = stop(&mut conn, &habit_id, true).unwrap();
+        let completion = stopped.completion.unwrap();
+        assert!(stopped.elapsed_seconds >= 90);
+        assert_eq!(completion.duration_seconds, Some(stopped.elapsed_seconds));
+        assert_eq!(completions::list_all(&conn, &habit_id).unwrap().len(), 1);
+        assert!(matches!(
+            stop(&mut conn, &habit_id, true),
+            Err(Error::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn target_reached_fires_once() {
+        let db = Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        let habit_id = timed_habit(&conn, Some(60));
+        let timer = start(&conn, &habit_id, None).unwrap();
+        let mut engine = TimerEngine::default();
+
+        let outcome = engine
+            .tick(&mut conn, timer.start_time + 59 * SECOND)
+            .unwrap();
+        assert!(outcome.reached.is_empty());
+        assert_eq!(outcome.ticks[0].elapsed_seconds, 59);
+
+        let outcome = engine
+            .tick(&mut conn, timer.start_time + 60 * SECOND)
+            .unwrap();
+        assert_eq!(outcome.reached.len(), 1);
+        assert!(outcome.reached[0].completion.is_none());
+        // Without auto-complete the timer keeps running past its target
+        assert_eq!(outcome.ticks.len(), 1);
+
+        let outcome = engine
+            .tick(&mut conn, timer.start_time + 61 * SECOND)
+            .unwrap();
+        assert!(outcome.reached.is_empty());
+        assert_eq!(outcome.ticks[0].elapsed_seconds, 61);
+    }
+
+    #[test]
+    fn auto_complete_logs_the_session() {
+        let db = Database::open_in_memory().unwrap();
+        let mut conn = db.conn();
+        settings::set(
+            &conn,
+            SETTINGS_KEY,
+            &TimerSettings {
+                auto_complete_on_target: true,
+            },
+        )
+        .unwrap();
+        let habit_id = timed_habit(&conn, Some(60));
+        let timer = start(&conn, &habit_id, None).unwrap();
+        let mut engine = TimerEngine::default();
+
+        let done_at = timer.start_time + 75 * SECOND;
+        let outcome = engine.tick(&mut conn, done_at).unwrap();
+        assert!(outcome.ticks.is_empty());
+        let completion = outcome.reached[0].completion.clone().unwrap();
+        assert_eq!(completion.duration_seconds, Some(75));
+        assert_eq!(completion.completed_at, done_at);
+        assert!(for_habit(&conn, &habit_id).unwrap().is_none());
+
+        let logged = completions::list_all(&conn, &habit_id).unwrap();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0].duration_seconds, Some(75));
+        assert!(engine
+            .tick(&mut conn, done_at + SECOND)
+            .unwrap()
+            .reached
+            .is_empty());
+    }
+}
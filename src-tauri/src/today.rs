@@ -0,0 +1,221 @@
+// "Today" view of the habit list: enabled habits grouped by enabled calendar,
+// with whether each one was completed today, whether its recurrence still
+// needs it today, and the state of its timer. Feeds the system tray and the
+// webview's "due today" list.
+
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::{Local, NaiveDate};
+use rusqlite::Connection;
+use serde::Serialize;
+use tauri::State;
+
+use crate::calendars::{self, Calendar};
+use crate::days;
+use crate::error::Result;
+use crate::habits::{self, Habit, HabitType};
+use crate::storage::Database;
+use crate::streaks;
+use crate::timers::{self, TimerStatus};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChecklistItem {
+    pub habit: Habit,
+    pub completed_today: bool,
+    /// The habit's recurrence still needs a completion today. Always `false`
+    /// for negative habits, which are avoided rather than done.
+    pub due_today: bool,
+    pub timer: Option<TimerStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChecklistGroup {
+    pub calendar: Calendar,
+    pub items: Vec<ChecklistItem>,
+}
+
+/// Today's enabled habits grouped by calendar, both in display order.
+/// Calendars without enabled habits are left out.
+pub fn checklist(conn: &Connection, today: NaiveDate) -> Result<Vec<ChecklistGroup>> {
+    let completion_days = streaks::completion_days(conn, &Local, None)?;
+    let no_days = BTreeSet::new();
+
+    let running: HashMap<String, TimerStatus> = timers::list(conn)?
+        .into_iter()
+        .map(|timer| (timer.habit_id, timer.status))
+        .collect();
+
+    let mut by_calendar: HashMap<String, Vec<ChecklistItem>> = HashMap::new();
+    for habit in habits::list(conn, None)?
+        .into_iter()
+        .filter(|h| h.is_enabled)
+    {
+        let done = completion_days.get(&habit.id).unwrap_or(&no_days);
+        let anchor = days::local_date_of(habit.created_at);
+        by_calendar
+            .entry(habit.calendar_id.clone())
+            .or_default()
+            .push(ChecklistItem {
+                completed_today: done.contains(&today),
+                due_today: habit.habit_type == HabitType::Positive
+                    && habit.recurrence.is_due(today, anchor, done),
+                timer: running.get(&habit.id).copied(),
+                habit,
+            });
+    }
+
+    Ok(calendars::list(conn)?
+        .into_iter()
+        .filter(|calendar| calendar.is_enabled)
+        .filter_map(|calendar| {
+            let items = by_calendar.remove(&calendar.id)?;
+            Some(ChecklistGroup { calendar, items })
+        })
+        .collect())
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn get_today_checklist(db: State<'_, Database>) -> Result<Vec<ChecklistGroup>> {
+    checklist(&db.conn(), days::today())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Datelike;
+
+    use crate::calendars::NewCalendar;
+    use crate::completions;
+    use crate::habits::NewHabit;
+    use crate::recurrence::{Recurrence, RecurrenceInput};
+
+    fn calendar(conn: &Connection, name: &str) -> String {
+        calendars::create(
+            conn,
+            NewCalendar {
+                name: name.into(),
+                color_theme: "blue".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap()
+        .id
+    }
+
+    fn habit(conn: &Connection, calendar_id: &str, name: &str, recurrence: Recurrence) -> String {
+        habits::create(
+            conn,
+            NewHabit {
+                recurrence: Some(RecurrenceInput::Model(recurrence)),
+                ..NewHabit::named(calendar_id, name)
+            },
+        )
+        .unwrap()
+        .id
+    }
+
+    // Calendar name with (habit name, completed today, due today) per item
+    type Summary = (String, Vec<(String, bool, bool)>);
+
+    fn summary(groups: &[ChecklistGroup]) -> Vec<Summary> {
+        groups
+            .iter()
+            .map(|group| {
+                let items = group
+                    .items
+                    .iter()
+                    .map(|item| {
+                        (
+                            item.habit.name.clone(),
+                            item.completed_today,
+                            item.due_today,
+                        )
+                    })
+                    .collect();
+                (group.calendar.name.clone(), items)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn marks_due_and_completed_habits() {
+        let db = Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let today = days::today();
+        let weekday = today.weekday().num_days_from_sunday() as u8;
+        let other_day = (weekday + 1) % 7;
+
+        let health = calendar(&conn, "Health");
+        habit(&conn, &health, "Walk", Recurrence::Daily);
+        let stretch = habit(&conn, &health, "Stretch", Recurrence::Daily);
+        habit(
+            &conn,
+            &health,
+            "Swim",
+            Recurrence::Weekdays {
+                days: vec![other_day],
+            },
+        );
+        habit(
+            &conn,
+            &health,
+            "Run",
+            Recurrence::Weekdays {
+                days: vec![weekday],
+            },
+        );
+        completions::log(&conn, &stretch, None, None).unwrap();
+
+        assert_eq!(
+            summary(&checklist(&conn, today).unwrap()),
+            vec![(
+                "Health".into(),
+                vec![
+                    ("Walk".into(), false, true),
+                    ("Stretch".into(), true, false),
+                    ("Swim".into(), false, false),
+                    ("Run".into(), false, true),
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn groups_by_enabled_calendar_in_order() {
+        let db = Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let health = calendar(&conn, "Health");
+        let work = calendar(&conn, "Work");
+        let hidden = calendar(&conn, "Hidden");
+        calendar(&conn, "Empty");
+        habit(&conn, &work, "Inbox zero", Recurrence::Daily);
+        habit(&conn, &health, "Walk", Recurrence::Daily);
+        let paused = habit(&conn, &health, "Paused", Recurrence::Daily);
+        habits::set_enabled(&conn, &paused, false).unwrap();
+        habit(&conn, &hidden, "Secret", Recurrence::Daily);
+        calendars::set_enabled(&conn, &hidden, false).unwrap();
+
+        let groups = checklist(&conn, days::today()).unwrap();
+        let names: Vec<(String, Vec<String>)> = groups
+            .iter()
+            .map(|group| {
+                (
+                    group.calendar.name.clone(),
+                    group.items.iter().map(|i| i.habit.name.clone()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("Health".into(), vec!["Walk".into()]),
+                ("Work".into(), vec!["Inbox zero".into()]),
+            ]
+        );
+    }
+}
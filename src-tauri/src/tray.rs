@@ -0,0 +1,214 @@
+// System tray with today's habit checklist.
+//
+// The menu lists enabled habits grouped by calendar. Ticking a habit logs a
+// completion (unticking undoes today's latest one) and timer entries start or
+// stop the habit's timer, all without opening the main window. A watcher
+// thread rebuilds the menu and the progress-ring icon when the database
+// changes or the day rolls over.
+
+use std::time::Duration;
+
+use tauri::image::Image;
+use tauri::menu::{CheckMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::error::Result;
+use crate::storage::Database;
+use crate::timers::TimerStatus;
+use crate::today::{self, ChecklistGroup};
+use crate::tray_icon::{self, Progress, ICON_SIZE};
+use crate::{completions, days, timers};
+
+pub const TRAY_ID: &str = "main";
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+// Menu item ids are `<action>:<habit id>`
+const COMPLETE_PREFIX: &str = "complete:";
+const TIMER_PREFIX: &str = "timer:";
+const OPEN_ID: &str = "open";
+const QUIT_ID: &str = "quit";
+
+/// Creates the tray icon and starts watching for changes.
+pub fn init<R: Runtime>(app: &AppHandle<R>) -> tauri::Result<()> {
+    let groups = load_checklist(app);
+    TrayIconBuilder::with_id(TRAY_ID)
+        .icon(progress_icon(&groups))
+        .tooltip(tooltip(&groups))
+        .menu(&build_menu(app, &groups)?)
+        .show_menu_on_left_click(true)
+        .on_menu_event(handle_menu_event)
+        .build(app)?;
+
+    let app = app.clone();
+    std::thread::Builder::new()
+        .name("habistat-tray".into())
+        .spawn(move || watch(app))?;
+    Ok(())
+}
+
+/// Rebuilds the tray menu and icon from the database.
+pub fn refresh<R: Runtime>(app: &AppHandle<R>) -> tauri::Result<()> {
+    if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        let groups = load_checklist(app);
+        tray.set_menu(Some(build_menu(app, &groups)?))?;
+        tray.set_icon(Some(progress_icon(&groups)))?;
+        tray.set_tooltip(Some(tooltip(&groups)))?;
+    }
+    Ok(())
+}
+
+fn watch<R: Runtime>(app: AppHandle<R>) {
+    let mut seen = (app.state::<Database>().generation(), days::today());
+    loop {
+        std::thread::sleep(REFRESH_INTERVAL);
+        let current = (app.state::<Database>().generation(), days::today());
+        if current != seen {
+            seen = current;
+            if let Err(e) = refresh(&app) {
+                eprintln!("Failed to refresh tray menu: {e}");
+            }
+        }
+    }
+}
+
+fn build_menu<R: Runtime>(app: &AppHandle<R>, groups: &[ChecklistGroup]) -> tauri::Result<Menu<R>> {
+    let menu = Menu::new(app)?;
+    menu.append(&MenuItem::new(app, summary(groups), false, None::<&str>)?)?;
+
+    for group in groups {
+        menu.append(&PredefinedMenuItem::separator(app)?)?;
+        menu.append(&MenuItem::new(
+            app,
+            &group.calendar.name,
+            false,
+            None::<&str>,
+        )?)?;
+        for item in &group.items {
+            let habit = &item.habit;
+            menu.append(&CheckMenuItem::with_id(
+                app,
+                format!("{COMPLETE_PREFIX}{}", habit.id),
+                &habit.name,
+                true,
+                item.completed_today,
+                None::<&str>,
+            )?)?;
+            if habit.timer_enabled {
+                let label = match item.timer {
+                    Some(TimerStatus::Running) => format!("    Stop timer: {}", habit.name),
+                    Some(TimerStatus::Paused) => format!("    Resume timer: {}", habit.name),
+                    None => format!("    Start timer: {}", habit.name),
+                };
+                menu.append(&MenuItem::with_id(
+                    app,
+                    format!("{TIMER_PREFIX}{}", habit.id),
+                    label,
+                    true,
+                    None::<&str>,
+                )?)?;
+            }
+        }
+    }
+
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+    menu.append(&MenuItem::with_id(
+        app,
+        OPEN_ID,
+        "Open Habistat",
+        true,
+        None::<&str>,
+    )?)?;
+    menu.append(&MenuItem::with_id(
+        app,
+        QUIT_ID,
+        "Quit",
+        true,
+        None::<&str>,
+    )?)?;
+    Ok(menu)
+}
+
+fn summary(groups: &[ChecklistGroup]) -> String {
+    let progress = Progress::from_checklist(groups);
+    if progress.total == 0 {
+        "No habits for today".to_string()
+    } else {
+        format!("Today: {}/{} done", progress.completed, progress.total)
+    }
+}
+
+fn tooltip(groups: &[ChecklistGroup]) -> String {
+    format!("Habistat – {}", summary(groups))
+}
+
+fn progress_icon(groups: &[ChecklistGroup]) -> Image<'static> {
+    let rgba = tray_icon::render_rgba(Progress::from_checklist(groups), ICON_SIZE);
+    Image::new_owned(rgba, ICON_SIZE, ICON_SIZE)
+}
+
+fn load_checklist<R: Runtime>(app: &AppHandle<R>) -> Vec<ChecklistGroup> {
+    let db = app.state::<Database>();
+    let conn = db.conn();
+    today::checklist(&conn, days::today()).unwrap_or_else(|e| {
+        eprintln!("Failed to load today's habits for the tray: {e}");
+        Vec::new()
+    })
+}
+
+fn handle_menu_event<R: Runtime>(app: &AppHandle<R>, event: MenuEvent) {
+    let id = event.id().as_ref();
+    let result = if let Some(habit_id) = id.strip_prefix(COMPLETE_PREFIX) {
+        toggle_completion(app, habit_id)
+    } else if let Some(habit_id) = id.strip_prefix(TIMER_PREFIX) {
+        toggle_timer(app, habit_id)
+    } else {
+        match id {
+            OPEN_ID => {
+                if let Some(window) = app.get_webview_window("main") {
+                    let _ = window.show();
+                    let _ = window.unminimize();
+                    let _ = window.set_focus();
+                }
+            }
+            QUIT_ID => app.exit(0),
+            _ => {}
+        }
+        Ok(())
+    };
+
+    if let Err(e) = result {
+        eprintln!("Tray action `{id}` failed: {e}");
+    }
+    // Re-sync check marks right away, also when the action failed
+    let _ = refresh(app);
+}
+
+fn toggle_completion<R: Runtime>(app: &AppHandle<R>, habit_id: &str) -> Result<()> {
+    let db = app.state::<Database>();
+    let conn = db.conn();
+    let today = days::today();
+    let (start, end) = days::local_day_bounds(today);
+    if completions::list_between(&conn, habit_id, start, end)?.is_empty() {
+        completions::log(&conn, habit_id, None, None)?;
+    } else {
+        completions::undo_last(&conn, habit_id, today)?;
+    }
+    Ok(())
+}
+
+// Stopping from the tray counts the session as a completion
+fn toggle_timer<R: Runtime>(app: &AppHandle<R>, habit_id: &str) -> Result<()> {
+    let db = app.state::<Database>();
+    let mut conn = db.conn();
+    match timers::for_habit(&conn, habit_id)? {
+        Some(timer) if timer.status == TimerStatus::Running => {
+            timers::stop(&mut conn, habit_id, true)?;
+        }
+        _ => {
+            timers::start(&conn, habit_id, None)?;
+        }
+    }
+    Ok(())
+}
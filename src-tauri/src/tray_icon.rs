@@ -0,0 +1,312 @@
+// Tray icon artwork, rendered at runtime with tiny-skia (pure Rust, no GPU or
+// display needed) so it can be snapshot-tested headlessly.
+//
+// The icon is a progress ring of today's completed habits with a badge in the
+// top-right corner while a habit timer is running.
+
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use tiny_skia::{
+    BlendMode, Color, FillRule, LineCap, Paint, PathBuilder, Pixmap, Stroke, Transform,
+};
+
+use crate::habits::HabitType;
+use crate::timers::TimerStatus;
+use crate::today::ChecklistGroup;
+
+/// Edge length of the rendered icon; the OS scales it down for the tray.
+pub const ICON_SIZE: u32 = 64;
+
+const TRACK_COLOR: (u8, u8, u8, u8) = (128, 128, 128, 96);
+const PROGRESS_COLOR: (u8, u8, u8, u8) = (34, 197, 94, 255); // green-500
+const BADGE_COLOR: (u8, u8, u8, u8) = (249, 115, 22, 255); // orange-500
+
+// Arcs are drawn as polylines with this many segments per full turn
+const ARC_SEGMENTS: f32 = 96.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub completed: usize,
+    pub total: usize,
+    pub running_timers: usize,
+}
+
+impl Progress {
+    /// Habits off their schedule today only count once they are completed anyway.
+    /// Negative habits are left out: completing one means slipping.
+    pub fn from_checklist(groups: &[ChecklistGroup]) -> Self {
+        let items = groups.iter().flat_map(|g| &g.items);
+        let scheduled = items.clone().filter(|item| {
+            item.habit.habit_type == HabitType::Positive && (item.due_today || item.completed_today)
+        });
+        Progress {
+            completed: scheduled
+                .clone()
+                .filter(|item| item.completed_today)
+                .count(),
+            total: scheduled.count(),
+            running_timers: items
+                .filter(|item| item.timer == Some(TimerStatus::Running))
+                .count(),
+        }
+    }
+
+    fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            0.0
+        } else {
+            (self.completed as f32 / self.total as f32).clamp(0.0, 1.0)
+        }
+    }
+}
+
+/// Renders the icon into a premultiplied pixmap of `size`×`size` pixels.
+pub fn render(progress: Progress, size: u32) -> Pixmap {
+    let mut pixmap = Pixmap::new(size, size).expect("icon size must be non-zero");
+    let s = size as f32;
+    let center = s / 2.0;
+    let width = s * 0.14;
+    let radius = center - width / 2.0 - s * 0.04;
+
+    let stroke = Stroke {
+        width,
+        line_cap: LineCap::Round,
+        ..Stroke::default()
+    };
+
+    if let Some(track) = PathBuilder::from_circle(center, center, radius) {
+        pixmap.stroke_path(
+            &track,
+            &paint(TRACK_COLOR),
+            &stroke,
+            Transform::identity(),
+            None,
+        );
+    }
+
+    let fraction = progress.fraction();
+    if fraction >= 1.0 {
+        // All done: closed ring plus a filled center
+        if let Some(ring) = PathBuilder::from_circle(center, center, radius) {
+            let progress_paint = paint(PROGRESS_COLOR);
+            pixmap.stroke_path(&ring, &progress_paint, &stroke, Transform::identity(), None);
+            if let Some(dot) = PathBuilder::from_circle(center, center, radius * 0.45) {
+                pixmap.fill_path(
+                    &dot,
+                    &progress_paint,
+                    FillRule::Winding,
+                    Transform::identity(),
+                    None,
+                );
+            }
+        }
+    } else if fraction > 0.0 {
+        if let Some(arc) = arc(center, radius, fraction) {
+            pixmap.stroke_path(
+                &arc,
+                &paint(PROGRESS_COLOR),
+                &stroke,
+                Transform::identity(),
+                None,
+            );
+        }
+    }
+
+    if progress.running_timers > 0 {
+        let badge_radius = s * 0.17;
+        let badge_center = s - badge_radius - s * 0.02;
+        // Punch a transparent gap around the badge so it reads on any tray color
+        if let Some(gap) =
+            PathBuilder::from_circle(badge_center, s - badge_center, badge_radius * 1.35)
+        {
+            let clear = Paint {
+                blend_mode: BlendMode::Clear,
+                ..Paint::default()
+            };
+            pixmap.fill_path(&gap, &clear, FillRule::Winding, Transform::identity(), None);
+        }
+        if let Some(badge) = PathBuilder::from_circle(badge_center, s - badge_center, badge_radius)
+        {
+            pixmap.fill_path(
+                &badge,
+                &paint(BADGE_COLOR),
+                FillRule::Winding,
+                Transform::identity(),
+                None,
+            );
+        }
+    }
+
+    pixmap
+}
+
+/// Renders the icon as straight (non-premultiplied) RGBA, the layout
+/// `tauri::image::Image` expects.
+pub fn render_rgba(progress: Progress, size: u32) -> Vec<u8> {
+    render(progress, size)
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect()
+}
+
+fn paint((r, g, b, a): (u8, u8, u8, u8)) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color(Color::from_rgba8(r, g, b, a));
+    paint.anti_alias = true;
+    paint
+}
+
+// Clockwise arc starting at 12 o'clock covering `fraction` of the circle
+fn arc(center: f32, radius: f32, fraction: f32) -> Option<tiny_skia::Path> {
+    let segments = (ARC_SEGMENTS * fraction).ceil().max(1.0) as usize;
+    let mut builder = PathBuilder::new();
+    for i in 0..=segments {
+        let angle = -FRAC_PI_2 + TAU * fraction * (i as f32 / segments as f32);
+        let (x, y) = (center + radius * angle.cos(), center + radius * angle.sin());
+        if i == 0 {
+            builder.move_to(x, y);
+        } else {
+            builder.line_to(x, y);
+        }
+    }
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // Set UPDATE_SNAPSHOTS=1 to (re)write the reference images.
+    fn assert_snapshot(name: &str, progress: Progress) {
+        let actual = render(progress, ICON_SIZE);
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/snapshots")
+            .join(format!("tray_icon_{name}.png"));
+
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            actual.save_png(&path).unwrap();
+            return;
+        }
+
+        let expected = Pixmap::load_png(&path)
+            .unwrap_or_else(|e| panic!("missing snapshot {}: {e}", path.display()));
+        assert_eq!(
+            (expected.width(), expected.height()),
+            (actual.width(), actual.height())
+        );
+        // Allow for rounding differences between SIMD backends
+        let max_diff = expected
+            .data()
+            .iter()
+            .zip(actual.data())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or(0);
+        assert!(max_diff <= 2, "{name} differs from snapshot by {max_diff}");
+    }
+
+    #[test]
+    fn empty_day() {
+        assert_snapshot("empty", Progress::default());
+    }
+
+    #[test]
+    fn partial_progress() {
+        let progress = Progress {
+            completed: 1,
+            total: 3,
+            running_timers: 0,
+        };
+        assert_snapshot("partial", progress);
+    }
+
+    #[test]
+    fn all_done() {
+        let progress = Progress {
+            completed: 4,
+            total: 4,
+            running_timers: 0,
+        };
+        assert_snapshot("done", progress);
+    }
+
+    #[test]
+    fn running_timer_badge() {
+        let progress = Progress {
+            completed: 2,
+            total: 3,
+            running_timers: 1,
+        };
+        assert_snapshot("timer", progress);
+    }
+
+    #[test]
+    fn negative_habits_do_not_count_towards_progress() {
+        use crate::calendars::{self, NewCalendar};
+        use crate::habits::{self, NewHabit};
+        use crate::storage::Database;
+        use crate::{completions, days, today};
+
+        let db = Database::open_in_memory().unwrap();
+        let conn = db.conn();
+        let calendar = calendars::create(
+            &conn,
+            NewCalendar {
+                name: "Health".into(),
+                color_theme: "green".into(),
+                user_id: None,
+                position: None,
+            },
+        )
+        .unwrap();
+        let habit = |name: &str, habit_type: HabitType| {
+            habits::create(
+                &conn,
+                NewHabit {
+                    habit_type,
+                    ..NewHabit::named(&calendar.id, name)
+                },
+            )
+            .unwrap()
+            .id
+        };
+        let walk = habit("Walk", HabitType::Positive);
+        habit("Read", HabitType::Positive);
+        habit("Stretch", HabitType::Positive);
+        let smoking = habit("Smoking", HabitType::Negative);
+        habit("Doomscrolling", HabitType::Negative);
+        completions::log(&conn, &walk, None, None).unwrap();
+        completions::log(&conn, &smoking, None, None).unwrap();
+
+        let groups = today::checklist(&conn, days::today()).unwrap();
+        assert!(groups[0]
+            .items
+            .iter()
+            .filter(|item| item.habit.habit_type == HabitType::Negative)
+            .all(|item| !item.due_today));
+        let progress = Progress::from_checklist(&groups);
+        assert_eq!(
+            progress,
+            Progress {
+                completed: 1,
+                total: 3,
+                running_timers: 0,
+            }
+        );
+        assert_snapshot("partial", progress);
+    }
+
+    #[test]
+    fn rgba_output_covers_whole_icon() {
+        let rgba = render_rgba(Progress::default(), ICON_SIZE);
+        assert_eq!(rgba.len(), (ICON_SIZE * ICON_SIZE * 4) as usize);
+        // The corner outside the ring stays transparent
+        assert_eq!(&rgba[..4], &[0, 0, 0, 0]);
+    }
+}